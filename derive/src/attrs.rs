@@ -0,0 +1,301 @@
+//! The attributes of a struct of template parameters and its fields, shared by
+//! every derive
+
+use proc_macro2::TokenStream;
+use syn::{
+    Attribute, Data, DeriveInput, Error, Expr, Field, Fields, Lit, LitStr, Path, Result, Token,
+    Type, meta::ParseNestedMeta, parenthesized, punctuated::Punctuated, token,
+};
+
+/// What the struct's attributes say about all of its parameters
+pub struct Container {
+    pub template: String,
+    pub rename_all: Option<String>,
+    pub deny_unknown_fields: bool,
+    pub default: bool,
+}
+
+/// How a field is read from its parameter and declared
+pub struct Parameter<'a> {
+    pub field: &'a Field,
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub default: Option<DefaultValue>,
+    pub skip: bool,
+    pub deserialize_with: Option<Path>,
+    pub display_name: Option<String>,
+    pub parameter_type: Option<String>,
+    pub values: Vec<Lit>,
+}
+
+/// Where a missing parameter's value comes from
+pub enum DefaultValue {
+    Trait,
+    Function(Path),
+}
+
+/// The container and field attributes of a struct with named fields, or an
+/// error naming the derive that needs one
+pub fn parse<'a>(input: &'a DeriveInput, derive: &str) -> Result<(Container, Vec<Parameter<'a>>)> {
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new_spanned(
+            input,
+            format!("{derive} can only be derived for structs"),
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(Error::new_spanned(
+            input,
+            format!("{derive} can only be derived for structs with named fields"),
+        ));
+    };
+
+    let container = Container::parse(input)?;
+    let parameters = fields
+        .named
+        .iter()
+        .map(|field| Parameter::parse(field, &container))
+        .collect::<Result<_>>()?;
+    Ok((container, parameters))
+}
+
+impl Container {
+    fn parse(input: &DeriveInput) -> Result<Self> {
+        let mut container = Container {
+            template: input.ident.to_string(),
+            rename_all: None,
+            deny_unknown_fields: false,
+            default: false,
+        };
+        for attr in &input.attrs {
+            if attr.path().is_ident("parameterized") {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("template") {
+                        container.template = meta.value()?.parse::<LitStr>()?.value();
+                        Ok(())
+                    } else {
+                        Err(meta.error("expected `template`"))
+                    }
+                })?;
+            } else if attr.path().is_ident("serde") {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("rename_all") {
+                        let rule = meta.value()?.parse::<LitStr>()?;
+                        if rename(&rule.value(), "a").is_none() {
+                            return Err(Error::new_spanned(rule, "unknown `rename_all` rule"));
+                        }
+                        container.rename_all = Some(rule.value());
+                    } else if meta.path.is_ident("deny_unknown_fields") {
+                        container.deny_unknown_fields = true;
+                    } else if meta.path.is_ident("default") {
+                        if meta.input.peek(Token![=]) {
+                            return Err(meta.error(
+                                "template parameters don't support `default = \"...\"` on a struct",
+                            ));
+                        }
+                        container.default = true;
+                    } else {
+                        skip(&meta)?;
+                    }
+                    Ok(())
+                })?;
+            }
+        }
+        Ok(container)
+    }
+}
+
+impl<'a> Parameter<'a> {
+    fn parse(field: &'a Field, container: &Container) -> Result<Self> {
+        let ident = field.ident.as_ref().expect("named field");
+        let ident = ident.to_string();
+        let ident = ident.trim_start_matches("r#");
+        let mut parameter = Parameter {
+            field,
+            name: match &container.rename_all {
+                Some(rule) => rename(rule, ident).unwrap_or_else(|| ident.to_string()),
+                None => ident.to_string(),
+            },
+            aliases: Vec::new(),
+            default: None,
+            skip: false,
+            deserialize_with: None,
+            display_name: display_name(&field.attrs),
+            parameter_type: None,
+            values: Vec::new(),
+        };
+        for attr in &field.attrs {
+            if attr.path().is_ident("parameterized") {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("type") {
+                        parameter.parameter_type = Some(meta.value()?.parse::<LitStr>()?.value());
+                    } else if meta.path.is_ident("values") {
+                        let content;
+                        parenthesized!(content in meta.input);
+                        parameter.values =
+                            Punctuated::<Lit, Token![,]>::parse_terminated(&content)?
+                                .into_iter()
+                                .collect();
+                    } else {
+                        return Err(meta.error("expected `type` or `values`"));
+                    }
+                    Ok(())
+                })?;
+            } else if attr.path().is_ident("serde") {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("rename") {
+                        parameter.name = meta.value()?.parse::<LitStr>()?.value();
+                    } else if meta.path.is_ident("alias") {
+                        parameter
+                            .aliases
+                            .push(meta.value()?.parse::<LitStr>()?.value());
+                    } else if meta.path.is_ident("default") {
+                        parameter.default = Some(if meta.input.peek(Token![=]) {
+                            DefaultValue::Function(meta.value()?.parse::<LitStr>()?.parse()?)
+                        } else {
+                            DefaultValue::Trait
+                        });
+                    } else if meta.path.is_ident("skip") || meta.path.is_ident("skip_deserializing")
+                    {
+                        parameter.skip = true;
+                    } else if meta.path.is_ident("deserialize_with") {
+                        parameter.deserialize_with =
+                            Some(meta.value()?.parse::<LitStr>()?.parse()?);
+                    } else if meta.path.is_ident("with") {
+                        let mut path: Path = meta.value()?.parse::<LitStr>()?.parse()?;
+                        path.segments.push(syn::parse_quote!(deserialize));
+                        parameter.deserialize_with = Some(path);
+                    } else if meta.path.is_ident("flatten") {
+                        return Err(meta.error("template parameters don't support `flatten`"));
+                    } else {
+                        skip(&meta)?;
+                    }
+                    Ok(())
+                })?;
+            }
+        }
+        Ok(parameter)
+    }
+
+    /// The parameter's type in a template's `parameters:` declaration
+    pub fn parameter_type(&self) -> String {
+        self.parameter_type
+            .clone()
+            .unwrap_or_else(|| parameter_type(&self.field.ty).to_string())
+    }
+}
+
+/// The first paragraph of a field's doc comment, on one line
+fn display_name(attrs: &[Attribute]) -> Option<String> {
+    let lines: Vec<String> = attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta.require_name_value().ok()?.value {
+            Expr::Lit(syn::ExprLit {
+                lit: Lit::Str(line),
+                ..
+            }) => Some(line.value().trim().to_string()),
+            _ => None,
+        })
+        .skip_while(String::is_empty)
+        .take_while(|line| !line.is_empty())
+        .collect();
+    (!lines.is_empty()).then(|| lines.join(" "))
+}
+
+/// The template parameter type a Rust type is declared as
+fn parameter_type(ty: &Type) -> &'static str {
+    let ty = match ty {
+        Type::Reference(reference) => &reference.elem,
+        ty => ty,
+    };
+    let Type::Path(path) = ty else {
+        return "object";
+    };
+    let Some(segment) = path.path.segments.last() else {
+        return "object";
+    };
+    let argument = match &segment.arguments {
+        syn::PathArguments::AngleBracketed(arguments) => {
+            arguments.args.first().and_then(|argument| match argument {
+                syn::GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            })
+        }
+        _ => None,
+    };
+    match (segment.ident.to_string().as_str(), argument) {
+        ("Option", Some(inner)) => parameter_type(inner),
+        ("Vec", Some(inner)) => match parameter_type(inner) {
+            "stage" => "stageList",
+            "job" => "jobList",
+            "step" => "stepList",
+            _ => "object",
+        },
+        ("String" | "str" | "char", _) => "string",
+        (
+            "i8" | "i16" | "i32" | "i64" | "i128" | "isize" | "u8" | "u16" | "u32" | "u64" | "u128"
+            | "usize" | "f32" | "f64",
+            _,
+        ) => "number",
+        ("bool", _) => "boolean",
+        ("Stage", _) => "stage",
+        ("Job", _) => "job",
+        ("Step", _) => "step",
+        _ => "object",
+    }
+}
+
+/// Whether a type is spelled `Option<...>`, which serde treats as optional
+pub fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "Option"),
+        _ => false,
+    }
+}
+
+/// Consume a serde attribute the derives don't need
+fn skip(meta: &ParseNestedMeta<'_>) -> Result<()> {
+    if meta.input.peek(Token![=]) {
+        meta.value()?.parse::<Expr>()?;
+    } else if meta.input.peek(token::Paren) {
+        let content;
+        parenthesized!(content in meta.input);
+        content.parse::<TokenStream>()?;
+    }
+    Ok(())
+}
+
+/// The name of a `snake_case` field under a serde `rename_all` rule
+fn rename(rule: &str, field: &str) -> Option<String> {
+    let words = || field.split('_').filter(|word| !word.is_empty());
+    let capitalize = |word: &str| {
+        let mut chars = word.chars();
+        chars
+            .next()
+            .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+            .unwrap_or_default()
+    };
+    Some(match rule {
+        "lowercase" => field.to_lowercase(),
+        "UPPERCASE" => field.to_uppercase(),
+        "PascalCase" => words().map(capitalize).collect(),
+        "camelCase" => {
+            let pascal: String = words().map(capitalize).collect();
+            let mut chars = pascal.chars();
+            chars
+                .next()
+                .map(|first| first.to_lowercase().chain(chars).collect())
+                .unwrap_or_default()
+        }
+        "snake_case" => field.to_string(),
+        "SCREAMING_SNAKE_CASE" => field.to_uppercase(),
+        "kebab-case" => field.replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => field.to_uppercase().replace('_', "-"),
+        _ => return None,
+    })
+}
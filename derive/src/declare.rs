@@ -0,0 +1,73 @@
+//! `#[derive(Declare)]`
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{DeriveInput, Result};
+
+use crate::attrs::{self, Container, DefaultValue, Parameter};
+
+pub fn expand(input: &DeriveInput) -> Result<TokenStream> {
+    let (container, parameters) = attrs::parse(input, "Declare")?;
+    let parameters: Vec<&Parameter<'_>> = parameters
+        .iter()
+        .filter(|parameter| !parameter.skip)
+        .collect();
+
+    let defaults = (container.default
+        && parameters
+            .iter()
+            .any(|parameter| parameter.default.is_none()))
+    .then(|| quote!(let defaults = <Self as ::std::default::Default>::default();));
+    let declarations = parameters
+        .iter()
+        .map(|parameter| declaration(parameter, &container));
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::azure_pipelines_rs::templates::declare::Declare
+            for #ident #ty_generics #where_clause
+        {
+            fn declarations() -> ::std::result::Result<
+                ::std::vec::Vec<::azure_pipelines_rs::core::v1::pipeline::PipelineParameter>,
+                ::std::boxed::Box<dyn ::std::error::Error>,
+            > {
+                #defaults
+                Ok(::std::vec![#(#declarations),*])
+            }
+        }
+    })
+}
+
+/// The `PipelineParameter` a field is declared as
+fn declaration(parameter: &Parameter<'_>, container: &Container) -> TokenStream {
+    let ident = &parameter.field.ident;
+    let ty = &parameter.field.ty;
+    let name = &parameter.name;
+    let parameter_type = parameter.parameter_type();
+
+    let to_value = quote!(::azure_pipelines_rs::__private::serde_yaml::to_value);
+    let default = match &parameter.default {
+        Some(DefaultValue::Function(path)) => quote!(Some(#to_value(&#path())?)),
+        Some(DefaultValue::Trait) => {
+            quote!(Some(#to_value(&<#ty as ::std::default::Default>::default())?))
+        }
+        None if container.default => quote!(Some(#to_value(&defaults.#ident)?)),
+        None => quote!(None),
+    };
+    let display_name = match &parameter.display_name {
+        Some(display_name) => quote!(Some(::std::string::String::from(#display_name))),
+        None => quote!(None),
+    };
+    let values = &parameter.values;
+    quote! {
+        ::azure_pipelines_rs::core::v1::pipeline::PipelineParameter {
+            name: ::std::string::String::from(#name),
+            display_name: #display_name,
+            parameter_type: ::std::string::String::from(#parameter_type),
+            default: #default,
+            values: ::std::vec![#(#to_value(&#values)?),*],
+            unknown: ::azure_pipelines_rs::__private::serde_yaml::Mapping::new(),
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Derive macros for azure-pipelines-rs
+//!
+//! Enable the `derive` feature of azure-pipelines-rs rather than depending on
+//! this crate directly.
+
+use proc_macro::TokenStream;
+use syn::{DeriveInput, parse_macro_input};
+
+mod attrs;
+mod declare;
+mod parameterized;
+
+/// Implement `Parameterized` for a struct of template parameters, reading each
+/// field from the parameter of the same name
+///
+/// The struct is its own `Parameters` type. Parameter names follow the
+/// struct's `#[serde(rename_all)]` and each field's `#[serde(rename)]` and
+/// `#[serde(alias)]`. A missing parameter is an error unless the field is an
+/// `Option` or has `#[serde(default)]`, and parameters the struct doesn't have
+/// are errors if it has `#[serde(deny_unknown_fields)]`.
+///
+/// Errors name the template, which defaults to the name of the struct:
+///
+/// ```ignore
+/// #[derive(Parameterized)]
+/// #[parameterized(template = "entrypoint.yml@tools")]
+/// #[serde(rename_all = "camelCase")]
+/// struct EntrypointParameters {
+///     custom_build_tags: Vec<String>,
+/// }
+/// ```
+#[proc_macro_derive(Parameterized, attributes(parameterized, serde))]
+pub fn derive_parameterized(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    parameterized::expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Implement `Declare` for a struct of template parameters, declaring each
+/// field as the parameter `#[derive(Parameterized)]` reads it from
+///
+/// A parameter's `displayName` is the first paragraph of the field's doc
+/// comment, and its `default` comes from `#[serde(default)]`, so fields with a
+/// default must implement `Serialize`. Its `type` follows the field's type:
+/// `string`, `number`, `boolean`, `stage`, `stageList`, `job`, `jobList`,
+/// `step` or `stepList`, and otherwise `object`. Set another type, or the
+/// allowed values, with `#[parameterized]`:
+///
+/// ```ignore
+/// #[derive(Declare)]
+/// struct BuildParameters {
+///     /// Operating system to build on
+///     #[parameterized(values("linux", "windows"))]
+///     #[serde(default = "default_os")]
+///     os: String,
+///
+///     #[parameterized(type = "filePath")]
+///     script: String,
+/// }
+///
+/// fn default_os() -> String {
+///     "linux".to_string()
+/// }
+/// ```
+#[proc_macro_derive(Declare, attributes(parameterized, serde))]
+pub fn derive_declare(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    declare::expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
@@ -0,0 +1,123 @@
+//! `#[derive(Parameterized)]`
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{DeriveInput, Result};
+
+use crate::attrs::{self, Container, DefaultValue, Parameter, is_option};
+
+pub fn expand(input: &DeriveInput) -> Result<TokenStream> {
+    let (container, parameters) = attrs::parse(input, "Parameterized")?;
+    let template = &container.template;
+
+    let unknown = container.deny_unknown_fields.then(|| {
+        let known = parameters
+            .iter()
+            .filter(|parameter| !parameter.skip)
+            .flat_map(|parameter| std::iter::once(&parameter.name).chain(&parameter.aliases));
+        quote! {
+            let known: &[&str] = &[#(#known),*];
+            if let Some(key) = hash_map.keys().find(|key| !known.contains(&key.as_str())) {
+                return Err(::azure_pipelines_rs::validator::diagnostic::Diagnostic::error(
+                    ::azure_pipelines_rs::validator::diagnostic::codes::TEMPLATE_PARAMETER,
+                    format!("template {}: unexpected parameter `{}`", #template, key),
+                )
+                .at(::azure_pipelines_rs::validator::diagnostic::AstPath::default().field("parameters").field(key)));
+            }
+        }
+    });
+    let defaults = (container.default
+        && parameters
+            .iter()
+            .any(|parameter| parameter.default.is_none()))
+    .then(|| quote!(let defaults = <Self as ::std::default::Default>::default();));
+    let initializers = parameters
+        .iter()
+        .map(|parameter| initializer(parameter, &container));
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::azure_pipelines_rs::templates::parameterized::Parameterized
+            for #ident #ty_generics #where_clause
+        {
+            type Parameters = Self;
+
+            fn get_parameters(
+                hash_map: &::azure_pipelines_rs::__private::IndexMap<
+                    ::std::string::String,
+                    ::azure_pipelines_rs::__private::serde_yaml::Value,
+                >,
+            ) -> ::std::result::Result<
+                Self::Parameters,
+                ::azure_pipelines_rs::validator::diagnostic::Diagnostic,
+            > {
+                #unknown
+                #defaults
+                Ok(Self {
+                    #(#initializers,)*
+                })
+            }
+        }
+    })
+}
+
+/// `field: value` in the struct expression
+fn initializer(parameter: &Parameter<'_>, container: &Container) -> TokenStream {
+    let ident = &parameter.field.ident;
+    let ty = &parameter.field.ty;
+    let template = &container.template;
+    let name = &parameter.name;
+
+    let default = match &parameter.default {
+        Some(DefaultValue::Function(path)) => Some(quote!(#path())),
+        Some(DefaultValue::Trait) => Some(quote!(<#ty as ::std::default::Default>::default())),
+        None if container.default => Some(quote!(defaults.#ident)),
+        None if parameter.skip => Some(quote!(<#ty as ::std::default::Default>::default())),
+        None if is_option(ty) && parameter.deserialize_with.is_none() => {
+            Some(quote!(::std::option::Option::None))
+        }
+        None => None,
+    };
+    if parameter.skip {
+        return quote!(#ident: #default);
+    }
+    let missing = default.unwrap_or_else(|| {
+        let diagnostic = diagnostic(
+            quote!(format!("template {}: missing parameter `{}`", #template, #name)),
+            name,
+        );
+        quote!(return Err(#diagnostic))
+    });
+
+    let deserialize = match &parameter.deserialize_with {
+        Some(path) => quote!(#path(value.clone())),
+        None => {
+            quote!(::azure_pipelines_rs::__private::serde_yaml::from_value::<#ty>(value.clone()))
+        }
+    };
+    let invalid = diagnostic(
+        quote!(format!("template {}: parameter `{}`: {}", #template, #name, e)),
+        name,
+    );
+    let aliases = &parameter.aliases;
+    quote! {
+        #ident: match hash_map.get(#name)#(.or_else(|| hash_map.get(#aliases)))* {
+            Some(value) => #deserialize.map_err(|e| #invalid)?,
+            None => #missing,
+        }
+    }
+}
+
+/// A `TEMPLATE_PARAMETER` error with `message`, at `parameters > <name>`
+fn diagnostic(message: TokenStream, name: &str) -> TokenStream {
+    quote! {
+        ::azure_pipelines_rs::validator::diagnostic::Diagnostic::error(
+            ::azure_pipelines_rs::validator::diagnostic::codes::TEMPLATE_PARAMETER,
+            #message,
+        )
+        .at(::azure_pipelines_rs::validator::diagnostic::AstPath::default()
+            .field("parameters")
+            .field(#name))
+    }
+}
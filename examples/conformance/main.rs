@@ -0,0 +1,65 @@
+use std::{collections::BTreeSet, error::Error, fs, process};
+
+use clap::Parser;
+
+use azure_pipelines_rs::core::v1::conformance;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    let contents = fs::read_to_string(&args.service_schema)?;
+    let service_schema: serde_json::Value = serde_json::from_str(&contents)?;
+    let gaps: Vec<String> = conformance::check(&service_schema)?
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+
+    let Some(baseline_file) = &args.baseline else {
+        for gap in &gaps {
+            println!("{gap}");
+        }
+        println!("{} gaps", gaps.len());
+        return Ok(());
+    };
+
+    let baseline = fs::read_to_string(baseline_file)?;
+    let baseline: BTreeSet<&str> = baseline.lines().filter(|line| !line.is_empty()).collect();
+    let current: BTreeSet<&str> = gaps.iter().map(String::as_str).collect();
+    for closed in baseline.difference(&current) {
+        println!("closed: {closed}");
+    }
+    let new: Vec<&&str> = current.difference(&baseline).collect();
+    for gap in &new {
+        println!("new: {gap}");
+    }
+    println!(
+        "{} gaps, {} new since {baseline_file}",
+        gaps.len(),
+        new.len()
+    );
+    if !new.is_empty() {
+        process::exit(1);
+    }
+
+    Ok(())
+}
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Compare the pipeline types with Microsoft's service schema"
+)]
+struct Args {
+    #[arg(
+        value_name = "SERVICE_SCHEMA",
+        help = "Path to a local copy of service-schema.json"
+    )]
+    service_schema: String,
+
+    #[arg(
+        long,
+        value_name = "BASELINE_FILE",
+        help = "Fail if there are gaps that aren't listed in a previous report"
+    )]
+    baseline: Option<String>,
+}
@@ -1,22 +1,48 @@
-use std::{error::Error, fs};
+use std::{error::Error, fs, process};
 
 use clap::Parser;
 
+#[cfg(feature = "schema")]
+use azure_pipelines_rs::core::v1::schema;
 use azure_pipelines_rs::{
-    core::v1::pipeline::Pipeline, templates::parameterized::Parameterized,
-    validator::dependencies::validate_dependencies,
+    core::v1::pipeline::Pipeline,
+    fmt::format,
+    source::parse::{self, Parsed},
+    templates::declare::{self, Declare},
+    validator::{
+        diagnostic::AstPath,
+        names::JobNameOverride,
+        report::{Mode, Report, validate_stages},
+        runtime,
+        unknown::unknown_field_diagnostics,
+    },
 };
 
 mod template;
 
-use template::ExampleEntrypoint;
+use template::ExampleEntrypointParameters;
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
 
+    #[cfg(feature = "schema")]
+    if let Some(schema_file) = &args.schema {
+        println!("writing pipeline schema to {schema_file}");
+        let schema = schema::pipeline_for::<ExampleEntrypointParameters>();
+        fs::write(schema_file, serde_json::to_string_pretty(&schema)?)?;
+    }
+
     let contents = fs::read_to_string(&args.pipeline_file)?;
 
-    let pipeline: Pipeline = serde_yaml::from_str(&contents)?;
+    // the template's parameters are read along with the rest of the pipeline
+    let Parsed {
+        value: pipeline,
+        source,
+    } = if args.lenient {
+        parse::from_str_lenient::<Pipeline<ExampleEntrypointParameters>>(&contents)?
+    } else {
+        parse::from_str(&contents)?
+    };
 
     println!("writing ast to ast.txt");
     fs::write("ast.txt", format!("{:#?}", &pipeline))?;
@@ -25,8 +51,72 @@ fn main() -> Result<(), Box<dyn Error>> {
     let parsed = serde_yaml::to_string(&pipeline)?;
     fs::write("parsed.yaml", parsed)?;
 
-    let parameters = ExampleEntrypoint::get_parameters(&pipeline.extends.parameters)?;
-    validate_dependencies(&parameters.stages)?;
+    let mode = if args.fail_fast {
+        Mode::FailFast
+    } else {
+        Mode::CollectAll
+    };
+    // validate_stages reports the unknown keys in the stages, so report those
+    // in the rest of the pipeline first
+    let stages = AstPath::default()
+        .field("extends")
+        .field("parameters")
+        .field("stages");
+    let mut report = Report {
+        diagnostics: unknown_field_diagnostics(&pipeline)
+            .into_iter()
+            .filter(|d| !d.path.0.starts_with(&stages.0))
+            .collect(),
+    };
+    report.locate(&source, &[]);
+    report.diagnostics.extend(
+        validate_stages(&pipeline.extends.parameters.stages, &JobNameOverride, mode).diagnostics,
+    );
+    report.locate(&source, &["extends", "parameters", "stages"]);
+    if !report.diagnostics.is_empty() {
+        println!("{report}");
+    }
+    if report.has_errors() {
+        process::exit(1);
+    }
+
+    if args.check_format
+        && let Some(line) = format::check(&contents)?
+    {
+        println!(
+            "{} isn't formatted, starting at line {line}",
+            args.pipeline_file
+        );
+        process::exit(1);
+    }
+
+    if !args.param.is_empty() {
+        let supplied = args
+            .param
+            .iter()
+            .map(|param| runtime::assignment(param))
+            .collect::<Result<_, _>>()?;
+        if let Err(report) = runtime::resolve(&pipeline.parameters, &supplied) {
+            println!("{report}");
+            process::exit(1);
+        }
+    }
+
+    if let Some(template_file) = &args.check_template {
+        let template = fs::read_to_string(template_file)?;
+        let declarations = ExampleEntrypointParameters::declarations()?;
+        let diagnostics = declare::check(&declarations, &template)?;
+        for diagnostic in &diagnostics {
+            println!("{template_file}: {diagnostic}");
+        }
+        if !diagnostics.is_empty() {
+            println!(
+                "expected the template to declare:\n{}",
+                declare::to_yaml(&declarations)?
+            );
+            process::exit(1);
+        }
+    }
 
     println!("pipeline valid");
 
@@ -38,4 +128,35 @@ fn main() -> Result<(), Box<dyn Error>> {
 struct Args {
     #[arg(value_name = "PIPELINE_FILE", help = "Path to your pipeline yaml file")]
     pipeline_file: String,
+
+    #[arg(long, help = "Stop at the first validation error")]
+    fail_fast: bool,
+
+    #[arg(long, help = "Warn about unknown keys instead of rejecting them")]
+    lenient: bool,
+
+    #[arg(long, help = "Fail if the pipeline file isn't canonically formatted")]
+    check_format: bool,
+
+    #[arg(
+        long,
+        value_name = "TEMPLATE_FILE",
+        help = "Fail if the template declares its parameters differently than the example"
+    )]
+    check_template: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "NAME=VALUE",
+        help = "Check a runtime parameter value as if queueing the pipeline (repeatable)"
+    )]
+    param: Vec<String>,
+
+    #[cfg(feature = "schema")]
+    #[arg(
+        long,
+        value_name = "SCHEMA_FILE",
+        help = "Write the pipeline's JSON Schema"
+    )]
+    schema: Option<String>,
 }
@@ -1,34 +1,45 @@
-use std::{collections::HashMap, error::Error};
-
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
-use serde_yaml::Value;
-
-use azure_pipelines_rs::{core::v1::stage::Stage, templates::parameterized::Parameterized};
-
-/// Define an Entrypoint type that has parameters and implement the
-/// `Parameterized` trait
-#[derive(Default)]
-pub struct ExampleEntrypoint {}
+use serde_yaml::{Mapping, Value};
 
-impl Parameterized for ExampleEntrypoint {
-    type Parameters = ExampleEntrypointParameters;
-
-    fn get_parameters(
-        hash_map: &HashMap<String, Value>,
-    ) -> Result<Self::Parameters, Box<dyn Error>> {
-        let s = serde_yaml::to_string(hash_map)?;
-        let parameters: ExampleEntrypointParameters = serde_yaml::from_str(&s)?;
-        Ok(parameters)
-    }
-}
+use azure_pipelines_rs::{
+    core::v1::stage::Stage,
+    validator::{diagnostic::AstPath, unknown::UnknownFields},
+};
+use azure_pipelines_rs_derive::{Declare, Parameterized};
 
-/// Define a type for the Entrypoint Parameters
-#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+/// Define a type for the Entrypoint Parameters and derive the `Parameterized`
+/// trait, naming the template in errors, and the `Declare` trait, which
+/// declares them as `entrypoint.yml` does
+#[derive(Serialize, Deserialize, Parameterized, Declare, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[parameterized(template = "entrypoint.yml@tools")]
 #[serde(deny_unknown_fields)]
 #[serde(rename_all = "camelCase")]
 pub struct ExampleEntrypointParameters {
+    /// Tags added to every build
+    #[serde(default)]
     pub custom_build_tags: Vec<String>,
-    pub containers: HashMap<String, String>,
-    pub feature_flags: HashMap<String, Value>,
+
+    /// Container images to run steps in, by name
+    #[serde(default)]
+    pub containers: IndexMap<String, String>,
+
+    /// Feature flags, by language
+    #[serde(default)]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "IndexMap<String, serde_json::Value>")
+    )]
+    pub feature_flags: IndexMap<String, Value>,
+
+    /// The stages to run
     pub stages: Vec<Stage>,
 }
+
+/// Lenient parsing keeps unknown keys in the stages, so report them
+impl UnknownFields for ExampleEntrypointParameters {
+    fn unknown_fields(&self, path: &AstPath, report: &mut dyn FnMut(&Mapping, &AstPath)) {
+        self.stages.unknown_fields(&path.field("stages"), report);
+    }
+}
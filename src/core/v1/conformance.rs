@@ -0,0 +1,420 @@
+//! Compare the pipeline types with Microsoft's published schema
+//!
+//! Azure DevOps publishes the schema its YAML editor uses as
+//! `service-schema.json`. [`check`] walks it alongside the [`schema`] of this
+//! crate's types and reports what they don't cover: keywords such as a
+//! `script` step, properties such as a stage's `lockBehavior`, and properties
+//! that take a different kind of value.
+
+use std::{
+    collections::BTreeSet,
+    error::Error,
+    fmt::{self, Display},
+};
+
+use serde_json::Value;
+
+use crate::core::v1::{schema, stage::Stage};
+
+/// Something Microsoft's schema accepts that this crate's types don't
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
+pub struct Gap {
+    /// Where the gap is, e.g. `stage(stage).jobs[](job).steps[]`, naming the
+    /// form of a node that may take several
+    pub path: String,
+
+    /// What's missing or different
+    pub kind: GapKind,
+}
+
+/// What's missing or different at a [`Gap`]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
+pub enum GapKind {
+    /// A form of node, named by the key it starts with, e.g. `deployment`
+    /// among jobs
+    Keyword(String),
+    /// A property of a node
+    Property(String),
+    /// A property takes other kinds of values
+    Type {
+        /// What this crate accepts
+        ours: BTreeSet<Kind>,
+        /// What Microsoft's schema accepts
+        theirs: BTreeSet<Kind>,
+    },
+}
+
+/// The kind of a YAML node
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
+pub enum Kind {
+    /// A string, number or boolean
+    Scalar,
+    /// A list
+    Sequence,
+    /// A set of keys and values
+    Mapping,
+}
+
+impl Display for Gap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            GapKind::Keyword(keyword) => write!(f, "{}: missing keyword `{keyword}`", self.path),
+            GapKind::Property(property) => {
+                write!(f, "{}: missing property `{property}`", self.path)
+            }
+            GapKind::Type { ours, theirs } => write!(
+                f,
+                "{}: accepts {}, expected {}",
+                self.path,
+                describe(ours),
+                describe(theirs)
+            ),
+        }
+    }
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Kind::Scalar => write!(f, "a scalar"),
+            Kind::Sequence => write!(f, "a sequence"),
+            Kind::Mapping => write!(f, "a mapping"),
+        }
+    }
+}
+
+fn describe(kinds: &BTreeSet<Kind>) -> String {
+    let kinds: Vec<String> = kinds.iter().map(Kind::to_string).collect();
+    kinds.join(" or ")
+}
+
+/// Every gap between this crate's types and `service_schema`, the parsed
+/// contents of `service-schema.json`, sorted by path
+pub fn check(service_schema: &Value) -> Result<Vec<Gap>, Box<dyn Error>> {
+    let roots = [
+        ("pipeline", schema::pipeline().to_value()),
+        ("stage", schema::schema_for::<Stage>().to_value()),
+    ];
+
+    let mut gaps = Vec::new();
+    for (name, ours) in &roots {
+        let theirs = service_schema
+            .pointer(&format!("/definitions/{name}"))
+            .ok_or_else(|| format!("the service schema has no `{name}` definition"))?;
+        let mut walk = Walk {
+            ours,
+            theirs: service_schema,
+            gaps: Vec::new(),
+        };
+        walk.compare(name, &[ours], &[theirs]);
+        gaps.append(&mut walk.gaps);
+    }
+    gaps.sort();
+    gaps.dedup();
+    Ok(gaps)
+}
+
+/// Walks this crate's schema and Microsoft's together, each resolving `$ref`s
+/// against its own root
+struct Walk<'a> {
+    ours: &'a Value,
+    theirs: &'a Value,
+    gaps: Vec<Gap>,
+}
+
+impl<'a> Walk<'a> {
+    fn compare(&mut self, path: &str, ours: &[&'a Value], theirs: &[&'a Value]) {
+        let ours = leaves(self.ours, ours);
+        let theirs = leaves(self.theirs, theirs);
+
+        // a schema that accepts anything leaves nothing to compare
+        let (Some(our_kinds), Some(their_kinds)) = (kinds(&ours), kinds(&theirs)) else {
+            return;
+        };
+        if our_kinds != their_kinds {
+            self.gaps.push(Gap {
+                path: path.to_string(),
+                kind: GapKind::Type {
+                    ours: our_kinds,
+                    theirs: their_kinds,
+                },
+            });
+        }
+
+        let our_maps: Vec<&Value> = of_kind(&ours, Kind::Mapping);
+        let their_maps: Vec<&Value> = of_kind(&theirs, Kind::Mapping);
+        if !our_maps.is_empty() {
+            let forms = their_maps.len();
+            for their_map in their_maps {
+                self.compare_mapping(path, &our_maps, their_map, forms > 1);
+            }
+        }
+
+        let our_items: Vec<&Value> = items(&ours);
+        let their_items: Vec<&Value> = items(&theirs);
+        if !our_items.is_empty() && !their_items.is_empty() {
+            self.compare(&format!("{path}[]"), &our_items, &their_items);
+        }
+    }
+
+    /// Compare one form of mapping in Microsoft's schema with the form of ours
+    /// that matches it. If there are several forms, the path names the one
+    /// compared, e.g. `steps[](checkout)`.
+    fn compare_mapping(
+        &mut self,
+        path: &str,
+        ours: &[&'a Value],
+        theirs: &'a Value,
+        name_form: bool,
+    ) {
+        let keywords = keywords(theirs);
+        let overlap = |our: &&&Value| {
+            properties(theirs)
+                .filter(|(name, _)| property(our, name).is_some())
+                .count()
+        };
+        let matched = ours
+            .iter()
+            .filter(|our| {
+                keywords
+                    .iter()
+                    .all(|keyword| property(our, keyword).is_some())
+            })
+            .max_by_key(overlap);
+        let Some(ours) = matched else {
+            if let Some(keyword) = keywords.first() {
+                self.gaps.push(Gap {
+                    path: path.to_string(),
+                    kind: GapKind::Keyword(keyword.to_string()),
+                });
+            }
+            return;
+        };
+
+        let path = match keywords.first() {
+            Some(keyword) if name_form => format!("{path}({keyword})"),
+            _ => path.to_string(),
+        };
+        let path = path.as_str();
+        for (name, their_property) in properties(theirs) {
+            match property(ours, name) {
+                Some(ours) => self.compare(&format!("{path}.{name}"), &[ours], &[their_property]),
+                None => self.gaps.push(Gap {
+                    path: path.to_string(),
+                    kind: GapKind::Property(name.clone()),
+                }),
+            }
+        }
+        if let (Some(ours @ Value::Object(_)), Some(theirs @ Value::Object(_))) = (
+            ours.get("additionalProperties"),
+            theirs.get("additionalProperties"),
+        ) {
+            self.compare(&format!("{path}.*"), &[ours], &[theirs]);
+        }
+    }
+}
+
+/// The schemas a value may match, following `$ref`s and `anyOf`, `oneOf` and
+/// `allOf` branches
+fn leaves<'a>(root: &'a Value, schemas: &[&'a Value]) -> Vec<&'a Value> {
+    fn visit<'a>(
+        root: &'a Value,
+        schema: &'a Value,
+        seen: &mut Vec<&'a str>,
+        out: &mut Vec<&'a Value>,
+    ) {
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            if seen.contains(&reference) {
+                return;
+            }
+            seen.push(reference);
+            if let Some(target) = root.pointer(reference.trim_start_matches('#')) {
+                visit(root, target, seen, out);
+            }
+            return;
+        }
+        let mut branched = false;
+        for key in ["anyOf", "oneOf", "allOf"] {
+            if let Some(branches) = schema.get(key).and_then(Value::as_array) {
+                branched = true;
+                for branch in branches {
+                    visit(root, branch, seen, out);
+                }
+            }
+        }
+        if !branched {
+            out.push(schema);
+        }
+    }
+
+    let mut seen = Vec::new();
+    let mut out = Vec::new();
+    for schema in schemas {
+        visit(root, schema, &mut seen, &mut out);
+    }
+    out
+}
+
+/// The kinds of node a schema accepts, or `None` if it accepts anything
+fn kind(schema: &Value) -> Option<BTreeSet<Kind>> {
+    let Value::Object(object) = schema else {
+        return match schema {
+            Value::Bool(true) => None,
+            _ => Some(BTreeSet::new()),
+        };
+    };
+    let types: Vec<&str> = match object.get("type") {
+        Some(Value::String(name)) => vec![name],
+        Some(Value::Array(names)) => names.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    let mut kinds: BTreeSet<Kind> = types
+        .into_iter()
+        .filter_map(|name| match name {
+            "object" => Some(Kind::Mapping),
+            "array" => Some(Kind::Sequence),
+            "null" => None,
+            _ => Some(Kind::Scalar),
+        })
+        .collect();
+    if kinds.is_empty() && !object.contains_key("type") {
+        if object.contains_key("properties") || object.contains_key("additionalProperties") {
+            kinds.insert(Kind::Mapping);
+        } else if object.contains_key("items") {
+            kinds.insert(Kind::Sequence);
+        } else if ["enum", "const", "pattern"]
+            .iter()
+            .any(|key| object.contains_key(*key))
+        {
+            kinds.insert(Kind::Scalar);
+        } else {
+            return None;
+        }
+    }
+    Some(kinds)
+}
+
+/// The kinds of node any of `leaves` accepts, or `None` if one accepts anything
+fn kinds(leaves: &[&Value]) -> Option<BTreeSet<Kind>> {
+    let mut kinds = BTreeSet::new();
+    for leaf in leaves {
+        kinds.extend(kind(leaf)?);
+    }
+    Some(kinds)
+}
+
+fn of_kind<'a>(leaves: &[&'a Value], of: Kind) -> Vec<&'a Value> {
+    leaves
+        .iter()
+        .copied()
+        .filter(|leaf| kind(leaf).is_some_and(|kinds| kinds.contains(&of)))
+        .collect()
+}
+
+fn items<'a>(leaves: &[&'a Value]) -> Vec<&'a Value> {
+    leaves.iter().filter_map(|leaf| leaf.get("items")).collect()
+}
+
+fn properties(schema: &Value) -> impl Iterator<Item = (&String, &Value)> {
+    schema
+        .get("properties")
+        .and_then(Value::as_object)
+        .into_iter()
+        .flatten()
+}
+
+fn property<'a>(schema: &'a Value, name: &str) -> Option<&'a Value> {
+    schema.get("properties")?.get(name)
+}
+
+/// The keys that pick a form of mapping, e.g. `job` or `template` for a job.
+/// Microsoft's schema names them in `firstProperty`, or else in `required`.
+fn keywords(schema: &Value) -> Vec<&str> {
+    ["firstProperty", "required"]
+        .iter()
+        .filter_map(|key| schema.get(*key).and_then(Value::as_array))
+        .find(|keys| !keys.is_empty())
+        .map(|keys| keys.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn reports_gaps() {
+        let service_schema = json!({
+            "definitions": {
+                "pipeline": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "lockBehavior": { "type": "string" },
+                        "pool": { "type": ["string", "object"] }
+                    }
+                },
+                "stage": {
+                    "anyOf": [
+                        {
+                            "type": "object",
+                            "firstProperty": ["stage"],
+                            "properties": {
+                                "stage": { "type": "string" },
+                                "lockBehavior": { "type": "string" },
+                                "jobs": { "$ref": "#/definitions/jobs" }
+                            }
+                        },
+                        {
+                            "type": "object",
+                            "firstProperty": ["template"],
+                            "properties": { "template": { "type": "string" } }
+                        }
+                    ]
+                },
+                "jobs": {
+                    "type": "array",
+                    "items": {
+                        "anyOf": [
+                            {
+                                "type": "object",
+                                "firstProperty": ["job"],
+                                "properties": { "job": { "type": "string" } }
+                            },
+                            {
+                                "type": "object",
+                                "firstProperty": ["deployment"],
+                                "properties": { "deployment": { "type": "string" } }
+                            }
+                        ]
+                    }
+                }
+            }
+        });
+        let gaps: Vec<String> = check(&service_schema)
+            .unwrap()
+            .iter()
+            .map(Gap::to_string)
+            .collect();
+        assert_eq!(
+            gaps,
+            [
+                "pipeline: missing property `lockBehavior`",
+                "pipeline.pool: accepts a scalar, expected a scalar or a mapping",
+                "stage(stage): missing property `lockBehavior`",
+                "stage(stage).jobs[]: missing keyword `deployment`",
+            ]
+        );
+    }
+
+    #[test]
+    fn requires_the_definitions_it_compares() {
+        let err = check(&json!({ "definitions": {} })).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the service schema has no `pipeline` definition"
+        );
+    }
+}
@@ -1,10 +1,38 @@
 //! The `dependsOn` property accepts `string` or `[ string ]`
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_yaml::Value;
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+use crate::core::v1::tagged::{buffered, type_name, variant};
+
+/// One name or a list of names, written back the way it was read
+#[derive(Serialize, PartialEq, Debug)]
 #[serde(untagged)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum DependsOn {
     Single(String),
     Multi(Vec<String>),
 }
+
+impl<'de> Deserialize<'de> for DependsOn {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        buffered(deserializer, |value| match value {
+            Value::String(name) => Ok(DependsOn::Single(name)),
+            value @ Value::Sequence(_) => variant(value, "dependsOn").map(DependsOn::Multi),
+            other => Err(format!(
+                "dependsOn: expected a name or a list of names, found {}",
+                type_name(&other)
+            )),
+        })
+    }
+}
+
+impl DependsOn {
+    /// Every name listed, whichever form was used
+    pub fn names(&self) -> &[String] {
+        match self {
+            DependsOn::Single(name) => std::slice::from_ref(name),
+            DependsOn::Multi(names) => names,
+        }
+    }
+}
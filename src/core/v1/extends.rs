@@ -2,18 +2,36 @@
 //!
 //! <https://learn.microsoft.com/en-us/azure/devops/pipelines/yaml-schema/extends?view=azure-pipelines>
 
-use std::collections::HashMap;
-
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
-use serde_yaml::Value;
+use serde_yaml::{Mapping, Value};
+
+use crate::core::v1::unknown;
 
 /// Extend a pipeline using a template
+///
+/// `P` is the type of the template's parameters: a mapping of YAML values
+/// unless it's read as a type of your own, such as one deriving
+/// [`Parameterized`](crate::templates::parameterized::Parameterized)
 #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
-#[serde(deny_unknown_fields)]
-pub struct Extends {
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(deny_unknown_fields)
+)]
+pub struct Extends<P = IndexMap<String, Value>> {
     /// The template referenced by the pipeline to extend
     pub template: String,
 
     /// Parameters used in the extend
-    pub parameters: HashMap<String, Value>,
+    ///
+    /// Unlike other empty fields, these are written even when empty: `P` may
+    /// have no default to read back when they're left out.
+    pub parameters: P,
+
+    /// Keys this crate doesn't know, which are rejected unless parsed with
+    /// [`parse::from_str_lenient`](crate::source::parse::from_str_lenient)
+    #[serde(flatten, deserialize_with = "unknown::deserialize")]
+    #[cfg_attr(feature = "schema", schemars(skip))]
+    pub unknown: Mapping,
 }
@@ -2,71 +2,165 @@
 //!
 //! <https://learn.microsoft.com/en-us/azure/devops/pipelines/yaml-schema/jobs?view=azure-pipelines>
 
-use std::collections::HashMap;
+use indexmap::IndexMap;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_yaml::{Mapping, Value};
 
-use serde::{Deserialize, Serialize};
-use serde_yaml::Value;
-
-use crate::core::v1::{depends::DependsOn, step::Step};
+#[cfg(feature = "schema")]
+use crate::core::v1::schema;
+use crate::core::v1::{
+    depends::DependsOn,
+    step::Step,
+    tagged::{buffered, mapping, variant},
+    unknown,
+};
 
 /// Specifies the jobs that make up the work of a stage
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, PartialEq, Debug)]
 #[serde(untagged)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum Job {
     /// A job is a collection of steps run by an agent or on a server
     Job(JobWithSteps),
     /// A set of jobs defined in a template
+    #[cfg_attr(feature = "schema", schemars(transform = schema::requires("template")))]
     Template(JobWithTemplate),
 }
 
+impl<'de> Deserialize<'de> for Job {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        buffered(deserializer, |value| {
+            let map = mapping(&value, "job")?;
+            if map.contains_key("template") {
+                variant(value, "job template").map(Job::Template)
+            } else if map.contains_key("deployment") {
+                Err("deployment jobs aren't supported".to_string())
+            } else {
+                variant(value, "job").map(Job::Job)
+            }
+        })
+    }
+}
+
 /// A job is a collection of steps run by an agent or on a server
 ///
 /// <https://learn.microsoft.com/en-us/azure/devops/pipelines/yaml-schema/jobs-job?view=azure-pipelines>
 #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
-#[serde(deny_unknown_fields)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(deny_unknown_fields)
+)]
 pub struct JobWithSteps {
     /// ID of the job. Acceptable values: Valid names may only contain
     /// alphanumeric characters and `_` and may not start with a number.
-    #[serde(rename = "job")]
+    #[serde(rename = "job", skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
 
     /// Human-readable name for the job
-    #[serde(rename = "displayName")]
+    #[serde(rename = "displayName", skip_serializing_if = "Option::is_none")]
     pub display_name: Option<String>,
 
     /// Any jobs which must complete before this one
-    #[serde(rename = "dependsOn")]
+    #[serde(rename = "dependsOn", skip_serializing_if = "Option::is_none")]
     pub depends_on: Option<DependsOn>,
 
     /// Evaluate this condition expression to determine whether to run this job
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub condition: Option<String>,
 
-    /// Pool where this job will run
-    pub pool: Option<String>,
-
     /// Time to wait for this job to complete before the server kills it
-    #[serde(rename = "timeoutInMinutes")]
+    #[serde(rename = "timeoutInMinutes", skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<serde_json::Value>"))]
     pub timeout_in_minutes: Option<Value>,
 
+    /// Pool where this job will run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool: Option<String>,
+
     /// Job-specific variables
-    #[serde(default)]
-    pub variables: HashMap<String, Value>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "IndexMap<String, serde_json::Value>")
+    )]
+    pub variables: IndexMap<String, Value>,
 
     /// A list of steps to run
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub steps: Vec<Step>,
+
+    /// Keys this crate doesn't know, which are rejected unless parsed with
+    /// [`parse::from_str_lenient`](crate::source::parse::from_str_lenient)
+    #[serde(flatten, deserialize_with = "unknown::deserialize")]
+    #[cfg_attr(feature = "schema", schemars(skip))]
+    pub unknown: Mapping,
 }
 
 /// A set of jobs defined in a template
 ///
 /// <https://learn.microsoft.com/en-us/azure/devops/pipelines/yaml-schema/jobs-template?view=azure-pipelines>
 #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
-#[serde(deny_unknown_fields)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(deny_unknown_fields)
+)]
 pub struct JobWithTemplate {
     /// Reference to a template for this deployment
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub template: Option<String>,
 
     /// Parameters used in a deployment template
-    #[serde(default)]
-    pub parameters: HashMap<String, Value>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "IndexMap<String, serde_json::Value>")
+    )]
+    pub parameters: IndexMap<String, Value>,
+
+    /// Keys this crate doesn't know, which are rejected unless parsed with
+    /// [`parse::from_str_lenient`](crate::source::parse::from_str_lenient)
+    #[serde(flatten, deserialize_with = "unknown::deserialize")]
+    #[cfg_attr(feature = "schema", schemars(skip))]
+    pub unknown: Mapping,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fmt::order::NodeKind;
+
+    use super::*;
+
+    #[test]
+    fn keys_follow_the_canonical_order() {
+        let source = "\
+steps: []
+variables:
+  answer: 42
+pool: linux
+timeoutInMinutes: 10
+condition: succeeded()
+dependsOn: build
+displayName: Test
+job: test
+";
+        let job: Job = serde_yaml::from_str(source).unwrap();
+        let written = serde_yaml::to_value(&job).unwrap();
+        let keys: Vec<_> = written
+            .as_mapping()
+            .unwrap()
+            .keys()
+            .map(|key| key.as_str().unwrap())
+            .collect();
+        let canonical: Vec<_> = NodeKind::Job
+            .key_order()
+            .iter()
+            .copied()
+            .filter(|key| keys.contains(key))
+            .collect();
+        assert_eq!(keys, canonical);
+        // empty `steps` are left out
+        assert_eq!(keys.len(), 7);
+    }
 }
@@ -1,9 +1,16 @@
 //! The current (only) version
 
+#[cfg(feature = "schema")]
+pub mod conformance;
 pub mod depends;
 pub mod extends;
 pub mod job;
 pub mod pipeline;
+pub mod predefined;
+#[cfg(feature = "schema")]
+pub mod schema;
 pub mod stage;
 pub mod step;
+pub(crate) mod tagged;
 pub mod trigger;
+pub(crate) mod unknown;
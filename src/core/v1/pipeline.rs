@@ -2,43 +2,64 @@
 //!
 //! <https://learn.microsoft.com/en-us/azure/devops/pipelines/yaml-schema/pipeline?view=azure-pipelines#pipelineextends>
 
-use serde::{Deserialize, Serialize};
-use serde_yaml::Value;
+use indexmap::IndexMap;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_yaml::{Mapping, Value};
 
 use crate::core::v1::{
     extends::Extends,
+    tagged::{buffered, mapping, variant},
     trigger::{ResourceTrigger, Trigger},
+    unknown,
 };
 
 /// Pipeline that extends a template
+///
+/// `P` is the type of the template's parameters, as in [`Extends`]. Parse a
+/// `Pipeline<MyParameters>` to read them along with the rest of the pipeline.
 #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
-#[serde(deny_unknown_fields)]
-pub struct Pipeline {
-    /// Extends a template
-    pub extends: Extends,
-
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(deny_unknown_fields)
+)]
+pub struct Pipeline<P = IndexMap<String, Value>> {
     /// Pipeline run number
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
 
-    /// The runtime parameters for this pipeline.
-    #[serde(default)]
-    pub parameters: Vec<PipelineParameter>,
-
-    /// Pool where jobs in this pipeline will run unless otherwise specified
-    pub pool: Option<String>,
+    /// Continuous integration triggers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger: Option<Trigger>,
 
     /// Pull request triggers
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub pr: Option<String>,
 
+    /// The runtime parameters for this pipeline.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parameters: Vec<PipelineParameter>,
+
     /// Containers and repositories used in the build
+    #[serde(skip_serializing_if = "PipelineResources::is_absent")]
     pub resources: Option<PipelineResources>,
 
-    /// Continuous integration triggers
-    pub trigger: Option<Trigger>,
-
     /// Variables for this pipeline
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub variables: Vec<PipelineVariable>,
+
+    /// Pool where jobs in this pipeline will run unless otherwise specified
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool: Option<String>,
+
+    /// Extends a template
+    pub extends: Extends<P>,
+
+    /// Keys this crate doesn't know, which are rejected unless parsed with
+    /// [`parse::from_str_lenient`](crate::source::parse::from_str_lenient)
+    #[serde(flatten, deserialize_with = "unknown::deserialize")]
+    #[cfg_attr(feature = "schema", schemars(skip))]
+    pub unknown: Mapping,
 }
 
 /// Resources specifies builds, repositories, pipelines, and other resources
@@ -46,34 +67,67 @@ pub struct Pipeline {
 ///
 /// <https://learn.microsoft.com/en-us/azure/devops/pipelines/yaml-schema/resources?view=azure-pipelines>
 #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
-#[serde(deny_unknown_fields)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(deny_unknown_fields)
+)]
 pub struct PipelineResources {
     /// List of container images
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub containers: Vec<ContainerResource>,
 
     /// List of pipeline resources
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub pipelines: Vec<PipelineResource>,
 
     /// List of repository resources
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub repositories: Vec<RepositoryResource>,
+
+    /// Keys this crate doesn't know, which are rejected unless parsed with
+    /// [`parse::from_str_lenient`](crate::source::parse::from_str_lenient)
+    #[serde(flatten, deserialize_with = "unknown::deserialize")]
+    #[cfg_attr(feature = "schema", schemars(skip))]
+    pub unknown: Mapping,
+}
+
+impl PipelineResources {
+    /// Whether there are no resources to write
+    fn is_absent(resources: &Option<Self>) -> bool {
+        resources.as_ref().is_none_or(|resources| {
+            resources.containers.is_empty()
+                && resources.pipelines.is_empty()
+                && resources.repositories.is_empty()
+                && resources.unknown.is_empty()
+        })
+    }
 }
 
 /// A container resource references a container image
 #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
-#[serde(deny_unknown_fields)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(deny_unknown_fields)
+)]
 pub struct ContainerResource {
     /// ID for the container. Acceptable values: `[-_A-Za-z0-9]*`
     #[serde(rename = "container")]
     pub name: String,
 
+    /// Container image tag
+    pub image: String,
+
     /// ID of the service endpoint connecting to a private container registry
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub endpoint: Option<String>,
 
-    /// Container image tag
-    pub image: String,
+    /// Keys this crate doesn't know, which are rejected unless parsed with
+    /// [`parse::from_str_lenient`](crate::source::parse::from_str_lenient)
+    #[serde(flatten, deserialize_with = "unknown::deserialize")]
+    #[cfg_attr(feature = "schema", schemars(skip))]
+    pub unknown: Mapping,
 }
 
 /// If you have an Azure Pipeline that produces artifacts, your pipeline can
@@ -83,7 +137,11 @@ pub struct ContainerResource {
 ///
 /// <https://learn.microsoft.com/en-us/azure/devops/pipelines/yaml-schema/resources-pipelines-pipeline?view=azure-pipelines>
 #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
-#[serde(deny_unknown_fields)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(deny_unknown_fields)
+)]
 pub struct PipelineResource {
     /// ID of the pipeline resource
     pub pipeline: String,
@@ -93,13 +151,24 @@ pub struct PipelineResource {
 
     /// Specify `none` to disable, `true` to include all branches, or use the
     /// full syntax
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub trigger: Option<ResourceTrigger>,
+
+    /// Keys this crate doesn't know, which are rejected unless parsed with
+    /// [`parse::from_str_lenient`](crate::source::parse::from_str_lenient)
+    #[serde(flatten, deserialize_with = "unknown::deserialize")]
+    #[cfg_attr(feature = "schema", schemars(skip))]
+    pub unknown: Mapping,
 }
 
 /// The `repository` keyword lets you specify an external repository. Use a
 /// repository resource to reference an additional repository in your pipeline.
 #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
-#[serde(deny_unknown_fields)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(deny_unknown_fields)
+)]
 pub struct RepositoryResource {
     /// Alias for the specified repository. Acceptable values: `[-_A-Za-z0-9]*`
     #[serde(rename = "repository")]
@@ -116,13 +185,20 @@ pub struct RepositoryResource {
     /// out by default whenever the resource trigger fires
     #[serde(rename = "ref")]
     pub repository_ref: String,
+
+    /// Keys this crate doesn't know, which are rejected unless parsed with
+    /// [`parse::from_str_lenient`](crate::source::parse::from_str_lenient)
+    #[serde(flatten, deserialize_with = "unknown::deserialize")]
+    #[cfg_attr(feature = "schema", schemars(skip))]
+    pub unknown: Mapping,
 }
 
 /// Define variables using name/value pairs
 ///
 /// <https://learn.microsoft.com/en-us/azure/devops/pipelines/yaml-schema/variables?view=azure-pipelines>
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, PartialEq, Debug)]
 #[serde(untagged)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum PipelineVariable {
     /// Reference variables from a variable group
     Group(VariableGroup),
@@ -131,36 +207,75 @@ pub enum PipelineVariable {
     Variable(ValueVariable),
 }
 
+impl<'de> Deserialize<'de> for PipelineVariable {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        buffered(deserializer, |value| {
+            let map = mapping(&value, "variable")?;
+            if map.contains_key("group") {
+                variant(value, "variable group").map(PipelineVariable::Group)
+            } else if map.contains_key("template") {
+                Err("variable templates aren't supported".to_string())
+            } else {
+                variant(value, "variable").map(PipelineVariable::Variable)
+            }
+        })
+    }
+}
+
 /// Reference variables from a variable group
 #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
-#[serde(deny_unknown_fields)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(deny_unknown_fields)
+)]
 pub struct VariableGroup {
     /// Variable group name
     pub group: String,
+
+    /// Keys this crate doesn't know, which are rejected unless parsed with
+    /// [`parse::from_str_lenient`](crate::source::parse::from_str_lenient)
+    #[serde(flatten, deserialize_with = "unknown::deserialize")]
+    #[cfg_attr(feature = "schema", schemars(skip))]
+    pub unknown: Mapping,
 }
 
 /// Define variables using name and full syntax
 #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
-#[serde(deny_unknown_fields)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(deny_unknown_fields)
+)]
 pub struct ValueVariable {
     /// Variable name
     pub name: String,
 
     /// Variable value
     pub value: String,
+
+    /// Keys this crate doesn't know, which are rejected unless parsed with
+    /// [`parse::from_str_lenient`](crate::source::parse::from_str_lenient)
+    #[serde(flatten, deserialize_with = "unknown::deserialize")]
+    #[cfg_attr(feature = "schema", schemars(skip))]
+    pub unknown: Mapping,
 }
 
 /// A parameter represents a value passed to a pipeline.
 ///
 /// <https://learn.microsoft.com/en-us/azure/devops/pipelines/yaml-schema/parameters-parameter?view=azure-pipelines>
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
-#[serde(deny_unknown_fields)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(deny_unknown_fields)
+)]
 pub struct PipelineParameter {
     /// Parameter name
     pub name: String,
 
     /// Human-readable name for the parameter
-    #[serde(rename = "displayName")]
+    #[serde(rename = "displayName", skip_serializing_if = "Option::is_none")]
     pub display_name: Option<String>,
 
     /// Parameter type
@@ -169,9 +284,202 @@ pub struct PipelineParameter {
 
     /// Default value -- if there is no default, then it's required for the user
     /// to specify a value at runtime
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<serde_json::Value>"))]
     pub default: Option<Value>,
 
     /// Allowed list of values (for some data types)
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(feature = "schema", schemars(with = "Vec<serde_json::Value>"))]
     pub values: Vec<Value>,
+
+    /// Keys this crate doesn't know, which are rejected unless parsed with
+    /// [`parse::from_str_lenient`](crate::source::parse::from_str_lenient)
+    #[serde(flatten, deserialize_with = "unknown::deserialize")]
+    #[cfg_attr(feature = "schema", schemars(skip))]
+    pub unknown: Mapping,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fmt::order::NodeKind;
+
+    use super::*;
+
+    fn keys(value: &Value) -> Vec<&str> {
+        value
+            .as_mapping()
+            .unwrap()
+            .keys()
+            .map(|key| key.as_str().unwrap())
+            .collect()
+    }
+
+    fn assert_canonical(value: &Value, kind: NodeKind) {
+        let keys = keys(value);
+        let canonical: Vec<_> = kind
+            .key_order()
+            .iter()
+            .copied()
+            .filter(|key| keys.contains(key))
+            .collect();
+        assert_eq!(keys, canonical);
+    }
+
+    #[test]
+    fn maps_keep_their_order() {
+        let source = "\
+extends:
+  template: entrypoint.yml
+  parameters:
+    zebra: 1
+    apple: 2
+    mango:
+      second: b
+      first: a
+parameters:
+- name: os
+  type: string
+  default: linux
+";
+        let pipeline: Pipeline = serde_yaml::from_str(source).unwrap();
+        let keys: Vec<_> = pipeline.extends.parameters.keys().collect();
+        assert_eq!(keys, ["zebra", "apple", "mango"]);
+
+        let written = serde_yaml::to_string(&pipeline).unwrap();
+        assert_eq!(
+            written,
+            serde_yaml::to_string(&serde_yaml::from_str::<Pipeline>(&written).unwrap()).unwrap()
+        );
+        assert!(written.find("zebra").unwrap() < written.find("apple").unwrap());
+        assert!(written.find("second").unwrap() < written.find("first").unwrap());
+    }
+
+    #[test]
+    fn keys_follow_the_canonical_order() {
+        let source = "\
+extends:
+  parameters: {}
+  template: entrypoint.yml
+variables:
+- name: answer
+  value: '42'
+pool: linux
+resources:
+  containers:
+  - endpoint: registry
+    image: ubuntu
+    container: linux
+parameters:
+- default: linux
+  type: string
+  name: os
+trigger: none
+name: $(Date:yyyyMMdd)
+";
+        let pipeline: Pipeline = serde_yaml::from_str(source).unwrap();
+        let written = serde_yaml::to_value(&pipeline).unwrap();
+        assert_eq!(
+            keys(&written),
+            [
+                "name",
+                "trigger",
+                "parameters",
+                "resources",
+                "variables",
+                "pool",
+                "extends"
+            ]
+        );
+        assert_canonical(&written, NodeKind::Pipeline);
+        assert_canonical(&written["parameters"][0], NodeKind::Parameter);
+        assert_canonical(&written["resources"], NodeKind::Resources);
+        assert_canonical(&written["resources"]["containers"][0], NodeKind::Container);
+        assert_canonical(&written["variables"][0], NodeKind::Variable);
+        assert_canonical(&written["extends"], NodeKind::Extends);
+    }
+
+    #[test]
+    fn empty_fields_are_omitted_except_extends_parameters() {
+        let source = "\
+parameters: []
+variables: []
+resources:
+  containers: []
+extends:
+  template: a.yml
+  parameters: {}
+";
+        let pipeline: Pipeline = serde_yaml::from_str(source).unwrap();
+        assert_eq!(
+            serde_yaml::to_string(&pipeline).unwrap(),
+            "extends:\n  template: a.yml\n  parameters: {}\n"
+        );
+    }
+
+    #[test]
+    fn shorthand_forms_are_kept() {
+        let source = "\
+trigger: none
+resources:
+  pipelines:
+  - pipeline: upstream
+    source: build
+    trigger: true
+  - pipeline: nightly
+    source: nightly
+    trigger: none
+extends:
+  template: a.yml
+  parameters: {}
+";
+        let pipeline: Pipeline = serde_yaml::from_str(source).unwrap();
+        assert_eq!(serde_yaml::to_string(&pipeline).unwrap(), source);
+    }
+
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    #[serde(rename_all = "camelCase", deny_unknown_fields)]
+    struct EntrypointParameters {
+        build_tags: Vec<String>,
+        #[serde(default)]
+        fast: bool,
+    }
+
+    #[test]
+    fn typed_extends_parameters() {
+        let source = "\
+extends:
+  template: entrypoint.yml
+  parameters:
+    buildTags: [Rust.Rocks]
+";
+        let pipeline: Pipeline<EntrypointParameters> = serde_yaml::from_str(source).unwrap();
+        assert_eq!(
+            pipeline.extends.parameters,
+            EntrypointParameters {
+                build_tags: vec!["Rust.Rocks".to_string()],
+                fast: false,
+            }
+        );
+        assert_eq!(
+            serde_yaml::to_string(&pipeline).unwrap(),
+            "extends:\n  template: entrypoint.yml\n  parameters:\n    buildTags:\n    - Rust.Rocks\n    fast: false\n"
+        );
+    }
+
+    #[test]
+    fn typed_extends_parameter_errors_point_into_the_yaml() {
+        let source = "\
+name: build
+extends:
+  template: entrypoint.yml
+  parameters:
+    buildTags: [Rust.Rocks]
+    fast: very
+";
+        let err = serde_yaml::from_str::<Pipeline<EntrypointParameters>>(source).unwrap_err();
+        let location = err.location().unwrap();
+        assert_eq!((location.line(), location.column()), (6, 11));
+        assert!(err.to_string().contains("extends.parameters.fast"), "{err}");
+    }
 }
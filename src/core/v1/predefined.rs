@@ -0,0 +1,163 @@
+//! Predefined variables
+//!
+//! Azure DevOps sets these for every run, but not everywhere: some are known
+//! when templates are expanded, while others are only set once a job starts,
+//! and those describing the agent or its directories don't exist in agentless
+//! (`pool: server`) jobs.
+//!
+//! <https://learn.microsoft.com/en-us/azure/devops/pipelines/build/variables?view=azure-pipelines>
+
+/// A predefined variable and where it's available
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Variable {
+    /// Variable name, in the casing used by the documentation
+    pub name: &'static str,
+
+    /// Whether the variable is set when templates are expanded, so template
+    /// expressions (`${{ }}`) can read it
+    pub template: bool,
+
+    /// Whether the variable is set in agentless jobs
+    pub agentless: bool,
+}
+
+impl Variable {
+    /// A variable set for the whole run, including when templates are
+    /// expanded
+    const fn run(name: &'static str) -> Self {
+        Variable {
+            name,
+            template: true,
+            agentless: true,
+        }
+    }
+
+    /// A variable set once a job starts
+    const fn job(name: &'static str) -> Self {
+        Variable {
+            name,
+            template: false,
+            agentless: true,
+        }
+    }
+
+    /// A variable set by the agent running a job
+    const fn agent(name: &'static str) -> Self {
+        Variable {
+            name,
+            template: false,
+            agentless: false,
+        }
+    }
+}
+
+/// Every predefined variable whose availability is documented
+pub const VARIABLES: &[Variable] = &[
+    Variable::agent("Agent.BuildDirectory"),
+    Variable::agent("Agent.ContainerMapping"),
+    Variable::agent("Agent.HomeDirectory"),
+    Variable::agent("Agent.Id"),
+    Variable::agent("Agent.JobName"),
+    Variable::agent("Agent.JobStatus"),
+    Variable::agent("Agent.MachineName"),
+    Variable::agent("Agent.Name"),
+    Variable::agent("Agent.OS"),
+    Variable::agent("Agent.OSArchitecture"),
+    Variable::agent("Agent.TempDirectory"),
+    Variable::agent("Agent.ToolsDirectory"),
+    Variable::agent("Agent.WorkFolder"),
+    Variable::agent("Build.ArtifactStagingDirectory"),
+    Variable::run("Build.BuildId"),
+    Variable::job("Build.BuildNumber"),
+    Variable::job("Build.BuildUri"),
+    Variable::agent("Build.BinariesDirectory"),
+    Variable::agent("Build.ContainerId"),
+    Variable::run("Build.CronSchedule.DisplayName"),
+    Variable::run("Build.DefinitionName"),
+    Variable::run("Build.DefinitionVersion"),
+    Variable::run("Build.QueuedBy"),
+    Variable::run("Build.QueuedById"),
+    Variable::run("Build.Reason"),
+    Variable::agent("Build.Repository.Clean"),
+    Variable::agent("Build.Repository.Git.SubmoduleCheckout"),
+    Variable::job("Build.Repository.ID"),
+    Variable::agent("Build.Repository.LocalPath"),
+    Variable::job("Build.Repository.Name"),
+    Variable::job("Build.Repository.Provider"),
+    Variable::agent("Build.Repository.Tfvc.Workspace"),
+    Variable::job("Build.Repository.Uri"),
+    Variable::run("Build.RequestedFor"),
+    Variable::run("Build.RequestedForEmail"),
+    Variable::run("Build.RequestedForId"),
+    Variable::run("Build.SourceBranch"),
+    Variable::run("Build.SourceBranchName"),
+    Variable::agent("Build.SourcesDirectory"),
+    Variable::job("Build.SourceTfvcShelveset"),
+    Variable::run("Build.SourceVersion"),
+    Variable::agent("Build.SourceVersionMessage"),
+    Variable::agent("Build.StagingDirectory"),
+    Variable::run("Build.TriggeredBy.BuildId"),
+    Variable::run("Build.TriggeredBy.BuildNumber"),
+    Variable::run("Build.TriggeredBy.DefinitionId"),
+    Variable::run("Build.TriggeredBy.DefinitionName"),
+    Variable::run("Build.TriggeredBy.ProjectID"),
+    Variable::job("Checks.StageAttempt"),
+    Variable::agent("Common.TestResultsDirectory"),
+    Variable::agent("Pipeline.Workspace"),
+    Variable::run("System.AccessToken"),
+    Variable::run("System.CollectionId"),
+    Variable::run("System.CollectionUri"),
+    Variable::agent("System.DefaultWorkingDirectory"),
+    Variable::run("System.DefinitionId"),
+    Variable::run("System.HostType"),
+    Variable::job("System.JobAttempt"),
+    Variable::job("System.JobDisplayName"),
+    Variable::job("System.JobId"),
+    Variable::job("System.JobName"),
+    Variable::job("System.PhaseAttempt"),
+    Variable::job("System.PhaseDisplayName"),
+    Variable::job("System.PhaseName"),
+    Variable::job("System.PlanId"),
+    Variable::run("System.PullRequest.IsFork"),
+    Variable::job("System.PullRequest.PullRequestId"),
+    Variable::job("System.PullRequest.PullRequestNumber"),
+    Variable::job("System.PullRequest.SourceBranch"),
+    Variable::job("System.PullRequest.SourceCommitId"),
+    Variable::job("System.PullRequest.SourceRepositoryURI"),
+    Variable::job("System.PullRequest.TargetBranch"),
+    Variable::job("System.PullRequest.targetBranchName"),
+    Variable::job("System.StageAttempt"),
+    Variable::job("System.StageDisplayName"),
+    Variable::job("System.StageName"),
+    Variable::run("System.TeamFoundationCollectionUri"),
+    Variable::run("System.TeamProject"),
+    Variable::run("System.TeamProjectId"),
+    Variable::job("System.TimelineId"),
+    Variable::run("TF_BUILD"),
+];
+
+/// Look up a predefined variable by name (variable names are
+/// case-insensitive)
+pub fn lookup(name: &str) -> Option<&'static Variable> {
+    VARIABLES.iter().find(|v| v.name.eq_ignore_ascii_case(name))
+}
+
+/// Whether a variable is one Azure DevOps defines, e.g. `Build.SourceBranch`.
+/// Besides those in [`VARIABLES`], this includes every name in a namespace
+/// Azure DevOps reserves, such as `System.Debug` or
+/// `Resources.Pipeline.<alias>.RunID`.
+pub fn is_predefined(name: &str) -> bool {
+    const PREFIXES: &[&str] = &[
+        "agent.",
+        "build.",
+        "checks.",
+        "common.",
+        "environment.",
+        "pipeline.",
+        "resources.",
+        "strategy.",
+        "system.",
+    ];
+    let lower = name.to_ascii_lowercase();
+    lookup(name).is_some() || PREFIXES.iter().any(|prefix| lower.starts_with(prefix))
+}
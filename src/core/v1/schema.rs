@@ -0,0 +1,111 @@
+//! JSON Schema of the pipeline types
+//!
+//! The schemas describe exactly what this crate accepts when parsing strictly,
+//! so keys it doesn't know are rejected. Point an editor's YAML language server
+//! at one to check pipelines as they're written.
+
+use indexmap::IndexMap;
+use schemars::{JsonSchema, Schema, generate::SchemaSettings};
+use serde_json::{Value, json};
+
+use crate::{core::v1::pipeline::Pipeline, templates::parameterized::Parameterized};
+
+/// JSON Schema of `T`, such as a [`Stage`](super::stage::Stage),
+/// [`Job`](super::job::Job) or [`Step`](super::step::Step)
+pub fn schema_for<T: JsonSchema>() -> Schema {
+    settings().into_generator().into_root_schema_for::<T>()
+}
+
+/// JSON Schema of a pipeline, whose `extends.parameters` may be any mapping
+pub fn pipeline() -> Schema {
+    schema_for::<Pipeline<IndexMap<String, Value>>>()
+}
+
+/// JSON Schema of a pipeline that extends the template `T`, whose
+/// `extends.parameters` must be `T::Parameters`
+pub fn pipeline_for<T>() -> Schema
+where
+    T: Parameterized,
+    T::Parameters: JsonSchema,
+{
+    schema_for::<Pipeline<T::Parameters>>()
+}
+
+/// Draft 7, which YAML language servers support best
+fn settings() -> SchemaSettings {
+    SchemaSettings::draft07()
+}
+
+/// Require `key` in the schema of an enum variant that's chosen by the
+/// presence of that key
+pub(crate) fn requires(key: &'static str) -> impl FnMut(&mut Schema) {
+    move |schema| {
+        schema.insert("required".to_string(), json!([key]));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use azure_pipelines_rs_derive::Parameterized;
+    use serde::Deserialize;
+
+    use crate::core::v1::step::Step;
+
+    use super::*;
+
+    #[test]
+    fn pipelines_reject_unknown_keys() {
+        let schema = pipeline().to_value();
+        assert_eq!(schema["$schema"], "http://json-schema.org/draft-07/schema#");
+        assert_eq!(schema["additionalProperties"], false);
+        assert_eq!(schema["required"], json!(["extends"]));
+        assert_eq!(
+            schema.pointer("/definitions/Extends/properties/parameters/type"),
+            Some(&json!("object"))
+        );
+    }
+
+    #[test]
+    fn steps_require_the_key_that_picks_their_form() {
+        let schema = schema_for::<Step>().to_value();
+        let required: Vec<&Value> = schema["anyOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|variant| &variant["required"][0])
+            .collect();
+        assert_eq!(required, ["checkout", "task", "template"]);
+        assert_eq!(
+            schema["definitions"]["TaskStep"]["additionalProperties"],
+            false
+        );
+    }
+
+    #[derive(Deserialize, JsonSchema, Parameterized)]
+    #[parameterized(template = "build.yml")]
+    #[serde(rename_all = "camelCase")]
+    #[allow(dead_code)]
+    struct BuildParameters {
+        pool_name: String,
+        retries: Option<u32>,
+    }
+
+    #[test]
+    fn typed_extends_parameters() {
+        let schema = pipeline_for::<BuildParameters>().to_value();
+        let parameters = &schema["definitions"]["BuildParameters"];
+        assert_eq!(parameters["required"], json!(["poolName"]));
+        assert!(parameters["properties"]["retries"].is_object());
+        let extends = schema["definitions"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .find(|(name, _)| name.starts_with("Extends"))
+            .map(|(_, extends)| extends)
+            .unwrap();
+        assert_eq!(
+            extends["properties"]["parameters"]["allOf"],
+            json!([{ "$ref": "#/definitions/BuildParameters" }])
+        );
+    }
+}
@@ -2,58 +2,94 @@
 //!
 //! <https://learn.microsoft.com/en-us/azure/devops/pipelines/yaml-schema/stages?view=azure-pipelines>
 
-use std::collections::HashMap;
+use indexmap::IndexMap;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_yaml::{Mapping, Value};
 
-use serde::{Deserialize, Serialize};
-use serde_yaml::Value;
-
-use crate::core::v1::{depends::DependsOn, job::Job};
+#[cfg(feature = "schema")]
+use crate::core::v1::schema;
+use crate::core::v1::{
+    depends::DependsOn,
+    job::Job,
+    tagged::{buffered, mapping, variant},
+    unknown,
+};
 
 /// Stages
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, PartialEq, Debug)]
 #[serde(untagged)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum Stage {
     /// Stages are a collection of related jobs
     Stage(StageWithJobs),
     /// You can define a set of stages in one file and use it multiple times in other files
+    #[cfg_attr(feature = "schema", schemars(transform = schema::requires("template")))]
     Template(StageWithTemplate),
 }
 
+impl<'de> Deserialize<'de> for Stage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        buffered(deserializer, |value| {
+            if mapping(&value, "stage")?.contains_key("template") {
+                variant(value, "stage template").map(Stage::Template)
+            } else {
+                variant(value, "stage").map(Stage::Stage)
+            }
+        })
+    }
+}
+
 /// Stages are a collection of related jobs. By default, stages run
 /// sequentially. Each stage starts only after the preceding stage is complete
 /// unless otherwise specified via the `dependsOn` property.
 ///
 /// <https://learn.microsoft.com/en-us/azure/devops/pipelines/yaml-schema/stages-stage?view=azure-pipelines>
 #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
-#[serde(deny_unknown_fields)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(deny_unknown_fields)
+)]
 pub struct StageWithJobs {
     /// ID of the stage
-    #[serde(rename = "stage")]
+    #[serde(rename = "stage", skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
 
     /// Human-readable name for the stage
-    #[serde(rename = "displayName")]
+    #[serde(rename = "displayName", skip_serializing_if = "Option::is_none")]
     pub display_name: Option<String>,
 
     /// Any stages which must complete before this one. By default stages are
     /// run sequentially in the order defined in the pipeline. Specify
     /// `dependsOn: []` for a stage if it shouldn't depend on the previous stage
     /// in the pipeline.
-    #[serde(rename = "dependsOn")]
+    #[serde(rename = "dependsOn", skip_serializing_if = "Option::is_none")]
     pub depends_on: Option<DependsOn>,
 
     /// Evaluate this condition expression to determine whether to run this stage
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub condition: Option<String>,
 
     /// Pool where jobs in this stage will run unless otherwise specified
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub pool: Option<String>,
 
     /// Stage-specific variables
-    #[serde(default)]
-    pub variables: HashMap<String, Value>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "IndexMap<String, serde_json::Value>")
+    )]
+    pub variables: IndexMap<String, Value>,
 
     /// Jobs which make up the stage
     pub jobs: Vec<Job>,
+
+    /// Keys this crate doesn't know, which are rejected unless parsed with
+    /// [`parse::from_str_lenient`](crate::source::parse::from_str_lenient)
+    #[serde(flatten, deserialize_with = "unknown::deserialize")]
+    #[cfg_attr(feature = "schema", schemars(skip))]
+    pub unknown: Mapping,
 }
 
 /// You can define a set of stages in one file and use it multiple times in
@@ -61,12 +97,27 @@ pub struct StageWithJobs {
 ///
 /// <https://learn.microsoft.com/en-us/azure/devops/pipelines/yaml-schema/stages-template?view=azure-pipelines>
 #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
-#[serde(deny_unknown_fields)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(deny_unknown_fields)
+)]
 pub struct StageWithTemplate {
     /// Reference to a template for this stage
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub template: Option<String>,
 
     /// Parameters used in a stage template
-    #[serde(default)]
-    pub parameters: HashMap<String, Value>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "IndexMap<String, serde_json::Value>")
+    )]
+    pub parameters: IndexMap<String, Value>,
+
+    /// Keys this crate doesn't know, which are rejected unless parsed with
+    /// [`parse::from_str_lenient`](crate::source::parse::from_str_lenient)
+    #[serde(flatten, deserialize_with = "unknown::deserialize")]
+    #[cfg_attr(feature = "schema", schemars(skip))]
+    pub unknown: Mapping,
 }
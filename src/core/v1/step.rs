@@ -2,71 +2,133 @@
 //!
 //! <https://learn.microsoft.com/en-us/azure/devops/pipelines/yaml-schema/steps?view=azure-pipelines>
 
-use std::collections::HashMap;
+use indexmap::IndexMap;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_yaml::{Mapping, Value};
 
-use serde::{Deserialize, Serialize};
-use serde_yaml::Value;
+#[cfg(feature = "schema")]
+use crate::core::v1::schema;
+use crate::core::v1::{
+    tagged::{buffered, first_key, mapping, variant},
+    unknown,
+};
 
 /// Steps are a linear sequence of operations that make up a job
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, PartialEq, Debug)]
 #[serde(untagged)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[allow(clippy::large_enum_variant)]
 pub enum Step {
     /// Configure how the pipeline checks out source code
+    #[cfg_attr(feature = "schema", schemars(transform = schema::requires("checkout")))]
     Checkout(CheckoutStep),
     /// Runs a task
+    #[cfg_attr(feature = "schema", schemars(transform = schema::requires("task")))]
     Step(TaskStep),
     /// Define a set of steps in one file and use it multiple times in another
     /// file
+    #[cfg_attr(feature = "schema", schemars(transform = schema::requires("template")))]
     Template(TemplateStep),
 }
 
+impl<'de> Deserialize<'de> for Step {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        buffered(deserializer, |value| {
+            let map = mapping(&value, "step")?;
+            if map.contains_key("template") {
+                variant(value, "step template").map(Step::Template)
+            } else if map.contains_key("task") {
+                variant(value, "task step").map(Step::Step)
+            } else if map.contains_key("checkout") {
+                variant(value, "checkout step").map(Step::Checkout)
+            } else {
+                Err(format!(
+                    "unsupported step{}; expected `checkout`, `task` or `template`",
+                    first_key(map)
+                        .map(|key| format!(" `{key}`"))
+                        .unwrap_or_default()
+                ))
+            }
+        })
+    }
+}
+
 /// Use `checkout` to configure how the pipeline checks out source code
 ///
 /// <https://learn.microsoft.com/en-us/azure/devops/pipelines/yaml-schema/steps-checkout?view=azure-pipelines>
 #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
-#[serde(deny_unknown_fields)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(deny_unknown_fields)
+)]
 #[serde(rename_all = "camelCase")]
 pub struct CheckoutStep {
     /// Configures checkout for the specified repository
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub checkout: Option<String>,
 
     /// Set to `'true'` to leave the OAuth token in the Git config after the
     /// initial fetch. The default is not to leave it.
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
     pub persist_credentials: bool,
+
+    /// Keys this crate doesn't know, which are rejected unless parsed with
+    /// [`parse::from_str_lenient`](crate::source::parse::from_str_lenient)
+    #[serde(flatten, deserialize_with = "unknown::deserialize")]
+    #[cfg_attr(feature = "schema", schemars(skip))]
+    pub unknown: Mapping,
 }
 
 /// A `task` step runs a task
 ///
 /// <https://learn.microsoft.com/en-us/azure/devops/pipelines/yaml-schema/steps-task?view=azure-pipelines>
 #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
-#[serde(deny_unknown_fields)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(deny_unknown_fields)
+)]
 #[serde(rename_all = "camelCase")]
 pub struct TaskStep {
-    /// Evaluate this condition expression to determine whether to run this task
-    pub condition: Option<String>,
+    /// Name of the task to run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task: Option<String>,
 
     /// Human-readable name for the task
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub display_name: Option<String>,
 
-    /// Variables to map into the process's environment
-    #[serde(default)]
-    pub env: HashMap<String, String>,
-
-    /// Inputs for the task
-    pub inputs: HashMap<String, String>,
-
     /// ID of the step
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
 
+    /// Evaluate this condition expression to determine whether to run this task
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub condition: Option<String>,
+
     /// Number of retries if the task fails
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<serde_json::Value>"))]
     pub retry_count_on_task_failure: Option<Value>,
 
     /// Environment in which to run this task
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub target: Option<StepTarget>,
 
-    /// Name of the task to run
-    pub task: Option<String>,
+    /// Variables to map into the process's environment
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub env: IndexMap<String, String>,
+
+    /// Inputs for the task
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub inputs: IndexMap<String, String>,
+
+    /// Keys this crate doesn't know, which are rejected unless parsed with
+    /// [`parse::from_str_lenient`](crate::source::parse::from_str_lenient)
+    #[serde(flatten, deserialize_with = "unknown::deserialize")]
+    #[cfg_attr(feature = "schema", schemars(skip))]
+    pub unknown: Mapping,
 }
 
 /// Tasks run in an execution context, which is either the agent host or a
@@ -74,22 +136,112 @@ pub struct TaskStep {
 ///
 /// <https://learn.microsoft.com/en-us/azure/devops/pipelines/yaml-schema/target?view=azure-pipelines>
 #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
-#[serde(deny_unknown_fields)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(deny_unknown_fields)
+)]
 pub struct StepTarget {
     /// Container to target (or `'host'` for host machine)
     pub container: String,
+
+    /// Keys this crate doesn't know, which are rejected unless parsed with
+    /// [`parse::from_str_lenient`](crate::source::parse::from_str_lenient)
+    #[serde(flatten, deserialize_with = "unknown::deserialize")]
+    #[cfg_attr(feature = "schema", schemars(skip))]
+    pub unknown: Mapping,
 }
 
 /// Define a set of steps in one file and use it multiple times in another file
 ///
 /// <https://learn.microsoft.com/en-us/azure/devops/pipelines/yaml-schema/steps-template?view=azure-pipelines>
 #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
-#[serde(deny_unknown_fields)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(deny_unknown_fields)
+)]
 pub struct TemplateStep {
     /// Reference to a template for this step
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub template: Option<String>,
 
     /// Parameters used in a step template
-    #[serde(default)]
-    pub parameters: HashMap<String, Value>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "IndexMap<String, serde_json::Value>")
+    )]
+    pub parameters: IndexMap<String, Value>,
+
+    /// Keys this crate doesn't know, which are rejected unless parsed with
+    /// [`parse::from_str_lenient`](crate::source::parse::from_str_lenient)
+    #[serde(flatten, deserialize_with = "unknown::deserialize")]
+    #[cfg_attr(feature = "schema", schemars(skip))]
+    pub unknown: Mapping,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fmt::order::NodeKind;
+
+    use super::*;
+
+    #[test]
+    fn inputs_and_env_keep_their_order() {
+        let source = "\
+task: Bash@3
+inputs:
+  targetType: inline
+  script: echo
+  failOnStderr: 'true'
+env:
+  Z_LAST: z
+  A_FIRST: a
+";
+        let Step::Step(task) = serde_yaml::from_str(source).unwrap() else {
+            unreachable!()
+        };
+        let inputs: Vec<_> = task.inputs.keys().collect();
+        assert_eq!(inputs, ["targetType", "script", "failOnStderr"]);
+        let env: Vec<_> = task.env.keys().collect();
+        assert_eq!(env, ["Z_LAST", "A_FIRST"]);
+    }
+
+    #[test]
+    fn task_keys_follow_the_canonical_order() {
+        let task = TaskStep {
+            task: Some("Bash@3".to_string()),
+            display_name: Some("Build".to_string()),
+            condition: Some("succeeded()".to_string()),
+            target: Some(StepTarget {
+                container: "linux".to_string(),
+                unknown: Mapping::new(),
+            }),
+            env: IndexMap::from([("CI".to_string(), "true".to_string())]),
+            inputs: IndexMap::from([("script".to_string(), "echo".to_string())]),
+            ..TaskStep::default()
+        };
+        let written = serde_yaml::to_value(Step::Step(task)).unwrap();
+        let keys: Vec<_> = written
+            .as_mapping()
+            .unwrap()
+            .keys()
+            .map(|key| key.as_str().unwrap())
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                "task",
+                "displayName",
+                "condition",
+                "target",
+                "env",
+                "inputs"
+            ]
+        );
+        let order = NodeKind::Step.key_order();
+        let canonical: Vec<_> = order.iter().filter(|key| keys.contains(key)).collect();
+        assert_eq!(keys.iter().collect::<Vec<_>>(), canonical);
+    }
 }
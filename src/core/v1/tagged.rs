@@ -0,0 +1,161 @@
+//! Deserialize enums by the key that distinguishes their variants
+//!
+//! `#[serde(untagged)]` tries every variant and, when none match, only reports
+//! "data did not match any variant". These helpers let an enum pick its variant
+//! up front (e.g. by a `template:` key) so the error names what's actually
+//! wrong, such as the unknown field.
+
+use std::{fmt, marker::PhantomData};
+
+use serde::{
+    Deserializer,
+    de::{DeserializeOwned, Error, MapAccess, SeqAccess, Visitor},
+};
+use serde_yaml::{Mapping, Value};
+
+/// Read a whole value and let `choose` pick its variant
+///
+/// `choose` runs while the value is being read, so `serde_yaml` reports its
+/// errors at the value's own position rather than at the collection it's in.
+pub(crate) fn buffered<'de, T, D>(
+    deserializer: D,
+    choose: impl FnOnce(Value) -> Result<T, String>,
+) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_any(Buffered(choose, PhantomData))
+}
+
+struct Buffered<F, T>(F, PhantomData<T>);
+
+impl<F, T> Buffered<F, T>
+where
+    F: FnOnce(Value) -> Result<T, String>,
+{
+    fn choose<E: Error>(self, value: Value) -> Result<T, E> {
+        (self.0)(value).map_err(E::custom)
+    }
+}
+
+impl<'de, F, T> Visitor<'de> for Buffered<F, T>
+where
+    F: FnOnce(Value) -> Result<T, String>,
+{
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a YAML value")
+    }
+
+    fn visit_bool<E: Error>(self, v: bool) -> Result<T, E> {
+        self.choose(Value::Bool(v))
+    }
+
+    fn visit_i64<E: Error>(self, v: i64) -> Result<T, E> {
+        self.choose(Value::Number(v.into()))
+    }
+
+    fn visit_u64<E: Error>(self, v: u64) -> Result<T, E> {
+        self.choose(Value::Number(v.into()))
+    }
+
+    fn visit_f64<E: Error>(self, v: f64) -> Result<T, E> {
+        self.choose(Value::Number(v.into()))
+    }
+
+    fn visit_str<E: Error>(self, v: &str) -> Result<T, E> {
+        self.choose(Value::String(v.to_string()))
+    }
+
+    fn visit_string<E: Error>(self, v: String) -> Result<T, E> {
+        self.choose(Value::String(v))
+    }
+
+    fn visit_unit<E: Error>(self) -> Result<T, E> {
+        self.choose(Value::Null)
+    }
+
+    fn visit_none<E: Error>(self) -> Result<T, E> {
+        self.choose(Value::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<T, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<T, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        self.choose(Value::Sequence(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<T, A::Error> {
+        let mut entries = Mapping::new();
+        while let Some((key, value)) = map.next_entry()? {
+            entries.insert(key, value);
+        }
+        self.choose(Value::Mapping(entries))
+    }
+}
+
+/// The mapping `value` is, or an error saying what `kind` of mapping was
+/// expected
+pub(crate) fn mapping<'a>(value: &'a Value, kind: &str) -> Result<&'a Mapping, String> {
+    match value {
+        Value::Mapping(map) => Ok(map),
+        other => Err(format!(
+            "expected a {kind} mapping, found {}",
+            type_name(other)
+        )),
+    }
+}
+
+/// The first key of `map`, for naming unsupported forms in errors
+pub(crate) fn first_key(map: &Mapping) -> Option<&str> {
+    map.keys().next().and_then(Value::as_str)
+}
+
+/// Deserialize the chosen variant, prefixing errors with the `kind` of thing
+/// it is
+///
+/// The variant is read from YAML text rather than from `value` so that errors
+/// in it keep `serde_yaml`'s path to where they are, e.g.
+/// `stage: jobs[0]: job: steps[1]: ...`, which
+/// [`parse::from_str`](crate::source::parse::from_str) follows to report the
+/// innermost node.
+pub(crate) fn variant<T: DeserializeOwned>(value: Value, kind: &str) -> Result<T, String> {
+    let text = serde_yaml::to_string(&value).map_err(|e| e.to_string())?;
+    serde_yaml::from_str(&text).map_err(|e| format!("{kind}: {}", message(&e)))
+}
+
+/// An error's message without the location `serde_yaml` adds to it
+pub(crate) fn message(error: &serde_yaml::Error) -> String {
+    let message = error.to_string();
+    // the location isn't shown when it's the start of the text
+    match error.location() {
+        Some(location) => {
+            let suffix = format!(" at line {} column {}", location.line(), location.column());
+            message
+                .strip_suffix(&suffix)
+                .unwrap_or(&message)
+                .to_string()
+        }
+        None => message,
+    }
+}
+
+/// Human-readable name of a YAML value's type
+pub(crate) fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Sequence(_) => "a list",
+        Value::Mapping(_) => "a mapping",
+        Value::Tagged(_) => "a tagged value",
+    }
+}
@@ -2,58 +2,134 @@
 //! to run
 //!
 //! <https://learn.microsoft.com/en-us/azure/devops/pipelines/yaml-schema/trigger?view=azure-pipelines>
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_yaml::{Mapping, Value};
+
+use crate::core::v1::{
+    tagged::{buffered, type_name, variant},
+    unknown,
+};
 
 /// A push trigger specifies which branches cause a continuous integration build
 /// to run
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+///
+/// Each form is written back the way it was read. The shorthand list of
+/// branches to include (`trigger: [main]`) isn't supported.
+#[derive(Serialize, PartialEq, Debug)]
 #[serde(untagged)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum Trigger {
     /// Disable CI triggers
     None(String),
     /// Full syntax for complete control
-    Trigger(PipelineTrigger),
+    Trigger(Box<PipelineTrigger>),
+}
+
+impl<'de> Deserialize<'de> for Trigger {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        buffered(deserializer, |value| match value {
+            Value::String(none) => Ok(Trigger::None(none)),
+            value @ Value::Mapping(_) => variant(value, "trigger").map(Trigger::Trigger),
+            other => Err(format!(
+                "trigger: expected `none` or a mapping, found {}",
+                type_name(&other)
+            )),
+        })
+    }
 }
 
 /// Use the full syntax control for full control over the CI trigger
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
-#[serde(deny_unknown_fields)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(deny_unknown_fields)
+)]
 pub struct PipelineTrigger {
     /// Branch names to include or exclude for triggering a run
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub branches: Option<TriggerItem>,
 
     /// File paths to include or exclude for triggering a run
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub paths: Option<TriggerItem>,
+
+    /// Keys this crate doesn't know, which are rejected unless parsed with
+    /// [`parse::from_str_lenient`](crate::source::parse::from_str_lenient)
+    #[serde(flatten, deserialize_with = "unknown::deserialize")]
+    #[cfg_attr(feature = "schema", schemars(skip))]
+    pub unknown: Mapping,
 }
 
 /// Lists of items to include or exclude for trigger events
 #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
-#[serde(deny_unknown_fields)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(deny_unknown_fields)
+)]
 pub struct TriggerItem {
     /// List of items to include
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub include: Vec<String>,
 
     /// List of items to exclude
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub exclude: Vec<String>,
+
+    /// Keys this crate doesn't know, which are rejected unless parsed with
+    /// [`parse::from_str_lenient`](crate::source::parse::from_str_lenient)
+    #[serde(flatten, deserialize_with = "unknown::deserialize")]
+    #[cfg_attr(feature = "schema", schemars(skip))]
+    pub unknown: Mapping,
 }
 
 /// Specify `none` to disable, `true` to include all branches, or use the full
-/// syntax
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+/// syntax, which is written back the way it was read
+#[derive(Serialize, PartialEq, Debug)]
 #[serde(untagged)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum ResourceTrigger {
+    /// `none` to disable
     Simple(String),
-    Full(PipelineResourceTrigger),
+    /// `true` to include all branches
+    All(bool),
+    /// Full syntax for complete control
+    Full(Box<PipelineResourceTrigger>),
+}
+
+impl<'de> Deserialize<'de> for ResourceTrigger {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        buffered(deserializer, |value| match value {
+            Value::String(simple) => Ok(ResourceTrigger::Simple(simple)),
+            Value::Bool(all) => Ok(ResourceTrigger::All(all)),
+            value @ Value::Mapping(_) => variant(value, "trigger").map(ResourceTrigger::Full),
+            other => Err(format!(
+                "trigger: expected `none`, `true` or a mapping, found {}",
+                type_name(&other)
+            )),
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
-#[serde(deny_unknown_fields)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(deny_unknown_fields)
+)]
 pub struct PipelineResourceTrigger {
     /// Branches to include or exclude for triggering a run
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub branches: Option<TriggerItem>,
 
     /// List of tags that when matched will trigger the pipeline
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub tags: Option<TriggerItem>,
+
+    /// Keys this crate doesn't know, which are rejected unless parsed with
+    /// [`parse::from_str_lenient`](crate::source::parse::from_str_lenient)
+    #[serde(flatten, deserialize_with = "unknown::deserialize")]
+    #[cfg_attr(feature = "schema", schemars(skip))]
+    pub unknown: Mapping,
 }
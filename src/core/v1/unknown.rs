@@ -0,0 +1,76 @@
+//! Keys that aren't part of this crate's schema
+//!
+//! Every node rejects keys it doesn't know, so typos are caught by plain
+//! `serde_yaml::from_str` as well as
+//! [`parse::from_str`](crate::source::parse::from_str).
+//! [`parse::from_str_lenient`](crate::source::parse::from_str_lenient) keeps
+//! them in the node's `unknown` map instead, which lets pipelines that use
+//! keywords newer than this crate parse, validate and serialize back out
+//! without losing anything.
+
+use std::cell::Cell;
+
+use serde::{Deserialize, Deserializer, de::Error};
+use serde_yaml::Mapping;
+
+thread_local! {
+    static LENIENT: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Run `f`, keeping unknown keys instead of rejecting them for anything it
+/// deserializes. Only lenient parsing calls this, so the plain serde impls
+/// stay strict.
+pub(crate) fn lenient<T>(f: impl FnOnce() -> T) -> T {
+    struct Restore(bool);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            LENIENT.with(|lenient| lenient.set(self.0));
+        }
+    }
+
+    let _restore = Restore(LENIENT.with(|lenient| lenient.replace(true)));
+    f()
+}
+
+/// Collect the keys a node didn't recognise, rejecting them unless lenient
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Mapping, D::Error> {
+    let unknown = Mapping::deserialize(deserializer)?;
+    match unknown.keys().next() {
+        Some(key) if !LENIENT.with(Cell::get) => Err(D::Error::custom(format!(
+            "unknown field `{}`",
+            serde_yaml::to_string(key).unwrap_or_default().trim_end()
+        ))),
+        _ => Ok(unknown),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::v1::{job::Job, pipeline::Pipeline};
+
+    use super::*;
+
+    const JOB: &str = "job: a\nconditon: succeeded()\nstepz: []\n";
+
+    #[test]
+    fn strict_by_default() {
+        let error = serde_yaml::from_str::<Job>(JOB).unwrap_err().to_string();
+        assert!(error.contains("unknown field `conditon`"), "{error}");
+
+        let pipeline = "extends:\n  template: a.yml\n  parameters: {}\nlockBehavior: sequential\n";
+        assert!(serde_yaml::from_str::<Pipeline>(pipeline).is_err());
+    }
+
+    #[test]
+    fn lenient_keeps_unknown_keys() {
+        let Job::Job(job) = lenient(|| serde_yaml::from_str::<Job>(JOB)).unwrap() else {
+            unreachable!()
+        };
+        assert_eq!(job.unknown.len(), 2);
+        assert!(serde_yaml::to_string(&job).unwrap().contains("stepz: []"));
+
+        // the flag is restored afterwards
+        assert!(serde_yaml::from_str::<Job>(JOB).is_err());
+    }
+}
@@ -0,0 +1,96 @@
+//! The syntax tree produced by [`parse`](crate::expressions::parser::parse)
+
+use std::ops::Range;
+
+/// A parsed expression along with its byte range in the source text
+#[derive(PartialEq, Debug, Clone)]
+pub struct Expr {
+    /// What kind of expression this is
+    pub kind: ExprKind,
+
+    /// Byte offsets of this expression within the parsed text
+    pub span: Range<usize>,
+}
+
+/// The different kinds of expressions
+#[derive(PartialEq, Debug, Clone)]
+pub enum ExprKind {
+    /// A literal value, e.g. `'main'`, `42` or `true`
+    Literal(Literal),
+
+    /// A named value at the root of a property chain, e.g. `variables`
+    NamedValue(String),
+
+    /// Property dereference, e.g. `variables.foo` or `dependencies.*.result`
+    Property {
+        /// The expression being dereferenced
+        target: Box<Expr>,
+        /// The property name (`*` for a filtered array)
+        name: String,
+    },
+
+    /// Index dereference, e.g. `variables['foo']`
+    Index {
+        /// The expression being indexed
+        target: Box<Expr>,
+        /// The index expression
+        index: Box<Expr>,
+    },
+
+    /// A function call, e.g. `eq(variables.foo, 'bar')`
+    Call {
+        /// The function name as written
+        name: String,
+        /// Byte offsets of the function name
+        name_span: Range<usize>,
+        /// The arguments passed to the function
+        args: Vec<Expr>,
+    },
+}
+
+/// Literal values
+#[derive(PartialEq, Debug, Clone)]
+pub enum Literal {
+    /// `null`
+    Null,
+    /// `true` or `false`
+    Boolean(bool),
+    /// A number such as `1` or `-2.5`
+    Number(f64),
+    /// A version number with two to four parts, e.g. `1.2.3`
+    Version(String),
+    /// A single-quoted string; `''` inside the quotes is a literal `'`
+    String(String),
+}
+
+impl Expr {
+    /// Visit this expression and every expression nested inside it
+    pub fn walk<'a>(&'a self, visit: &mut impl FnMut(&'a Expr)) {
+        visit(self);
+        match &self.kind {
+            ExprKind::Literal(_) | ExprKind::NamedValue(_) => {}
+            ExprKind::Property { target, .. } => target.walk(visit),
+            ExprKind::Index { target, index } => {
+                target.walk(visit);
+                index.walk(visit);
+            }
+            ExprKind::Call { args, .. } => {
+                for arg in args {
+                    arg.walk(visit);
+                }
+            }
+        }
+    }
+
+    /// The named value at the root of a property chain, e.g. `variables` for
+    /// `variables['foo']`
+    pub fn root_named_value(&self) -> Option<&str> {
+        match &self.kind {
+            ExprKind::NamedValue(name) => Some(name),
+            ExprKind::Property { target, .. } | ExprKind::Index { target, .. } => {
+                target.root_named_value()
+            }
+            _ => None,
+        }
+    }
+}
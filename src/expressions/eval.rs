@@ -0,0 +1,541 @@
+//! Evaluate a parsed [`Expr`]
+//!
+//! <https://learn.microsoft.com/en-us/azure/devops/pipelines/process/expressions?view=azure-pipelines#type-casting>
+
+use std::{cmp::Ordering, collections::BTreeMap, error::Error, fmt};
+
+use crate::expressions::{
+    ast::{Expr, ExprKind, Literal},
+    functions::{self, FunctionKind},
+};
+
+/// The result of evaluating an expression
+#[derive(PartialEq, Debug, Clone)]
+pub enum Value {
+    /// `null`
+    Null,
+    /// `true` or `false`
+    Boolean(bool),
+    /// A number
+    Number(f64),
+    /// A version number such as `1.2.3`
+    Version(String),
+    /// A string
+    String(String),
+    /// A list of values, e.g. the result of `split` or `dependencies.*`
+    Array(Vec<Value>),
+    /// A dictionary, e.g. `variables` or `dependencies`
+    Object(BTreeMap<String, Value>),
+}
+
+/// An expression that could not be evaluated
+#[derive(PartialEq, Debug, Clone)]
+pub struct EvalError {
+    /// What went wrong
+    pub message: String,
+
+    /// Byte offset within the expression where the problem was found
+    pub offset: usize,
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.offset + 1)
+    }
+}
+
+impl Error for EvalError {}
+
+/// Supplies the named values and job status an expression is evaluated
+/// against
+pub trait Context {
+    /// Look up a named value such as `variables` or `dependencies`
+    fn named_value(&self, name: &str) -> Option<Value>;
+
+    /// Evaluate a status function (`always`, `canceled`, `failed`,
+    /// `succeeded`, `succeededOrFailed`) with the given job or stage names
+    fn status(&self, function: &str, names: &[String]) -> Result<bool, String>;
+}
+
+impl Value {
+    /// Convert to a boolean the way Azure does for conditions
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Null => false,
+            Value::Boolean(b) => *b,
+            Value::Number(n) => *n != 0.0,
+            Value::String(s) => !s.is_empty(),
+            Value::Version(_) | Value::Array(_) | Value::Object(_) => true,
+        }
+    }
+
+    /// Convert to a string the way Azure does when formatting or comparing
+    pub fn to_display_string(&self) -> String {
+        match self {
+            Value::Null | Value::Array(_) | Value::Object(_) => String::new(),
+            Value::Boolean(true) => "True".to_string(),
+            Value::Boolean(false) => "False".to_string(),
+            Value::Number(n) => n.to_string(),
+            Value::Version(v) | Value::String(v) => v.clone(),
+        }
+    }
+
+    fn to_number(&self) -> Option<f64> {
+        match self {
+            Value::Null => Some(0.0),
+            Value::Boolean(b) => Some(if *b { 1.0 } else { 0.0 }),
+            Value::Number(n) => Some(*n),
+            Value::String(s) if s.trim().is_empty() => Some(0.0),
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    fn to_version(&self) -> Option<Vec<u64>> {
+        match self {
+            Value::Version(v) | Value::String(v) => {
+                let parts: Option<Vec<u64>> = v.split('.').map(|p| p.parse().ok()).collect();
+                parts.filter(|p| (2..=4).contains(&p.len()))
+            }
+            Value::Number(n) => Value::String(n.to_string()).to_version(),
+            _ => None,
+        }
+    }
+
+    /// Look up a property, ignoring case like Azure does
+    fn property(&self, name: &str) -> Value {
+        match self {
+            Value::Object(map) => map
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.clone())
+                .unwrap_or(Value::Null),
+            Value::Array(items) => {
+                Value::Array(items.iter().map(|item| item.property(name)).collect())
+            }
+            _ => Value::Null,
+        }
+    }
+
+    /// Compare `self` with `other`, converting `other` to the type of `self`
+    fn compare(&self, other: &Value) -> Option<Ordering> {
+        match self {
+            Value::Null => match other {
+                Value::Null => Some(Ordering::Equal),
+                _ => None,
+            },
+            Value::Boolean(b) => Some(b.cmp(&other.is_truthy())),
+            Value::Number(n) => n.partial_cmp(&other.to_number()?),
+            Value::Version(_) => Some(self.to_version()?.cmp(&other.to_version()?)),
+            Value::String(s) => {
+                let other = match other {
+                    Value::Array(_) | Value::Object(_) => return None,
+                    other => other.to_display_string(),
+                };
+                Some(s.to_lowercase().cmp(&other.to_lowercase()))
+            }
+            Value::Array(_) | Value::Object(_) => (self == other).then_some(Ordering::Equal),
+        }
+    }
+
+    fn equals(&self, other: &Value) -> bool {
+        self.compare(other) == Some(Ordering::Equal)
+    }
+}
+
+impl From<&serde_yaml::Value> for Value {
+    fn from(value: &serde_yaml::Value) -> Self {
+        match value {
+            serde_yaml::Value::Null => Value::Null,
+            serde_yaml::Value::Bool(b) => Value::Boolean(*b),
+            serde_yaml::Value::Number(n) => n
+                .as_f64()
+                .map(Value::Number)
+                .unwrap_or_else(|| Value::String(n.to_string())),
+            serde_yaml::Value::String(s) => Value::String(s.clone()),
+            serde_yaml::Value::Sequence(items) => {
+                Value::Array(items.iter().map(Value::from).collect())
+            }
+            serde_yaml::Value::Mapping(map) => Value::Object(
+                map.iter()
+                    .map(|(key, value)| (Value::from(key).to_display_string(), Value::from(value)))
+                    .collect(),
+            ),
+            serde_yaml::Value::Tagged(tagged) => Value::from(&tagged.value),
+        }
+    }
+}
+
+impl From<Value> for serde_yaml::Value {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Null => serde_yaml::Value::Null,
+            Value::Boolean(b) => serde_yaml::Value::Bool(b),
+            Value::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => {
+                serde_yaml::Value::Number((n as i64).into())
+            }
+            Value::Number(n) => serde_yaml::Value::Number(n.into()),
+            Value::Version(s) | Value::String(s) => serde_yaml::Value::String(s),
+            Value::Array(items) => {
+                serde_yaml::Value::Sequence(items.into_iter().map(Into::into).collect())
+            }
+            Value::Object(map) => serde_yaml::Value::Mapping(
+                map.into_iter()
+                    .map(|(key, value)| (serde_yaml::Value::String(key), value.into()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl From<&Literal> for Value {
+    fn from(literal: &Literal) -> Self {
+        match literal {
+            Literal::Null => Value::Null,
+            Literal::Boolean(b) => Value::Boolean(*b),
+            Literal::Number(n) => Value::Number(*n),
+            Literal::Version(v) => Value::Version(v.clone()),
+            Literal::String(s) => Value::String(s.clone()),
+        }
+    }
+}
+
+/// Evaluate an expression against a [`Context`]
+pub fn evaluate(expr: &Expr, context: &impl Context) -> Result<Value, EvalError> {
+    let error = |message: String| EvalError {
+        message,
+        offset: expr.span.start,
+    };
+
+    match &expr.kind {
+        ExprKind::Literal(literal) => Ok(literal.into()),
+        ExprKind::NamedValue(name) => context
+            .named_value(name)
+            .ok_or_else(|| error(format!("unknown named value `{name}`"))),
+        ExprKind::Property { target, name } => {
+            let target = evaluate(target, context)?;
+            if name == "*" {
+                Ok(match target {
+                    Value::Object(map) => Value::Array(map.into_values().collect()),
+                    Value::Array(items) => Value::Array(items),
+                    _ => Value::Null,
+                })
+            } else {
+                Ok(target.property(name))
+            }
+        }
+        ExprKind::Index { target, index } => {
+            let target = evaluate(target, context)?;
+            let index = evaluate(index, context)?;
+            Ok(match (&target, &index) {
+                (Value::Array(items), index) => index
+                    .to_number()
+                    .filter(|i| *i >= 0.0)
+                    .and_then(|i| items.get(i as usize))
+                    .cloned()
+                    .unwrap_or(Value::Null),
+                (_, index) => target.property(&index.to_display_string()),
+            })
+        }
+        ExprKind::Call { name, args, .. } => {
+            let function = functions::lookup(name)
+                .ok_or_else(|| error(format!("unknown function `{name}`")))?;
+            if !function.accepts(args.len()) {
+                return Err(error(format!(
+                    "`{}` takes {} argument(s) but {} were given",
+                    function.name,
+                    function.arity(),
+                    args.len()
+                )));
+            }
+
+            if function.kind == FunctionKind::Status {
+                let names = args
+                    .iter()
+                    .map(|arg| evaluate(arg, context).map(|v| v.to_display_string()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                return context
+                    .status(function.name, &names)
+                    .map(Value::Boolean)
+                    .map_err(error);
+            }
+
+            call(function.name, args, context, error)
+        }
+    }
+}
+
+/// Call the function `name`. Errors in its arguments keep their own offsets;
+/// errors in the call itself are made with `error`.
+fn call(
+    name: &str,
+    args: &[Expr],
+    context: &impl Context,
+    error: impl Fn(String) -> EvalError,
+) -> Result<Value, EvalError> {
+    let arg = |i: usize| evaluate(&args[i], context);
+    let all = || -> Result<Vec<Value>, EvalError> { (0..args.len()).map(arg).collect() };
+
+    let value = match name {
+        // logical functions short-circuit
+        "and" => {
+            for i in 0..args.len() {
+                if !arg(i)?.is_truthy() {
+                    return Ok(Value::Boolean(false));
+                }
+            }
+            Value::Boolean(true)
+        }
+        "or" => {
+            for i in 0..args.len() {
+                if arg(i)?.is_truthy() {
+                    return Ok(Value::Boolean(true));
+                }
+            }
+            Value::Boolean(false)
+        }
+        "not" => Value::Boolean(!arg(0)?.is_truthy()),
+        "xor" => Value::Boolean(arg(0)?.is_truthy() != arg(1)?.is_truthy()),
+        "iif" => {
+            if arg(0)?.is_truthy() {
+                arg(1)?
+            } else {
+                arg(2)?
+            }
+        }
+        "coalesce" => {
+            for i in 0..args.len() {
+                let value = arg(i)?;
+                if value != Value::Null && value != Value::String(String::new()) {
+                    return Ok(value);
+                }
+            }
+            Value::Null
+        }
+
+        // comparison functions
+        "eq" => Value::Boolean(arg(0)?.equals(&arg(1)?)),
+        "ne" => Value::Boolean(!arg(0)?.equals(&arg(1)?)),
+        "gt" | "ge" | "lt" | "le" => {
+            let (left, right) = (arg(0)?, arg(1)?);
+            let ordering = left
+                .compare(&right)
+                .ok_or_else(|| error(format!("can't compare {left:?} with {right:?}")))?;
+            Value::Boolean(match name {
+                "gt" => ordering == Ordering::Greater,
+                "ge" => ordering != Ordering::Less,
+                "lt" => ordering == Ordering::Less,
+                _ => ordering != Ordering::Greater,
+            })
+        }
+        "in" | "notIn" => {
+            let values = all()?;
+            let found = values[1..].iter().any(|v| values[0].equals(v));
+            Value::Boolean(found == (name == "in"))
+        }
+
+        // string functions
+        "contains" | "startsWith" | "endsWith" => {
+            let haystack = arg(0)?.to_display_string().to_lowercase();
+            let needle = arg(1)?.to_display_string().to_lowercase();
+            Value::Boolean(match name {
+                "contains" => haystack.contains(&needle),
+                "startsWith" => haystack.starts_with(&needle),
+                _ => haystack.ends_with(&needle),
+            })
+        }
+        "containsValue" => {
+            let (collection, needle) = (arg(0)?, arg(1)?);
+            Value::Boolean(match collection {
+                Value::Array(items) => items.iter().any(|v| v.equals(&needle)),
+                Value::Object(map) => map.values().any(|v| v.equals(&needle)),
+                _ => false,
+            })
+        }
+        "format" => {
+            let values = all()?;
+            Value::String(
+                format_string(&values[0].to_display_string(), &values[1..]).map_err(error)?,
+            )
+        }
+        "join" => {
+            let (separator, collection) = (arg(0)?.to_display_string(), arg(1)?);
+            Value::String(match collection {
+                Value::Array(items) => items
+                    .iter()
+                    .map(Value::to_display_string)
+                    .collect::<Vec<_>>()
+                    .join(&separator),
+                other => other.to_display_string(),
+            })
+        }
+        "length" => Value::Number(match arg(0)? {
+            Value::String(s) => s.chars().count() as f64,
+            Value::Array(items) => items.len() as f64,
+            Value::Object(map) => map.len() as f64,
+            Value::Null => 0.0,
+            other => return Err(error(format!("`length` can't be applied to {other:?}"))),
+        }),
+        "lower" => Value::String(arg(0)?.to_display_string().to_lowercase()),
+        "upper" => Value::String(arg(0)?.to_display_string().to_uppercase()),
+        "trim" => Value::String(arg(0)?.to_display_string().trim().to_string()),
+        "replace" => {
+            let values = all()?;
+            let from = values[1].to_display_string();
+            let input = values[0].to_display_string();
+            Value::String(if from.is_empty() {
+                input
+            } else {
+                input.replace(&from, &values[2].to_display_string())
+            })
+        }
+        "split" => {
+            let (input, separator) = (arg(0)?.to_display_string(), arg(1)?.to_display_string());
+            Value::Array(
+                input
+                    .split(separator.as_str())
+                    .map(|s| Value::String(s.to_string()))
+                    .collect(),
+            )
+        }
+        other => return Err(error(format!("`{other}` can't be evaluated here"))),
+    };
+
+    Ok(value)
+}
+
+/// Replace `{0}`, `{1}`, ... with the given values; `{{` and `}}` escape braces
+fn format_string(template: &str, values: &[Value]) -> Result<String, String> {
+    let mut output = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                output.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                output.push('}');
+            }
+            '{' => {
+                let index: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                let value = index
+                    .trim()
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|i| values.get(i))
+                    .ok_or_else(|| format!("invalid format placeholder `{{{index}}}`"))?;
+                output.push_str(&value.to_display_string());
+            }
+            c => output.push(c),
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expressions::parser::parse;
+
+    /// Variables `os` and `list`, and every status function `true`
+    struct Variables;
+
+    impl Context for Variables {
+        fn named_value(&self, name: &str) -> Option<Value> {
+            (name == "variables").then(|| {
+                Value::Object(BTreeMap::from([
+                    ("os".to_string(), Value::String("Linux".to_string())),
+                    (
+                        "list".to_string(),
+                        Value::Array(vec![Value::Number(1.0), Value::Number(2.0)]),
+                    ),
+                ]))
+            })
+        }
+
+        fn status(&self, _function: &str, _names: &[String]) -> Result<bool, String> {
+            Ok(true)
+        }
+    }
+
+    fn eval(input: &str) -> Value {
+        evaluate(&parse(input).unwrap(), &Variables).unwrap()
+    }
+
+    #[test]
+    fn comparisons_convert_to_the_left_type() {
+        assert_eq!(eval("eq(variables.OS, 'linux')"), Value::Boolean(true));
+        assert_eq!(eval("eq(1, '1.0')"), Value::Boolean(true));
+        assert_eq!(eval("eq('1.0', 1)"), Value::Boolean(false));
+        assert_eq!(eval("eq(true, 'yes')"), Value::Boolean(true));
+        assert_eq!(eval("gt(1.10.0, '1.9')"), Value::Boolean(true));
+        assert_eq!(eval("lt('a', 'B')"), Value::Boolean(true));
+        assert_eq!(eval("in('b', 'a', 'B')"), Value::Boolean(true));
+        assert_eq!(eval("notIn('c', 'a', 'b')"), Value::Boolean(true));
+    }
+
+    #[test]
+    fn logical_functions_short_circuit() {
+        // `nope` would be an error if it were evaluated
+        assert_eq!(eval("and(false, nope.x)"), Value::Boolean(false));
+        assert_eq!(eval("or(true, nope.x)"), Value::Boolean(true));
+        assert_eq!(eval("coalesce('', null, 'x')"), Value::String("x".into()));
+        assert_eq!(eval("iif(eq(1, 2), 'a', 'b')"), Value::String("b".into()));
+    }
+
+    #[test]
+    fn string_functions() {
+        assert_eq!(
+            eval("format('{0}-{{1}}-{1}', 'a', 2)"),
+            Value::String("a-{1}-2".into())
+        );
+        assert_eq!(
+            eval("join(';', split('a,b', ','))"),
+            Value::String("a;b".into())
+        );
+        assert_eq!(eval("length(variables.list)"), Value::Number(2.0));
+        assert_eq!(
+            eval("startsWith('Refs/Heads', 'refs/')"),
+            Value::Boolean(true)
+        );
+        assert_eq!(
+            eval("replace('aXa', 'X', '-')"),
+            Value::String("a-a".into())
+        );
+        assert_eq!(
+            eval("containsValue(variables.list, 2)"),
+            Value::Boolean(true)
+        );
+    }
+
+    #[test]
+    fn indexes() {
+        assert_eq!(eval("variables.list[1]"), Value::Number(2.0));
+        assert_eq!(eval("variables.list[2]"), Value::Null);
+        assert_eq!(eval("variables.list[-1]"), Value::Null);
+        assert_eq!(eval("variables['OS']"), Value::String("Linux".into()));
+        assert_eq!(eval("variables.missing"), Value::Null);
+    }
+
+    #[test]
+    fn errors() {
+        let error = evaluate(&parse("eq(1, nope.x)").unwrap(), &Variables).unwrap_err();
+        assert_eq!(error.message, "unknown named value `nope`");
+        assert_eq!(error.offset, 6);
+        let error = evaluate(&parse("not(eq(lower(nope), 1))").unwrap(), &Variables).unwrap_err();
+        assert_eq!(error.offset, 13);
+        let error = evaluate(&parse("format('{2}', 1)").unwrap(), &Variables).unwrap_err();
+        assert!(error.message.contains("{2}"), "{error}");
+        assert_eq!(error.offset, 0);
+    }
+
+    #[test]
+    fn display_strings() {
+        assert_eq!(Value::Boolean(true).to_display_string(), "True");
+        assert_eq!(Value::Number(2.0).to_display_string(), "2");
+        assert!(!Value::String(String::new()).is_truthy());
+        assert!(Value::Array(Vec::new()).is_truthy());
+    }
+}
@@ -0,0 +1,104 @@
+//! Built-in expression functions
+//!
+//! <https://learn.microsoft.com/en-us/azure/devops/pipelines/process/expressions?view=azure-pipelines#functions>
+
+/// What a function does, which determines where it may be used
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum FunctionKind {
+    /// General purpose functions usable in any expression
+    General,
+    /// Job status check functions, only meaningful in a `condition`
+    Status,
+}
+
+/// Signature of a built-in function
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Function {
+    /// Function name, in the casing used by the documentation
+    pub name: &'static str,
+
+    /// Minimum number of arguments
+    pub min_args: usize,
+
+    /// Maximum number of arguments (`None` for unbounded)
+    pub max_args: Option<usize>,
+
+    /// What the function does
+    pub kind: FunctionKind,
+}
+
+impl Function {
+    const fn general(name: &'static str, min_args: usize, max_args: Option<usize>) -> Self {
+        Function {
+            name,
+            min_args,
+            max_args,
+            kind: FunctionKind::General,
+        }
+    }
+
+    const fn status(name: &'static str, max_args: Option<usize>) -> Self {
+        Function {
+            name,
+            min_args: 0,
+            max_args,
+            kind: FunctionKind::Status,
+        }
+    }
+
+    /// Whether `count` arguments are acceptable
+    pub fn accepts(&self, count: usize) -> bool {
+        count >= self.min_args && self.max_args.is_none_or(|max| count <= max)
+    }
+
+    /// Human-readable description of the accepted argument count
+    pub fn arity(&self) -> String {
+        match self.max_args {
+            Some(max) if max == self.min_args => format!("{max}"),
+            Some(max) => format!("{} to {max}", self.min_args),
+            None => format!("at least {}", self.min_args),
+        }
+    }
+}
+
+/// Every built-in function
+pub const FUNCTIONS: &[Function] = &[
+    Function::general("and", 2, None),
+    Function::general("coalesce", 2, None),
+    Function::general("contains", 2, Some(2)),
+    Function::general("containsValue", 2, Some(2)),
+    Function::general("convertToJson", 1, Some(1)),
+    Function::general("counter", 2, Some(2)),
+    Function::general("endsWith", 2, Some(2)),
+    Function::general("eq", 2, Some(2)),
+    Function::general("format", 1, None),
+    Function::general("ge", 2, Some(2)),
+    Function::general("gt", 2, Some(2)),
+    Function::general("iif", 3, Some(3)),
+    Function::general("in", 1, None),
+    Function::general("join", 2, Some(2)),
+    Function::general("le", 2, Some(2)),
+    Function::general("length", 1, Some(1)),
+    Function::general("lower", 1, Some(1)),
+    Function::general("lt", 2, Some(2)),
+    Function::general("ne", 2, Some(2)),
+    Function::general("not", 1, Some(1)),
+    Function::general("notIn", 1, None),
+    Function::general("or", 2, None),
+    Function::general("replace", 3, Some(3)),
+    Function::general("split", 2, Some(2)),
+    Function::general("startsWith", 2, Some(2)),
+    Function::general("trim", 1, Some(1)),
+    Function::general("upper", 1, Some(1)),
+    Function::general("xor", 2, Some(2)),
+    Function::status("always", Some(0)),
+    Function::status("canceled", Some(0)),
+    Function::status("failed", None),
+    Function::status("succeeded", None),
+    Function::status("succeededOrFailed", None),
+];
+
+/// Look up a built-in function by name (function names are case-insensitive)
+pub fn lookup(name: &str) -> Option<&'static Function> {
+    FUNCTIONS.iter().find(|f| f.name.eq_ignore_ascii_case(name))
+}
@@ -0,0 +1,12 @@
+//! Pipeline expressions
+//!
+//! Runtime conditions (`condition:`), template expressions (`${{ }}`) and
+//! runtime expressions (`$[ ]`) all share the same grammar of literals,
+//! named values with property access, and function calls.
+//!
+//! <https://learn.microsoft.com/en-us/azure/devops/pipelines/process/expressions?view=azure-pipelines>
+
+pub mod ast;
+pub mod eval;
+pub mod functions;
+pub mod parser;
@@ -0,0 +1,443 @@
+//! Parse an expression string into an [`Expr`]
+
+use std::{error::Error, fmt, iter::Peekable, ops::Range, str::CharIndices};
+
+use crate::expressions::ast::{Expr, ExprKind, Literal};
+
+/// An expression that could not be parsed
+#[derive(PartialEq, Debug, Clone)]
+pub struct ParseError {
+    /// What went wrong
+    pub message: String,
+
+    /// Byte offset within the expression where the problem was found
+    pub offset: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.offset + 1)
+    }
+}
+
+impl Error for ParseError {}
+
+/// Parse an expression, e.g. `and(succeeded(), eq(variables['x'], 'y'))`
+pub fn parse(input: &str) -> Result<Expr, ParseError> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        end: input.len(),
+    };
+    let expr = parser.expression()?;
+    if let Some(token) = parser.peek() {
+        return Err(ParseError {
+            message: format!("unexpected {} after expression", token.kind),
+            offset: token.span.start,
+        });
+    }
+    Ok(expr)
+}
+
+#[derive(PartialEq, Debug, Clone)]
+enum TokenKind {
+    OpenParen,
+    CloseParen,
+    OpenBracket,
+    CloseBracket,
+    Comma,
+    Dot,
+    Star,
+    Identifier(String),
+    Literal(Literal),
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenKind::OpenParen => write!(f, "`(`"),
+            TokenKind::CloseParen => write!(f, "`)`"),
+            TokenKind::OpenBracket => write!(f, "`[`"),
+            TokenKind::CloseBracket => write!(f, "`]`"),
+            TokenKind::Comma => write!(f, "`,`"),
+            TokenKind::Dot => write!(f, "`.`"),
+            TokenKind::Star => write!(f, "`*`"),
+            TokenKind::Identifier(name) => write!(f, "`{name}`"),
+            TokenKind::Literal(Literal::String(s)) => write!(f, "'{s}'"),
+            TokenKind::Literal(_) => write!(f, "literal"),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    span: Range<usize>,
+}
+
+/// Byte offset of the `}}` that ends a template expression, where `input`
+/// starts just after its `${{`. String literals may contain `}}`, so they're
+/// skipped over the way [`parse`] reads them.
+pub(crate) fn template_expression_end(input: &str) -> Option<usize> {
+    let mut chars = input.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\'' => {
+                string(&mut chars)?;
+            }
+            '}' if input[i..].starts_with("}}") => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Read a string literal whose opening quote was just read, returning its
+/// value and the offset just after its closing quote, or `None` if it isn't
+/// closed. A quote is escaped by doubling it.
+fn string(chars: &mut Peekable<CharIndices<'_>>) -> Option<(String, usize)> {
+    let mut value = String::new();
+    loop {
+        match chars.next()? {
+            (i, '\'') => {
+                if let Some(&(_, '\'')) = chars.peek() {
+                    chars.next();
+                    value.push('\'');
+                } else {
+                    return Some((value, i + 1));
+                }
+            }
+            (_, c) => value.push(c),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        let single = match c {
+            '(' => Some(TokenKind::OpenParen),
+            ')' => Some(TokenKind::CloseParen),
+            '[' => Some(TokenKind::OpenBracket),
+            ']' => Some(TokenKind::CloseBracket),
+            ',' => Some(TokenKind::Comma),
+            '.' => Some(TokenKind::Dot),
+            '*' => Some(TokenKind::Star),
+            _ => None,
+        };
+        if let Some(kind) = single {
+            chars.next();
+            tokens.push(Token {
+                kind,
+                span: start..start + 1,
+            });
+            continue;
+        }
+
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '\'' {
+            chars.next();
+            let (value, end) = string(&mut chars).ok_or_else(|| ParseError {
+                message: "unterminated string".to_string(),
+                offset: start,
+            })?;
+            tokens.push(Token {
+                kind: TokenKind::Literal(Literal::String(value)),
+                span: start..end,
+            });
+        } else if c.is_ascii_digit() || c == '-' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if c.is_ascii_digit() || c == '.' || (i == start && c == '-') {
+                    end = i + c.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            let text = &input[start..end];
+            tokens.push(Token {
+                kind: TokenKind::Literal(number(text, start)?),
+                span: start..end,
+            });
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if c.is_alphanumeric() || c == '_' || c == '-' {
+                    end = i + c.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            let text = &input[start..end];
+            let kind = if text.eq_ignore_ascii_case("true") {
+                TokenKind::Literal(Literal::Boolean(true))
+            } else if text.eq_ignore_ascii_case("false") {
+                TokenKind::Literal(Literal::Boolean(false))
+            } else if text.eq_ignore_ascii_case("null") {
+                TokenKind::Literal(Literal::Null)
+            } else {
+                TokenKind::Identifier(text.to_string())
+            };
+            tokens.push(Token {
+                kind,
+                span: start..end,
+            });
+        } else {
+            return Err(ParseError {
+                message: format!("unexpected character `{c}`"),
+                offset: start,
+            });
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn number(text: &str, offset: usize) -> Result<Literal, ParseError> {
+    let dots = text.matches('.').count();
+    if dots >= 2 && !text.starts_with('-') {
+        if dots <= 3 && text.split('.').all(|part| !part.is_empty()) {
+            return Ok(Literal::Version(text.to_string()));
+        }
+    } else if let Ok(n) = text.parse::<f64>() {
+        return Ok(Literal::Number(n));
+    }
+    Err(ParseError {
+        message: format!("invalid number `{text}`"),
+        offset,
+    })
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token, ParseError> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token.ok_or(ParseError {
+            message: "unexpected end of expression".to_string(),
+            offset: self.end,
+        })
+    }
+
+    fn expect(&mut self, kind: TokenKind, context: &str) -> Result<Token, ParseError> {
+        let token = self.next()?;
+        if token.kind == kind {
+            Ok(token)
+        } else {
+            Err(ParseError {
+                message: format!("expected {kind} {context}, found {}", token.kind),
+                offset: token.span.start,
+            })
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr, ParseError> {
+        let token = self.next()?;
+        let mut expr = match token.kind {
+            TokenKind::Literal(literal) => {
+                return Ok(Expr {
+                    kind: ExprKind::Literal(literal),
+                    span: token.span,
+                });
+            }
+            TokenKind::Identifier(name) => {
+                if self.peek().map(|t| &t.kind) == Some(&TokenKind::OpenParen) {
+                    self.call(name, token.span)?
+                } else {
+                    Expr {
+                        kind: ExprKind::NamedValue(name),
+                        span: token.span,
+                    }
+                }
+            }
+            other => {
+                return Err(ParseError {
+                    message: format!("expected an expression, found {other}"),
+                    offset: token.span.start,
+                });
+            }
+        };
+
+        loop {
+            match self.peek().map(|t| t.kind.clone()) {
+                Some(TokenKind::Dot) => {
+                    self.next()?;
+                    let property = self.next()?;
+                    let name = match property.kind {
+                        TokenKind::Identifier(name) => name,
+                        TokenKind::Star => "*".to_string(),
+                        other => {
+                            return Err(ParseError {
+                                message: format!("expected a property name, found {other}"),
+                                offset: property.span.start,
+                            });
+                        }
+                    };
+                    let span = expr.span.start..property.span.end;
+                    expr = Expr {
+                        kind: ExprKind::Property {
+                            target: Box::new(expr),
+                            name,
+                        },
+                        span,
+                    };
+                }
+                Some(TokenKind::OpenBracket) => {
+                    self.next()?;
+                    let index = self.expression()?;
+                    let close = self.expect(TokenKind::CloseBracket, "after index")?;
+                    let span = expr.span.start..close.span.end;
+                    expr = Expr {
+                        kind: ExprKind::Index {
+                            target: Box::new(expr),
+                            index: Box::new(index),
+                        },
+                        span,
+                    };
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    fn call(&mut self, name: String, name_span: Range<usize>) -> Result<Expr, ParseError> {
+        self.expect(TokenKind::OpenParen, "after function name")?;
+        let mut args = Vec::new();
+        let close = if self.peek().map(|t| &t.kind) == Some(&TokenKind::CloseParen) {
+            self.next()?
+        } else {
+            loop {
+                args.push(self.expression()?);
+                let token = self.next()?;
+                match token.kind {
+                    TokenKind::Comma => {}
+                    TokenKind::CloseParen => break token,
+                    other => {
+                        return Err(ParseError {
+                            message: format!(
+                                "expected `,` or `)` in arguments to `{name}`, found {other}"
+                            ),
+                            offset: token.span.start,
+                        });
+                    }
+                }
+            }
+        };
+        Ok(Expr {
+            span: name_span.start..close.span.end,
+            kind: ExprKind::Call {
+                name,
+                name_span,
+                args,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind(input: &str) -> ExprKind {
+        parse(input).unwrap().kind
+    }
+
+    fn named(name: &str) -> Box<Expr> {
+        match kind(name) {
+            kind @ ExprKind::NamedValue(_) => Box::new(Expr {
+                kind,
+                span: 0..name.len(),
+            }),
+            other => panic!("expected a named value, found {other:?}"),
+        }
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(kind("null"), ExprKind::Literal(Literal::Null));
+        assert_eq!(kind("True"), ExprKind::Literal(Literal::Boolean(true)));
+        assert_eq!(kind("-2.5"), ExprKind::Literal(Literal::Number(-2.5)));
+        assert_eq!(
+            kind("1.2.3"),
+            ExprKind::Literal(Literal::Version("1.2.3".to_string()))
+        );
+        assert_eq!(
+            kind("'it''s'"),
+            ExprKind::Literal(Literal::String("it's".to_string()))
+        );
+    }
+
+    #[test]
+    fn property_and_index() {
+        assert_eq!(
+            kind("variables.foo"),
+            ExprKind::Property {
+                target: named("variables"),
+                name: "foo".to_string(),
+            }
+        );
+        let ExprKind::Index { target, index } = kind("variables['foo']") else {
+            panic!("expected an index");
+        };
+        assert_eq!(target, named("variables"));
+        assert_eq!(index.kind, ExprKind::Literal(Literal::String("foo".into())));
+        assert_eq!(index.span, 10..15);
+    }
+
+    #[test]
+    fn filtered_array() {
+        let ExprKind::Property { target, name } = kind("dependencies.*.result") else {
+            panic!("expected a property");
+        };
+        assert_eq!(name, "result");
+        assert!(matches!(target.kind, ExprKind::Property { name, .. } if name == "*"));
+    }
+
+    #[test]
+    fn calls() {
+        let input = "and(succeeded(), eq(variables.x, 'y'))";
+        let expr = parse(input).unwrap();
+        let ExprKind::Call {
+            name,
+            name_span,
+            args,
+        } = &expr.kind
+        else {
+            panic!("expected a call");
+        };
+        assert_eq!(name, "and");
+        assert_eq!(name_span, &(0..3));
+        assert_eq!(args.len(), 2);
+        assert_eq!(expr.span, 0..input.len());
+        assert!(matches!(&args[0].kind, ExprKind::Call { args, .. } if args.is_empty()));
+    }
+
+    #[test]
+    fn errors() {
+        let error = parse("eq(variables['x'] 'y')").unwrap_err();
+        assert_eq!(error.offset, 18);
+        assert!(error.message.contains("expected `,` or `)`"), "{error}");
+
+        assert_eq!(parse("'open").unwrap_err().offset, 0);
+        assert_eq!(parse("eq(1,").unwrap_err().offset, 5);
+        assert_eq!(parse("a b").unwrap_err().offset, 2);
+        assert_eq!(parse("1.2.3.4.5").unwrap_err().offset, 0);
+        assert_eq!(parse("a.(").unwrap_err().offset, 2);
+        assert_eq!(parse("$x").unwrap_err().offset, 0);
+    }
+}
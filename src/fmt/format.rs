@@ -0,0 +1,553 @@
+//! Rewrite pipeline YAML in a canonical layout
+//!
+//! Keys are written in the order given by [`NodeKind::key_order`], nested
+//! nodes are indented by two spaces, list items start at the same column as
+//! their parent key, and scalars are only quoted when they need to be, with
+//! single quotes unless they contain characters that need escaping. Comments
+//! move with the entry or item they're written above or beside, and a single
+//! blank line is kept wherever there was at least one.
+//!
+//! Mappings that contain template expression directives (`${{ if }}` and the
+//! like) keep their order, since the meaning of a directive can depend on what
+//! comes before it.
+
+use std::error::Error;
+
+use serde_yaml::Value;
+
+use crate::{
+    fmt::order::{NodeKind, is_directive},
+    source::{
+        edit::escape_double_quoted,
+        map::{SourceMap, SourceNode},
+        text::{dash, line_end},
+    },
+};
+
+/// Format a pipeline or template file. Fails if the YAML is invalid, or if
+/// the formatted YAML wouldn't have the same content as the original.
+pub fn format(source: &str) -> Result<String, Box<dyn Error>> {
+    let map = SourceMap::new(source)?;
+    let mut writer = Writer {
+        source,
+        comments: comments(source, &map.root),
+        lines: Vec::new(),
+    };
+    match &map.root {
+        SourceNode::Mapping { entries, .. } if !entries.is_empty() => {
+            writer.mapping(entries, NodeKind::Pipeline, 0, 0)
+        }
+        node @ SourceNode::Sequence { items, .. } if !items.is_empty() => {
+            writer.sequence(node, NodeKind::Other, 0, 0)
+        }
+        SourceNode::Scalar { value, .. } if value.is_empty() => {}
+        node => {
+            let (first, rest) = writer.scalar(node);
+            writer.lines.push(first);
+            writer.lines.extend(rest);
+        }
+    }
+    let remaining: Vec<String> = writer.take_comments(0, source.len()).collect();
+    writer.lines.extend(remaining);
+
+    let mut formatted = writer.lines.join("\n");
+    formatted.push('\n');
+
+    let before: Value = serde_yaml::from_str(source)?;
+    let after: Value = serde_yaml::from_str(&formatted)
+        .map_err(|e| format!("formatting produced invalid YAML: {e}"))?;
+    if before != after {
+        return Err("formatting would change the content of the file".into());
+    }
+    Ok(formatted)
+}
+
+/// Check whether a file is already formatted. Returns the line number of the
+/// first line that formatting would change, if any.
+pub fn check(source: &str) -> Result<Option<usize>, Box<dyn Error>> {
+    let formatted = format(source)?;
+    if formatted == source {
+        return Ok(None);
+    }
+    let mut original = source.lines();
+    let mut formatted = formatted.lines();
+    let mut line = 1;
+    loop {
+        match (original.next(), formatted.next()) {
+            (Some(a), Some(b)) if a == b => line += 1,
+            _ => return Ok(Some(line)),
+        }
+    }
+}
+
+/// A comment and where it was found
+struct Comment {
+    offset: usize,
+    text: String,
+    /// Whether nothing but whitespace comes before it on its line
+    own_line: bool,
+    written: bool,
+}
+
+struct Writer<'a> {
+    source: &'a str,
+    comments: Vec<Comment>,
+    lines: Vec<String>,
+}
+
+impl Writer<'_> {
+    /// Write the entries of a mapping, whose comments start after `from`
+    fn mapping(
+        &mut self,
+        entries: &[(SourceNode, SourceNode)],
+        kind: NodeKind,
+        indent: usize,
+        from: usize,
+    ) {
+        // comments and blank lines above each entry belong to it, so they're
+        // found before any entry is moved
+        let mut leading = Vec::new();
+        let mut previous_end = None;
+        for (key, value) in entries {
+            let start = key.span().start.offset;
+            let from = previous_end.map_or(from, |end| line_end(self.source, end).min(start));
+            let blank = previous_end.is_some() && self.blank_line(from, start);
+            leading.push((blank, self.take_comments(from, start).collect::<Vec<_>>()));
+            previous_end = Some(value.span().end.offset);
+        }
+
+        let mut order: Vec<usize> = (0..entries.len()).collect();
+        let keys: Vec<String> = entries.iter().map(|(key, _)| scalar_value(key)).collect();
+        if !keys.iter().any(|key| is_directive(key)) {
+            let known = kind.key_order();
+            order.sort_by_key(|&i| {
+                known
+                    .iter()
+                    .position(|k| *k == keys[i])
+                    .unwrap_or(known.len())
+            });
+        }
+
+        // comments above the first entry are about the whole mapping, so they
+        // stay at the top, and if entries are separated by blank lines the
+        // first one is too when it moves down
+        let separated = leading.iter().any(|(blank, _)| *blank);
+        if let Some((blank, comments)) = leading.first_mut() {
+            *blank = separated;
+            let comments = std::mem::take(comments);
+            self.push_comments(comments, indent);
+        }
+
+        for (position, i) in order.into_iter().enumerate() {
+            let (blank, comments) = std::mem::take(&mut leading[i]);
+            if blank && position > 0 {
+                self.lines.push(String::new());
+            }
+            self.push_comments(comments, indent);
+            let (key, value) = &entries[i];
+            self.entry(key, &keys[i], value, kind, indent);
+        }
+    }
+
+    fn entry(
+        &mut self,
+        key: &SourceNode,
+        name: &str,
+        value: &SourceNode,
+        kind: NodeKind,
+        indent: usize,
+    ) {
+        let pad = " ".repeat(indent);
+        let (key_text, _) = self.scalar(key);
+        let properties = self.properties(value);
+        let properties = if properties.is_empty() {
+            String::new()
+        } else {
+            format!(" {properties}")
+        };
+
+        match value {
+            SourceNode::Mapping { entries, .. } if !entries.is_empty() => {
+                let line = format!("{pad}{key_text}:{properties}");
+                self.push_trailing(line, key.span().end.offset);
+                self.mapping(entries, kind.value(name), indent + 2, key.span().end.offset);
+            }
+            SourceNode::Sequence { items, .. } if !items.is_empty() => {
+                let line = format!("{pad}{key_text}:{properties}");
+                self.push_trailing(line, key.span().end.offset);
+                self.sequence(value, kind.items(name), indent, key.span().end.offset);
+            }
+            value => {
+                let (first, rest) = self.scalar(value);
+                let line = format!("{pad}{key_text}:{properties} {first}");
+                self.push_trailing(line, value.span().end.offset);
+                self.push_block(rest, indent + 2);
+            }
+        }
+    }
+
+    /// Write the items of a sequence, whose comments start after `from`
+    fn sequence(&mut self, sequence: &SourceNode, kind: NodeKind, indent: usize, from: usize) {
+        let SourceNode::Sequence { items, .. } = sequence else {
+            return;
+        };
+        let flow = self.source[sequence.span().start.offset..].starts_with('[');
+        let pad = " ".repeat(indent);
+        let mut previous_end = None;
+        for item in items {
+            let start = if flow {
+                item.span().start.offset
+            } else {
+                dash(self.source, item.span().start.offset)
+            };
+            let from = previous_end.map_or(from, |end| line_end(self.source, end).min(start));
+            if previous_end.is_some() && self.blank_line(from, start) {
+                self.lines.push(String::new());
+            }
+            let comments: Vec<String> = self.take_comments(from, start).collect();
+            self.push_comments(comments, indent);
+            previous_end = Some(item.span().end.offset);
+
+            let properties = self.properties(item);
+            let properties = if properties.is_empty() {
+                String::new()
+            } else {
+                format!("{properties} ")
+            };
+            match item {
+                SourceNode::Mapping { .. } | SourceNode::Sequence { .. } if !is_empty(item) => {
+                    // write the item as if it were indented under a key, then
+                    // put the dash on its first line
+                    let outer = std::mem::take(&mut self.lines);
+                    match item {
+                        SourceNode::Mapping { entries, .. } => {
+                            self.mapping(entries, kind, indent + 2, start)
+                        }
+                        SourceNode::Sequence { .. } => {
+                            self.sequence(item, NodeKind::Other, indent + 2, start)
+                        }
+                        _ => {}
+                    }
+                    let inner = std::mem::replace(&mut self.lines, outer);
+                    if !properties.is_empty() {
+                        // on the dash's line, they'd belong to the first key
+                        self.lines.push(format!("{pad}- {}", properties.trim_end()));
+                        self.lines.extend(inner);
+                        continue;
+                    }
+                    let first = inner
+                        .iter()
+                        .position(|line| !line.trim_start().starts_with('#'))
+                        .unwrap_or(0);
+                    for (i, line) in inner.into_iter().enumerate() {
+                        if i < first {
+                            self.lines.push(format!("{pad}{}", line.trim_start()));
+                        } else if i == first {
+                            let content = line.get(indent + 2..).unwrap_or(&line);
+                            self.lines.push(format!("{pad}- {properties}{content}"));
+                        } else {
+                            self.lines.push(line);
+                        }
+                    }
+                }
+                item => {
+                    let (first, rest) = self.scalar(item);
+                    let line = format!("{pad}- {properties}{first}");
+                    self.push_trailing(line, item.span().end.offset);
+                    self.push_block(rest, indent + 2);
+                }
+            }
+        }
+    }
+
+    /// A scalar, alias or empty collection as it should be written: its first
+    /// line, and any further lines of a block scalar without their indentation
+    fn scalar(&self, node: &SourceNode) -> (String, Vec<String>) {
+        let span = node.span();
+        let raw = &self.source[span.start.offset..span.end.offset];
+        match node {
+            SourceNode::Mapping { .. } => return ("{}".to_string(), Vec::new()),
+            SourceNode::Sequence { .. } => return ("[]".to_string(), Vec::new()),
+            SourceNode::Alias { .. } => return (raw.to_string(), Vec::new()),
+            SourceNode::Scalar { .. } => {}
+        }
+        let value = scalar_value(node);
+
+        match raw.chars().next() {
+            Some('|' | '>') => {
+                let mut lines = raw.lines();
+                let header = lines.next().unwrap_or_default().trim_end().to_string();
+                let content: Vec<&str> = lines.collect();
+                let strip = content
+                    .iter()
+                    .find(|line| !line.trim().is_empty())
+                    .map_or(0, |line| line.len() - line.trim_start().len());
+                let content = content
+                    .iter()
+                    .map(|line| line.get(strip..).unwrap_or("").trim_end().to_string())
+                    .collect();
+                (header, content)
+            }
+            Some('\'' | '"') => (quote(&value), Vec::new()),
+            _ if raw.contains('\n') => (quote(&value), Vec::new()),
+            _ => (raw.to_string(), Vec::new()),
+        }
+    }
+
+    /// Anchors and tags written just before a node, e.g. `&defaults`
+    fn properties(&self, node: &SourceNode) -> String {
+        let mut end = node.span().start.offset;
+        let mut properties = Vec::new();
+        loop {
+            let before = self.source[..end].trim_end();
+            let start = before
+                .rfind(|c: char| c.is_whitespace())
+                .map_or(0, |i| i + 1);
+            let token = &before[start..];
+            if token.len() > 1 && token.starts_with(['&', '!']) {
+                properties.push(token);
+                end = start;
+            } else {
+                break;
+            }
+        }
+        properties.reverse();
+        properties.join(" ")
+    }
+
+    /// Whether there's a blank line between `from` and `to`, before the first
+    /// comment
+    fn blank_line(&self, from: usize, to: usize) -> bool {
+        let gap = &self.source[from..to];
+        let gap = gap.find('#').map_or(gap, |i| &gap[..i]);
+        gap.split('\n')
+            .skip(1)
+            .collect::<Vec<_>>()
+            .split_last()
+            .is_some_and(|(_, between)| between.iter().any(|line| line.trim().is_empty()))
+    }
+
+    /// Comments found between `from` and `to` that haven't been written yet
+    fn take_comments(&mut self, from: usize, to: usize) -> impl Iterator<Item = String> + use<> {
+        let mut taken = Vec::new();
+        for comment in &mut self.comments {
+            if !comment.written && (from..to).contains(&comment.offset) {
+                comment.written = true;
+                taken.push(comment.text.clone());
+            }
+        }
+        taken.into_iter()
+    }
+
+    fn push_comments(&mut self, comments: Vec<String>, indent: usize) {
+        let pad = " ".repeat(indent);
+        self.lines.extend(
+            comments
+                .into_iter()
+                .map(|comment| format!("{pad}{comment}")),
+        );
+    }
+
+    /// Write `line` followed by any comment at the end of the source line
+    /// containing `offset`
+    fn push_trailing(&mut self, mut line: String, offset: usize) {
+        let end = line_end(self.source, offset);
+        for comment in &mut self.comments {
+            if !comment.written && !comment.own_line && (offset..end).contains(&comment.offset) {
+                comment.written = true;
+                line.push(' ');
+                line.push_str(&comment.text);
+            }
+        }
+        self.lines.push(line);
+    }
+
+    /// Write the content lines of a block scalar
+    fn push_block(&mut self, lines: Vec<String>, indent: usize) {
+        let pad = " ".repeat(indent);
+        self.lines.extend(lines.into_iter().map(|line| {
+            if line.is_empty() {
+                line
+            } else {
+                format!("{pad}{line}")
+            }
+        }));
+    }
+}
+
+fn is_empty(node: &SourceNode) -> bool {
+    match node {
+        SourceNode::Mapping { entries, .. } => entries.is_empty(),
+        SourceNode::Sequence { items, .. } => items.is_empty(),
+        _ => false,
+    }
+}
+
+fn scalar_value(node: &SourceNode) -> String {
+    match node {
+        SourceNode::Scalar { value, .. } => value.clone(),
+        _ => String::new(),
+    }
+}
+
+/// `value` plain if that reads back as the same string, otherwise quoted
+fn quote(value: &str) -> String {
+    if value.contains(|c: char| c.is_control()) {
+        return format!("\"{}\"", escape_double_quoted(value));
+    }
+    match serde_yaml::to_string(value) {
+        Ok(plain) if plain.trim_end() == value && !yaml_1_1_special(value) => value.to_string(),
+        _ => format!("'{}'", value.replace('\'', "''")),
+    }
+}
+
+/// Whether a plain scalar would be read as something other than a string by
+/// YAML 1.1 parsers, e.g. `yes`, `off` or `12:30`
+fn yaml_1_1_special(value: &str) -> bool {
+    let lower = value.to_ascii_lowercase();
+    let numeric = value.starts_with(|c: char| c.is_ascii_digit())
+        && value.contains([':', '_'])
+        && value
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, ':' | '_' | '.'));
+    numeric || matches!(lower.as_str(), "y" | "n" | "yes" | "no" | "on" | "off")
+}
+
+/// Every comment in `source`, skipping `#`s inside scalars
+fn comments(source: &str, root: &SourceNode) -> Vec<Comment> {
+    fn scalars(node: &SourceNode, spans: &mut Vec<(usize, usize)>) {
+        match node {
+            SourceNode::Scalar { span, .. } | SourceNode::Alias { span } => {
+                spans.push((span.start.offset, span.end.offset))
+            }
+            SourceNode::Sequence { items, .. } => {
+                items.iter().for_each(|item| scalars(item, spans))
+            }
+            SourceNode::Mapping { entries, .. } => entries.iter().for_each(|(key, value)| {
+                scalars(key, spans);
+                scalars(value, spans);
+            }),
+        }
+    }
+
+    let mut spans = Vec::new();
+    scalars(root, &mut spans);
+    spans.sort();
+
+    let mut comments = Vec::new();
+    let mut offset = 0;
+    let mut spans = spans.into_iter().peekable();
+    while offset < source.len() {
+        while spans.peek().is_some_and(|(_, end)| *end <= offset) {
+            spans.next();
+        }
+        if let Some(&(start, end)) = spans.peek()
+            && start <= offset
+            && start < end
+        {
+            offset = end;
+            continue;
+        }
+        let rest = &source[offset..];
+        let Some(c) = rest.chars().next() else {
+            break;
+        };
+        let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let before = &source[line_start..offset];
+        if c == '#' && (before.is_empty() || before.ends_with(char::is_whitespace)) {
+            let len = rest.find('\n').unwrap_or(rest.len());
+            comments.push(Comment {
+                offset,
+                text: rest[..len].trim_end().to_string(),
+                own_line: before.trim().is_empty(),
+                written: false,
+            });
+            offset += len;
+        } else {
+            offset += c.len_utf8();
+        }
+    }
+    comments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multi_line_plain_scalars() {
+        let formatted = format("a: x\n   éé\nb: 1\n").unwrap();
+        let value: Value = serde_yaml::from_str(&formatted).unwrap();
+        assert_eq!(value["a"], "x éé");
+        assert_eq!(value["b"], 1);
+
+        let source = "condition: and(succeeded(),\n    eq(1, 1))\n";
+        let value: Value = serde_yaml::from_str(&format(source).unwrap()).unwrap();
+        assert_eq!(value["condition"], "and(succeeded(), eq(1, 1))");
+    }
+
+    #[test]
+    fn keys_are_sorted_and_lists_indented() {
+        let source = "\
+steps:
+  - displayName: Build  # why
+    task: Bash@3
+
+
+pr: none
+";
+        assert_eq!(
+            format(source).unwrap(),
+            "pr: none\n\nsteps:\n- task: Bash@3\n  displayName: Build # why\n"
+        );
+        assert_eq!(check(source).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn comments_survive_key_reordering() {
+        let source = "\
+# the only stage
+stages:
+- jobs:
+  # builds it
+  - steps: []  # none yet
+    job: build
+  # runs first
+  stage: build
+";
+        assert_eq!(
+            format(source).unwrap(),
+            "\
+# the only stage
+stages:
+# runs first
+- stage: build
+  jobs:
+  # builds it
+  - job: build
+    steps: [] # none yet
+"
+        );
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let sources = [
+            "steps:\n  - displayName: Build  # why\n    task: Bash@3\n\n\npr: none\n",
+            "a: x\n   éé\nb: \"it's\"\nc: |\n    one\n    two\n",
+            "- &first\n  b: 1\n  a: 2\n- *first\n# trailing\n",
+            "stages:\n- ${{ if eq(1, 1) }}:\n  - stage: a\n    jobs: []\n",
+            include_str!("../../examples/entrypoint/azure-pipelines.yml"),
+        ];
+        for source in sources {
+            let formatted = format(source).expect(source);
+            assert_eq!(format(&formatted).unwrap(), formatted, "{source}");
+        }
+    }
+
+    #[test]
+    fn example_is_formatted() {
+        let source = include_str!("../../examples/entrypoint/azure-pipelines.yml");
+        assert_eq!(check(source).unwrap(), None);
+    }
+}
@@ -0,0 +1,10 @@
+//! Format pipeline YAML
+//!
+//! [`format`](crate::fmt::format::format) rewrites a file in a canonical
+//! layout, with keys in the order
+//! [`NodeKind::key_order`](crate::fmt::order::NodeKind::key_order) gives, and
+//! [`check`](crate::fmt::format::check) reports whether a file is already
+//! formatted.
+
+pub mod format;
+pub mod order;
@@ -0,0 +1,187 @@
+//! The canonical key order of each kind of node
+
+/// What a mapping in pipeline YAML is, which decides the order of its keys
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum NodeKind {
+    /// The root of a pipeline or template file
+    Pipeline,
+    /// An item of `stages`
+    Stage,
+    /// An item of `jobs`
+    Job,
+    /// An item of `steps`
+    Step,
+    /// An item of `variables` in list form
+    Variable,
+    /// An item of `parameters` in a pipeline or template
+    Parameter,
+    /// `resources`
+    Resources,
+    /// An item of `resources.repositories`
+    Repository,
+    /// An item of `resources.containers`
+    Container,
+    /// An item of `resources.pipelines`
+    PipelineResource,
+    /// A `trigger` or `pr` in full syntax
+    Trigger,
+    /// `include`/`exclude` lists of a trigger
+    TriggerItem,
+    /// `extends`
+    Extends,
+    /// Anything else, such as template parameters or task inputs, whose keys
+    /// keep their order
+    Other,
+}
+
+impl NodeKind {
+    /// Keys in the order they're written. Keys that aren't listed follow, in
+    /// the order they were found.
+    pub fn key_order(&self) -> &'static [&'static str] {
+        match self {
+            NodeKind::Pipeline => &[
+                "name",
+                "appendCommitMessageToRunName",
+                "trigger",
+                "pr",
+                "schedules",
+                "parameters",
+                "resources",
+                "variables",
+                "pool",
+                "lockBehavior",
+                "extends",
+                "stages",
+                "jobs",
+                "steps",
+            ],
+            NodeKind::Stage => &[
+                "stage",
+                "template",
+                "displayName",
+                "dependsOn",
+                "condition",
+                "pool",
+                "variables",
+                "lockBehavior",
+                "templateContext",
+                "parameters",
+                "jobs",
+            ],
+            NodeKind::Job => &[
+                "job",
+                "deployment",
+                "template",
+                "displayName",
+                "dependsOn",
+                "condition",
+                "continueOnError",
+                "timeoutInMinutes",
+                "cancelTimeoutInMinutes",
+                "pool",
+                "container",
+                "services",
+                "workspace",
+                "environment",
+                "strategy",
+                "variables",
+                "templateContext",
+                "parameters",
+                "steps",
+            ],
+            NodeKind::Step => &[
+                "task",
+                "script",
+                "bash",
+                "pwsh",
+                "powershell",
+                "checkout",
+                "download",
+                "downloadBuild",
+                "getPackage",
+                "publish",
+                "reviewApp",
+                "template",
+                "displayName",
+                "name",
+                "condition",
+                "continueOnError",
+                "enabled",
+                "timeoutInMinutes",
+                "retryCountOnTaskFailure",
+                "target",
+                "env",
+                "inputs",
+                "parameters",
+            ],
+            NodeKind::Variable => &[
+                "name",
+                "group",
+                "template",
+                "value",
+                "readonly",
+                "parameters",
+            ],
+            NodeKind::Parameter => &["name", "displayName", "type", "default", "values"],
+            NodeKind::Resources => &[
+                "builds",
+                "containers",
+                "pipelines",
+                "repositories",
+                "webhooks",
+                "packages",
+            ],
+            NodeKind::Repository => &["repository", "type", "endpoint", "name", "ref", "trigger"],
+            NodeKind::Container => &["container", "image", "endpoint", "env", "options"],
+            NodeKind::PipelineResource => &[
+                "pipeline", "source", "project", "branch", "version", "tags", "trigger",
+            ],
+            NodeKind::Trigger => &["batch", "autoCancel", "drafts", "branches", "paths", "tags"],
+            NodeKind::TriggerItem => &["include", "exclude"],
+            NodeKind::Extends => &["template", "parameters"],
+            NodeKind::Other => &[],
+        }
+    }
+
+    /// The kind of a mapping found under `key` in a node of this kind
+    pub fn value(&self, key: &str) -> NodeKind {
+        if is_directive(key) {
+            return *self;
+        }
+        match (self, key) {
+            (NodeKind::Pipeline, "resources") => NodeKind::Resources,
+            (NodeKind::Pipeline, "trigger" | "pr") => NodeKind::Trigger,
+            (NodeKind::Pipeline, "extends") => NodeKind::Extends,
+            (NodeKind::Repository | NodeKind::PipelineResource, "trigger") => NodeKind::Trigger,
+            (NodeKind::Trigger, "branches" | "paths" | "tags") => NodeKind::TriggerItem,
+            _ => NodeKind::Other,
+        }
+    }
+
+    /// The kind of the items of a list found under `key` in a node of this
+    /// kind
+    pub fn items(&self, key: &str) -> NodeKind {
+        if is_directive(key) {
+            return *self;
+        }
+        match (self, key) {
+            (_, "stages") => NodeKind::Stage,
+            (_, "jobs") => NodeKind::Job,
+            (_, "steps") => NodeKind::Step,
+            (NodeKind::Pipeline | NodeKind::Stage | NodeKind::Job, "variables") => {
+                NodeKind::Variable
+            }
+            (NodeKind::Pipeline, "parameters") => NodeKind::Parameter,
+            (NodeKind::Resources, "repositories") => NodeKind::Repository,
+            (NodeKind::Resources, "containers") => NodeKind::Container,
+            (NodeKind::Resources, "pipelines") => NodeKind::PipelineResource,
+            _ => NodeKind::Other,
+        }
+    }
+}
+
+/// Whether a key is a template expression directive such as `${{ if ... }}`,
+/// whose value is part of the node it's in
+pub fn is_directive(key: &str) -> bool {
+    key.trim_start().starts_with("${{")
+}
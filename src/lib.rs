@@ -7,8 +7,31 @@
 /// The Azure Pipeline type definitions
 pub mod core;
 
+/// Expression parsing
+pub mod expressions;
+
+/// Canonical formatting of pipeline YAML
+pub mod fmt;
+
+/// Source locations of parsed YAML
+pub mod source;
+
+/// Simulate pipeline runs
+pub mod simulator;
+
 /// Support for pipeline templates
 pub mod templates;
 
 /// Validation methods
 pub mod validator;
+
+// lets the tests use the derive macros, whose code names this crate
+#[cfg(test)]
+extern crate self as azure_pipelines_rs;
+
+/// Used by the code `#[derive(Parameterized)]` generates
+#[doc(hidden)]
+pub mod __private {
+    pub use indexmap::IndexMap;
+    pub use serde_yaml;
+}
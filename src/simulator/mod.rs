@@ -0,0 +1,5 @@
+//! Simulate which stages and jobs a run would execute
+//!
+//! <https://learn.microsoft.com/en-us/azure/devops/pipelines/process/conditions?view=azure-pipelines>
+
+pub mod run;
@@ -0,0 +1,608 @@
+use std::collections::{BTreeMap, HashMap};
+
+use indexmap::IndexMap;
+
+use crate::{
+    core::v1::{
+        job::Job,
+        stage::{Stage, StageWithJobs},
+    },
+    expressions::{
+        eval::{self, Context, Value},
+        parser,
+    },
+    validator::graph::{DependencyGraph, Node},
+};
+
+/// The result of a job or stage
+///
+/// <https://learn.microsoft.com/en-us/azure/devops/pipelines/process/expressions?view=azure-pipelines#dependencies>
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum TaskResult {
+    /// Completed without errors
+    #[default]
+    Succeeded,
+    /// Completed with warnings
+    SucceededWithIssues,
+    /// Completed with errors
+    Failed,
+    /// Canceled before it completed
+    Canceled,
+    /// Didn't run because of its condition
+    Skipped,
+}
+
+impl TaskResult {
+    /// The name used for this result in `dependencies.<name>.result`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskResult::Succeeded => "Succeeded",
+            TaskResult::SucceededWithIssues => "SucceededWithIssues",
+            TaskResult::Failed => "Failed",
+            TaskResult::Canceled => "Canceled",
+            TaskResult::Skipped => "Skipped",
+        }
+    }
+
+    fn is_success(&self) -> bool {
+        matches!(
+            self,
+            TaskResult::Succeeded | TaskResult::SucceededWithIssues
+        )
+    }
+}
+
+/// The inputs to a simulated run
+#[derive(PartialEq, Debug, Default)]
+pub struct RunOptions {
+    /// Why the run was queued, available as `variables['Build.Reason']`, e.g.
+    /// `IndividualCI`, `PullRequest`, `Manual` or `Schedule`
+    pub reason: String,
+
+    /// Variables set for the run (pipeline variables, variable groups and
+    /// queue-time values)
+    pub variables: HashMap<String, String>,
+
+    /// The result each job ends in if it runs, keyed by stage name and job
+    /// name. Jobs that aren't listed succeed.
+    pub outcomes: HashMap<(String, String), TaskResult>,
+
+    /// Whether the run was canceled, which `canceled()` reports. A job that
+    /// ends as [`TaskResult::Canceled`] doesn't cancel the run.
+    pub canceled: bool,
+}
+
+impl RunOptions {
+    /// Choose the result a job ends in if it runs
+    pub fn set_outcome(&mut self, stage: &str, job: &str, result: TaskResult) {
+        self.outcomes
+            .insert((stage.to_string(), job.to_string()), result);
+    }
+}
+
+/// What happened to each stage in a simulated run, in the order they ran
+#[derive(PartialEq, Debug, Default)]
+pub struct RunResult {
+    /// Every stage in the order it was evaluated
+    pub stages: Vec<StageRun>,
+}
+
+impl RunResult {
+    /// Find a stage by name
+    pub fn stage(&self, name: &str) -> Option<&StageRun> {
+        self.stages.iter().find(|s| s.name == name)
+    }
+
+    /// Find a job by stage name and job name
+    pub fn job(&self, stage: &str, job: &str) -> Option<&JobRun> {
+        self.stage(stage)?.jobs.iter().find(|j| j.name == job)
+    }
+}
+
+/// What happened to a stage in a simulated run
+#[derive(PartialEq, Debug)]
+pub struct StageRun {
+    /// Stage name
+    pub name: String,
+
+    /// Whether the stage's condition allowed it to run
+    pub ran: bool,
+
+    /// How the stage ended
+    pub result: TaskResult,
+
+    /// Every job in the stage, in the order they were evaluated
+    pub jobs: Vec<JobRun>,
+}
+
+/// What happened to a job in a simulated run
+#[derive(PartialEq, Debug)]
+pub struct JobRun {
+    /// Job name
+    pub name: String,
+
+    /// Whether the job's condition allowed it to run
+    pub ran: bool,
+
+    /// How the job ended
+    pub result: TaskResult,
+}
+
+/// Walk the stages and jobs in dependency order, evaluate each `condition`
+/// (or the default `succeeded()`), and record which ones run and how they end.
+///
+/// A stage whose jobs were all skipped ends as skipped. Templates must be
+/// expanded before simulating.
+pub fn simulate(stages: &[Stage], options: &RunOptions) -> Result<RunResult, String> {
+    // with templates ruled out, graph nodes line up with `stages`
+    let graph = DependencyGraph::stages(stages);
+    let stages = stages
+        .iter()
+        .map(|stage| match stage {
+            Stage::Stage(stage) => Ok(stage),
+            Stage::Template(template) => Err(format!(
+                "stage template {:?} must be expanded before simulating",
+                template.template
+            )),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut state = RunState {
+        options,
+        stage_results: BTreeMap::new(),
+        job_results: BTreeMap::new(),
+    };
+    let mut result = RunResult::default();
+
+    for i in order(&graph)? {
+        let stage = stages[i];
+        let node = &graph.nodes[i];
+
+        let ran = state.evaluate(
+            stage.condition.as_deref(),
+            Level::Stage {
+                graph: &graph,
+                node,
+                variables: &stage.variables,
+            },
+        )?;
+        let jobs = state.run_jobs(stage, &node.name, ran)?;
+        let stage_result = if !ran || jobs.iter().all(|j| j.result == TaskResult::Skipped) {
+            TaskResult::Skipped
+        } else {
+            [
+                TaskResult::Failed,
+                TaskResult::Canceled,
+                TaskResult::SucceededWithIssues,
+            ]
+            .into_iter()
+            .find(|r| jobs.iter().any(|j| j.result == *r))
+            .unwrap_or(TaskResult::Succeeded)
+        };
+
+        state.stage_results.insert(node.name.clone(), stage_result);
+        result.stages.push(StageRun {
+            name: node.name.clone(),
+            ran,
+            result: stage_result,
+            jobs,
+        });
+    }
+
+    Ok(result)
+}
+
+/// Indexes into `graph.nodes` in the order they should be evaluated
+fn order(graph: &DependencyGraph) -> Result<Vec<usize>, String> {
+    if let Some(name) = graph.duplicates().first() {
+        return Err(format!("{name:?} is declared more than once"));
+    }
+    if let Some((name, missing)) = graph.missing().first() {
+        return Err(format!("{name:?} depends on non-existent {missing:?}"));
+    }
+    Ok(graph
+        .topological_order()
+        .map_err(|d| d.message)?
+        .into_iter()
+        .filter_map(|name| graph.nodes.iter().position(|n| n.name == name))
+        .collect())
+}
+
+enum Level<'a> {
+    Stage {
+        graph: &'a DependencyGraph,
+        node: &'a Node,
+        variables: &'a IndexMap<String, serde_yaml::Value>,
+    },
+    Job {
+        stage: &'a str,
+        graph: &'a DependencyGraph,
+        node: &'a Node,
+        stage_variables: &'a IndexMap<String, serde_yaml::Value>,
+        variables: &'a IndexMap<String, serde_yaml::Value>,
+    },
+}
+
+struct RunState<'a> {
+    options: &'a RunOptions,
+    stage_results: BTreeMap<String, TaskResult>,
+    /// Job results keyed by stage name, then job name
+    job_results: BTreeMap<String, BTreeMap<String, TaskResult>>,
+}
+
+impl RunState<'_> {
+    fn run_jobs(
+        &mut self,
+        stage: &StageWithJobs,
+        stage_name: &str,
+        stage_ran: bool,
+    ) -> Result<Vec<JobRun>, String> {
+        let jobs = stage
+            .jobs
+            .iter()
+            .map(|job| match job {
+                Job::Job(job) => Ok(job),
+                Job::Template(template) => Err(format!(
+                    "job template {:?} must be expanded before simulating",
+                    template.template
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // with templates ruled out, graph nodes line up with `jobs`
+        let graph = DependencyGraph::jobs(stage);
+        let order = order(&graph).map_err(|e| format!("stage {stage_name:?}: {e}"))?;
+
+        self.job_results
+            .insert(stage_name.to_string(), BTreeMap::new());
+        let mut runs = Vec::new();
+
+        for i in order {
+            let node = &graph.nodes[i];
+            let name = &node.name;
+            let ran = stage_ran
+                && self
+                    .evaluate(
+                        jobs[i].condition.as_deref(),
+                        Level::Job {
+                            stage: stage_name,
+                            graph: &graph,
+                            node,
+                            stage_variables: &stage.variables,
+                            variables: &jobs[i].variables,
+                        },
+                    )
+                    .map_err(|e| format!("stage {stage_name:?} job {name:?}: {e}"))?;
+            let result = if ran {
+                self.options
+                    .outcomes
+                    .get(&(stage_name.to_string(), name.clone()))
+                    .copied()
+                    .unwrap_or_default()
+            } else {
+                TaskResult::Skipped
+            };
+            if let Some(results) = self.job_results.get_mut(stage_name) {
+                results.insert(name.clone(), result);
+            }
+            runs.push(JobRun {
+                name: name.clone(),
+                ran,
+                result,
+            });
+        }
+
+        Ok(runs)
+    }
+
+    fn evaluate(&self, condition: Option<&str>, level: Level) -> Result<bool, String> {
+        let condition = condition.unwrap_or("succeeded()");
+        let expr = parser::parse(condition).map_err(|e| format!("condition: {e}"))?;
+        let context = ConditionContext { state: self, level };
+        eval::evaluate(&expr, &context)
+            .map(|value| value.is_truthy())
+            .map_err(|e| format!("condition: {e}"))
+    }
+}
+
+struct ConditionContext<'a> {
+    state: &'a RunState<'a>,
+    level: Level<'a>,
+}
+
+impl ConditionContext<'_> {
+    fn variables(&self) -> Value {
+        let mut variables = BTreeMap::new();
+        variables.insert(
+            "Build.Reason".to_string(),
+            Value::String(self.state.options.reason.clone()),
+        );
+        for (name, value) in &self.state.options.variables {
+            variables.insert(name.clone(), Value::String(value.clone()));
+        }
+
+        let scopes = match &self.level {
+            Level::Stage { variables, .. } => vec![*variables],
+            Level::Job {
+                stage_variables,
+                variables,
+                ..
+            } => vec![*stage_variables, *variables],
+        };
+        for scope in scopes {
+            for (name, value) in scope {
+                variables.insert(name.clone(), yaml_to_value(value));
+            }
+        }
+
+        Value::Object(variables)
+    }
+
+    /// The results of the current level's dependencies, keyed by name
+    fn dependency_results(&self) -> BTreeMap<String, TaskResult> {
+        match &self.level {
+            Level::Stage { .. } => self.state.stage_results.clone(),
+            Level::Job { stage, .. } => self
+                .state
+                .job_results
+                .get(*stage)
+                .cloned()
+                .unwrap_or_default(),
+        }
+    }
+
+    fn depends(&self) -> &[String] {
+        match &self.level {
+            Level::Stage { node, .. } | Level::Job { node, .. } => &node.depends_on,
+        }
+    }
+
+    /// Everything the current stage or job depends on, directly or indirectly
+    fn ancestors(&self) -> Vec<&str> {
+        match &self.level {
+            Level::Stage { graph, node, .. } | Level::Job { graph, node, .. } => {
+                graph.ancestors(&node.name)
+            }
+        }
+    }
+}
+
+impl Context for ConditionContext<'_> {
+    fn named_value(&self, name: &str) -> Option<Value> {
+        let name = name.to_ascii_lowercase();
+        match name.as_str() {
+            "variables" => Some(self.variables()),
+            "dependencies" => Some(Value::Object(
+                self.dependency_results()
+                    .into_iter()
+                    .filter(|(name, _)| self.depends().contains(name))
+                    .map(|(name, result)| (name, result_value(result)))
+                    .collect(),
+            )),
+            "stagedependencies" => Some(Value::Object(
+                self.state
+                    .job_results
+                    .iter()
+                    .map(|(stage, jobs)| {
+                        let jobs = jobs
+                            .iter()
+                            .map(|(job, result)| (job.clone(), result_value(*result)))
+                            .collect();
+                        (stage.clone(), Value::Object(jobs))
+                    })
+                    .collect(),
+            )),
+            "pipeline" | "resources" => Some(Value::Object(BTreeMap::new())),
+            _ => None,
+        }
+    }
+
+    fn status(&self, function: &str, names: &[String]) -> Result<bool, String> {
+        let results = self.dependency_results();
+        let canceled = self.state.options.canceled;
+        let lookup = |name: &String| {
+            results
+                .get(name)
+                .copied()
+                .ok_or_else(|| format!("`{function}` refers to unknown dependency {name:?}"))
+        };
+        let named = || names.iter().map(lookup).collect::<Result<Vec<_>, _>>();
+        // with no names, the direct dependencies
+        let direct = || {
+            if names.is_empty() {
+                self.depends().iter().map(lookup).collect()
+            } else {
+                named()
+            }
+        };
+
+        Ok(match function {
+            "always" => true,
+            "canceled" => canceled,
+            "succeeded" => !canceled && direct()?.iter().all(TaskResult::is_success),
+            "failed" => {
+                let checked = if names.is_empty() {
+                    self.ancestors()
+                        .iter()
+                        .filter_map(|n| results.get(*n).copied())
+                        .collect()
+                } else {
+                    named()?
+                };
+                !canceled && checked.contains(&TaskResult::Failed)
+            }
+            "succeededOrFailed" => {
+                !canceled
+                    && direct()?
+                        .iter()
+                        .all(|r| r.is_success() || *r == TaskResult::Failed)
+            }
+            other => return Err(format!("unknown status function `{other}`")),
+        })
+    }
+}
+
+fn result_value(result: TaskResult) -> Value {
+    let mut map = BTreeMap::new();
+    map.insert(
+        "result".to_string(),
+        Value::String(result.as_str().to_string()),
+    );
+    map.insert("outputs".to_string(), Value::Object(BTreeMap::new()));
+    Value::Object(map)
+}
+
+fn yaml_to_value(value: &serde_yaml::Value) -> Value {
+    match value {
+        serde_yaml::Value::Null => Value::Null,
+        serde_yaml::Value::Bool(b) => Value::String(if *b { "True" } else { "False" }.to_string()),
+        serde_yaml::Value::Number(n) => Value::String(n.to_string()),
+        serde_yaml::Value::String(s) => Value::String(s.clone()),
+        other => Value::String(serde_yaml::to_string(other).unwrap_or_default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stages(yaml: &str) -> Vec<Stage> {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    const PIPELINE: &str = "
+- stage: build
+  jobs:
+  - job: compile
+  - job: test
+    dependsOn: compile
+  - job: report
+    dependsOn: test
+    condition: failed()
+- stage: deploy
+  condition: and(succeeded(), eq(variables['Build.Reason'], 'IndividualCI'))
+  jobs:
+  - job: release
+- stage: cleanup
+  dependsOn: []
+  condition: always()
+  jobs:
+  - job: tidy
+    condition: canceled()
+";
+
+    fn results(result: &RunResult) -> Vec<(&str, TaskResult)> {
+        result
+            .stages
+            .iter()
+            .map(|stage| (stage.name.as_str(), stage.result))
+            .collect()
+    }
+
+    #[test]
+    fn everything_succeeds() {
+        let options = RunOptions {
+            reason: "IndividualCI".to_string(),
+            ..Default::default()
+        };
+        let result = simulate(&stages(PIPELINE), &options).unwrap();
+        assert_eq!(
+            results(&result),
+            [
+                ("build", TaskResult::Succeeded),
+                ("deploy", TaskResult::Succeeded),
+                ("cleanup", TaskResult::Skipped),
+            ]
+        );
+        assert!(!result.job("build", "report").unwrap().ran);
+        assert!(result.stage("cleanup").unwrap().ran);
+    }
+
+    #[test]
+    fn failure_skips_dependents() {
+        let mut options = RunOptions {
+            reason: "IndividualCI".to_string(),
+            ..Default::default()
+        };
+        options.set_outcome("build", "test", TaskResult::Failed);
+        let result = simulate(&stages(PIPELINE), &options).unwrap();
+        assert!(result.job("build", "report").unwrap().ran);
+        assert_eq!(
+            results(&result),
+            [
+                ("build", TaskResult::Failed),
+                ("deploy", TaskResult::Skipped),
+                ("cleanup", TaskResult::Skipped),
+            ]
+        );
+    }
+
+    #[test]
+    fn variables_in_conditions() {
+        let options = RunOptions {
+            reason: "PullRequest".to_string(),
+            ..Default::default()
+        };
+        let result = simulate(&stages(PIPELINE), &options).unwrap();
+        assert!(!result.stage("deploy").unwrap().ran);
+    }
+
+    #[test]
+    fn canceled_job_doesnt_cancel_the_run() {
+        let yaml = "
+- stage: build
+  jobs:
+  - job: a
+  - job: b
+    condition: canceled()
+  - job: c
+    dependsOn: a
+    condition: succeededOrFailed()
+";
+        let mut options = RunOptions::default();
+        options.set_outcome("build", "a", TaskResult::Canceled);
+        let result = simulate(&stages(yaml), &options).unwrap();
+        assert!(!result.job("build", "b").unwrap().ran);
+        assert!(!result.job("build", "c").unwrap().ran);
+        assert_eq!(result.stage("build").unwrap().result, TaskResult::Canceled);
+    }
+
+    #[test]
+    fn canceled_run() {
+        let options = RunOptions {
+            reason: "IndividualCI".to_string(),
+            canceled: true,
+            ..Default::default()
+        };
+        let result = simulate(&stages(PIPELINE), &options).unwrap();
+        assert_eq!(
+            results(&result),
+            [
+                ("build", TaskResult::Skipped),
+                ("deploy", TaskResult::Skipped),
+                ("cleanup", TaskResult::Succeeded),
+            ]
+        );
+        assert!(result.job("cleanup", "tidy").unwrap().ran);
+    }
+
+    #[test]
+    fn templates_must_be_expanded() {
+        let error = simulate(&stages("- template: stages.yml"), &RunOptions::default());
+        assert!(error.unwrap_err().contains("must be expanded"));
+    }
+
+    #[test]
+    fn cycles_are_errors() {
+        let yaml = "
+- stage: a
+  dependsOn: b
+  jobs: []
+- stage: b
+  dependsOn: a
+  jobs: []
+";
+        let error = simulate(&stages(yaml), &RunOptions::default()).unwrap_err();
+        assert!(error.contains("cycle"), "{error}");
+    }
+}
@@ -0,0 +1,542 @@
+//! Edit pipeline YAML in place, keeping comments, anchors, quoting and layout
+//!
+//! A [`Document`] keeps the original text alongside the value parsed from it.
+//! Edits address nodes by a path of [`Segment`]s and only rewrite the text of
+//! the nodes they touch. After every edit the text is parsed again, and an edit
+//! that would leave it invalid, or leave the edited node without the value
+//! asked for, is rolled back.
+
+use std::{error::Error, ops::Range};
+
+use serde::de::DeserializeOwned;
+use serde_yaml::Value;
+
+use crate::source::{
+    map::{Segment, SourceMap, SourceNode},
+    parse::{self, Parsed},
+    text::{dash, line_end},
+};
+
+/// Replace `range` of the source text with `text`
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Edit {
+    /// Byte range of the source text to replace
+    pub range: Range<usize>,
+
+    /// What to put there instead
+    pub text: String,
+}
+
+/// YAML source text and the value parsed from it
+#[derive(PartialEq, Debug)]
+pub struct Document<T> {
+    text: String,
+    parsed: Parsed<T>,
+}
+
+impl<T: DeserializeOwned> Document<T> {
+    /// Parse `text` into `T`, keeping the text for editing
+    pub fn parse(text: impl Into<String>) -> Result<Self, Box<dyn Error>> {
+        let text = text.into();
+        let parsed = parse::from_str(&text)?;
+        Ok(Document { text, parsed })
+    }
+
+    /// The current source text
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The value parsed from the current text
+    pub fn value(&self) -> &T {
+        &self.parsed.value
+    }
+
+    /// The location of every node of the current text
+    pub fn source(&self) -> &SourceMap {
+        &self.parsed.source
+    }
+
+    /// The node at `path` from the root
+    pub fn node(&self, path: &[Segment<'_>]) -> Result<&SourceNode, Box<dyn Error>> {
+        self.parsed
+            .source
+            .root
+            .at(path)
+            .ok_or_else(|| format!("no node at {}", describe(path)).into())
+    }
+
+    /// Apply every edit at once and parse the result. Edits may not overlap.
+    /// If the result doesn't parse, the document is left unchanged.
+    pub fn apply(&mut self, edits: Vec<Edit>) -> Result<(), Box<dyn Error>> {
+        let (text, parsed) = self.edited(edits)?;
+        self.text = text;
+        self.parsed = parsed;
+        Ok(())
+    }
+
+    /// Apply `edits` like [`apply`](Self::apply), keeping the result only if
+    /// `check` accepts it, so an edit that parses but changes the wrong text
+    /// is rolled back too
+    fn apply_checked(
+        &mut self,
+        edits: Vec<Edit>,
+        check: impl FnOnce(&str, &Parsed<T>) -> Result<(), String>,
+    ) -> Result<(), Box<dyn Error>> {
+        let (text, parsed) = self.edited(edits)?;
+        check(&text, &parsed).map_err(|e| format!("edit is invalid: {e}"))?;
+        self.text = text;
+        self.parsed = parsed;
+        Ok(())
+    }
+
+    /// The text with `edits` applied and the value parsed from it
+    fn edited(&self, mut edits: Vec<Edit>) -> Result<(String, Parsed<T>), Box<dyn Error>> {
+        for Edit { range, .. } in &edits {
+            if range.start > range.end || range.end > self.text.len() {
+                return Err(format!(
+                    "edit at {range:?} is outside the document of {} bytes",
+                    self.text.len()
+                )
+                .into());
+            }
+            if !self.text.is_char_boundary(range.start) || !self.text.is_char_boundary(range.end) {
+                return Err(format!("edit at {range:?} splits a character").into());
+            }
+        }
+        edits.sort_by_key(|edit| edit.range.start);
+        if let Some(pair) = edits
+            .windows(2)
+            .find(|pair| pair[0].range.end > pair[1].range.start)
+        {
+            return Err(format!(
+                "edits at {:?} and {:?} overlap",
+                pair[0].range, pair[1].range
+            )
+            .into());
+        }
+
+        let mut text = self.text.clone();
+        for edit in edits.iter().rev() {
+            text.replace_range(edit.range.clone(), &edit.text);
+        }
+        let parsed = parse::from_str(&text).map_err(|e| format!("edit is invalid: {e}"))?;
+        Ok((text, parsed))
+    }
+
+    /// Replace the scalar at `path` with `value`, written in the same style
+    /// (plain, quoted or block) as the scalar it replaces
+    pub fn set_scalar(&mut self, path: &[Segment<'_>], value: &str) -> Result<(), Box<dyn Error>> {
+        let node = self.node(path)?;
+        let SourceNode::Scalar { .. } = node else {
+            return Err(format!("{} isn't a scalar", describe(path)).into());
+        };
+        let range = range(node);
+        let block = matches!(self.text[range.clone()].chars().next(), Some('|' | '>'));
+        let text = match self.text[range.clone()].chars().next() {
+            Some('\'') => format!("'{}'", value.replace('\'', "''")),
+            Some('"') => format!("\"{}\"", escape_double_quoted(value)),
+            Some('|' | '>') => {
+                let indent = self.block_scalar_indent(range.start);
+                let header = self.text[range.start..].lines().next().unwrap_or_default();
+                let lines: Vec<String> = value
+                    .lines()
+                    .map(|line| format!("{:indent$}{line}", ""))
+                    .collect();
+                format!("{header}\n{}", lines.join("\n"))
+            }
+            _ => plain_or_quoted(value)?,
+        };
+        self.apply_checked(vec![Edit { range, text }], |_, parsed| {
+            match parsed.source.root.at(path) {
+                // block scalars end with a line break that isn't part of the
+                // value asked for
+                Some(SourceNode::Scalar { value: written, .. })
+                    if written == value
+                        || block
+                            && written.trim_end_matches('\n') == value.trim_end_matches('\n') =>
+                {
+                    Ok(())
+                }
+                _ => Err(format!(
+                    "{} doesn't hold `{value}` afterwards",
+                    describe(path)
+                )),
+            }
+        })
+    }
+
+    /// Set `key` of the mapping at `path` to `value`, a YAML fragment such as
+    /// `build` or `[build, test]`. An existing entry's value is replaced and a
+    /// new entry is added after the last one.
+    pub fn set(
+        &mut self,
+        path: &[Segment<'_>],
+        key: &str,
+        value: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let node = self.node(path)?;
+        let mut at = path.to_vec();
+        at.push(Segment::Key(key));
+        let check = |text: &str, _: &Parsed<T>| holds(text, &at, value);
+        let SourceNode::Mapping { entries, .. } = node else {
+            return Err(format!("{} isn't a mapping", describe(path)).into());
+        };
+
+        if let Some((existing_key, existing)) = node.entry(key) {
+            let indent = self.indent(existing_key.span().start.offset);
+            let edit = if value.contains('\n') {
+                Edit {
+                    range: existing_key.span().end.offset..existing.span().end.offset,
+                    text: format!(":\n{}", indent_lines(value, indent + 2, true)),
+                }
+            } else {
+                Edit {
+                    range: range(existing),
+                    text: value.to_string(),
+                }
+            };
+            return self.apply_checked(vec![edit], check);
+        }
+
+        let span = range(node);
+        let edit = if self.is_flow(node) {
+            if value.contains('\n') {
+                return Err(format!(
+                    "can't add a multi-line value to the flow mapping at {}",
+                    describe(path)
+                )
+                .into());
+            }
+            let separator = if entries.is_empty() { "" } else { ", " };
+            Edit {
+                range: span.end - 1..span.end - 1,
+                text: format!("{separator}{key}: {value}"),
+            }
+        } else {
+            let indent = entries
+                .first()
+                .map_or(0, |(key, _)| self.indent(key.span().start.offset));
+            let value = if value.contains('\n') {
+                format!(":\n{}", indent_lines(value, indent + 2, true))
+            } else {
+                format!(": {value}")
+            };
+            let at = line_end(&self.text, span.end);
+            Edit {
+                range: at..at,
+                text: format!("\n{:indent$}{key}{value}", ""),
+            }
+        };
+        self.apply_checked(vec![edit], check)
+    }
+
+    /// Append `item`, a YAML fragment such as `build` or `job: test`, to the
+    /// sequence at `path`
+    pub fn push(&mut self, path: &[Segment<'_>], item: &str) -> Result<(), Box<dyn Error>> {
+        let node = self.node(path)?;
+        let SourceNode::Sequence { items, .. } = node else {
+            return Err(format!("{} isn't a sequence", describe(path)).into());
+        };
+        let mut at = path.to_vec();
+        at.push(Segment::Index(items.len()));
+
+        let span = range(node);
+        let edit = if self.is_flow(node) {
+            if item.contains('\n') {
+                return Err(format!(
+                    "can't add a multi-line item to the flow sequence at {}",
+                    describe(path)
+                )
+                .into());
+            }
+            let separator = if items.is_empty() { "" } else { ", " };
+            Edit {
+                range: span.end - 1..span.end - 1,
+                text: format!("{separator}{item}"),
+            }
+        } else {
+            let dash = items.first().map_or(span.start, |first| {
+                dash(&self.text, first.span().start.offset)
+            });
+            let indent = self.indent(dash);
+            let at = line_end(&self.text, span.end);
+            Edit {
+                range: at..at,
+                text: format!(
+                    "\n{:indent$}- {}",
+                    "",
+                    indent_lines(item, indent + 2, false)
+                ),
+            }
+        };
+        self.apply_checked(vec![edit], |text, _| holds(text, &at, item))
+    }
+
+    /// Remove the mapping entry or sequence item at `path`, along with any
+    /// comment on the same line
+    pub fn remove(&mut self, path: &[Segment<'_>]) -> Result<(), Box<dyn Error>> {
+        let Some((last, parent_path)) = path.split_last() else {
+            return Err("can't remove the root node".into());
+        };
+        let parent = self.node(parent_path)?;
+
+        // where each entry or item starts (its key or dash) and ends
+        let children: Vec<Range<usize>> = match parent {
+            SourceNode::Mapping { entries, .. } => entries
+                .iter()
+                .map(|(key, value)| key.span().start.offset..value.span().end.offset)
+                .collect(),
+            SourceNode::Sequence { items, .. } if self.is_flow(parent) => {
+                items.iter().map(range).collect()
+            }
+            SourceNode::Sequence { items, .. } => items
+                .iter()
+                .map(|item| dash(&self.text, item.span().start.offset)..item.span().end.offset)
+                .collect(),
+            _ => Vec::new(),
+        };
+        let index = match (last, parent) {
+            (Segment::Key(key), SourceNode::Mapping { entries, .. }) => entries
+                .iter()
+                .position(|(k, _)| matches!(k, SourceNode::Scalar { value, .. } if value == key)),
+            (Segment::Index(index), SourceNode::Sequence { .. }) => {
+                Some(*index).filter(|index| *index < children.len())
+            }
+            _ => None,
+        }
+        .ok_or_else(|| format!("no node at {}", describe(path)))?;
+
+        let flow = self.is_flow(parent);
+        let child = &children[index];
+        let range = if let Some(next) = children.get(index + 1) {
+            child.start..next.start
+        } else if let Some(previous) = index.checked_sub(1).map(|i| &children[i]) {
+            if flow {
+                previous.end..child.end
+            } else {
+                line_end(&self.text, previous.end)..line_end(&self.text, child.end)
+            }
+        } else if flow {
+            child.clone()
+        } else {
+            child.start..line_end(&self.text, child.end)
+        };
+        self.apply(vec![Edit {
+            range,
+            text: String::new(),
+        }])
+    }
+
+    /// Whether a collection is written in flow style, e.g. `[a, b]`
+    fn is_flow(&self, node: &SourceNode) -> bool {
+        matches!(
+            self.text[node.span().start.offset..].chars().next(),
+            Some('[' | '{')
+        )
+    }
+
+    /// Number of columns before `offset` on its line
+    fn indent(&self, offset: usize) -> usize {
+        let start = self.text[..offset].rfind('\n').map_or(0, |i| i + 1);
+        self.text[start..offset].chars().count()
+    }
+
+    /// Indentation of the content of the block scalar whose header is at
+    /// `offset`
+    fn block_scalar_indent(&self, offset: usize) -> usize {
+        self.text[offset..]
+            .lines()
+            .skip(1)
+            .find(|line| !line.trim().is_empty())
+            .map_or(self.indent(offset) + 2, |line| {
+                line.len() - line.trim_start().len()
+            })
+    }
+}
+
+fn range(node: &SourceNode) -> Range<usize> {
+    let span = node.span();
+    span.start.offset..span.end.offset
+}
+
+/// Check that the node at `path` in `text` is the YAML fragment `expected`
+fn holds(text: &str, path: &[Segment<'_>], fragment: &str) -> Result<(), String> {
+    let expected: Value = serde_yaml::from_str(fragment).map_err(|e| e.to_string())?;
+    let root: Value = serde_yaml::from_str(text).map_err(|e| e.to_string())?;
+    let actual = path.iter().try_fold(&root, |node, segment| match segment {
+        Segment::Key(key) => node.get(*key),
+        Segment::Index(index) => node.get(*index),
+    });
+    if actual == Some(&expected) {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} doesn't hold `{fragment}` afterwards",
+            describe(path)
+        ))
+    }
+}
+
+fn describe(path: &[Segment<'_>]) -> String {
+    if path.is_empty() {
+        return "the root".to_string();
+    }
+    path.iter().map(Segment::to_string).collect()
+}
+
+/// Indent every line of `text` by `indent` spaces, optionally leaving the first
+/// line as is
+fn indent_lines(text: &str, indent: usize, first: bool) -> String {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| {
+            if (i > 0 || first) && !line.is_empty() {
+                format!("{:indent$}{line}", "")
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// `value` as a plain scalar, or quoted if it would otherwise be read as
+/// something other than that string
+fn plain_or_quoted(value: &str) -> Result<String, Box<dyn Error>> {
+    if value.contains('\n') {
+        return Ok(format!("\"{}\"", escape_double_quoted(value)));
+    }
+    Ok(serde_yaml::to_string(value)?.trim_end().to_string())
+}
+
+/// Escape `value` for a double-quoted scalar
+pub(crate) fn escape_double_quoted(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_yaml::Mapping;
+
+    use super::*;
+
+    fn document(text: &str) -> Document<Mapping> {
+        Document::parse(text).unwrap()
+    }
+
+    #[test]
+    fn set_scalar_replaces_the_whole_scalar() {
+        let mut doc = document("cond: and(succeeded(),\n    eq(1, 1))\nnext: 1\n");
+        doc.set_scalar(&[Segment::Key("cond")], "always()").unwrap();
+        assert_eq!(doc.text(), "cond: always()\nnext: 1\n");
+
+        let mut doc = document("a: x\n   éé\nb: 1\n");
+        doc.set_scalar(&[Segment::Key("a")], "ü").unwrap();
+        assert_eq!(doc.text(), "a: ü\nb: 1\n");
+    }
+
+    #[test]
+    fn set_scalar_keeps_the_style() {
+        let mut doc = document("a: 'x'  # comment\nb: \"y\"\nc: |\n  one\n  two\n");
+        doc.set_scalar(&[Segment::Key("a")], "it's").unwrap();
+        doc.set_scalar(&[Segment::Key("b")], "say \"hi\"").unwrap();
+        doc.set_scalar(&[Segment::Key("c")], "three\nfour").unwrap();
+        assert_eq!(
+            doc.text(),
+            "a: 'it''s'  # comment\nb: \"say \\\"hi\\\"\"\nc: |\n  three\n  four\n"
+        );
+        assert!(doc.set_scalar(&[Segment::Key("missing")], "x").is_err());
+    }
+
+    #[test]
+    fn set_and_push() {
+        let mut doc = document("stage: build\ndependsOn: [a]\n");
+        doc.set(&[], "condition", "succeeded()").unwrap();
+        doc.push(&[Segment::Key("dependsOn")], "b").unwrap();
+        doc.set(&[], "stage", "test").unwrap();
+        assert_eq!(
+            doc.text(),
+            "stage: test\ndependsOn: [a, b]\ncondition: succeeded()\n"
+        );
+    }
+
+    #[test]
+    fn edits_that_change_other_text_are_rolled_back() {
+        // parses, but as `n: a` and a new `b` key rather than the value asked for
+        let mut doc = document("m: {k: 1}\n");
+        let err = doc.set(&[Segment::Key("m")], "n", "a, b: 2").unwrap_err();
+        assert!(err.to_string().contains("doesn't hold"), "{err}");
+        assert_eq!(doc.text(), "m: {k: 1}\n");
+        assert_eq!(
+            doc.value(),
+            &serde_yaml::from_str::<Mapping>("m: {k: 1}").unwrap()
+        );
+    }
+
+    #[test]
+    fn invalid_edits_are_rolled_back() {
+        let mut doc = document("a: 1\n");
+        let edit = Edit {
+            range: 0..0,
+            text: "[".to_string(),
+        };
+        assert!(doc.apply(vec![edit]).is_err());
+        let overlapping = vec![
+            Edit {
+                range: 0..2,
+                text: String::new(),
+            },
+            Edit {
+                range: 1..3,
+                text: String::new(),
+            },
+        ];
+        assert!(doc.apply(overlapping).is_err());
+        assert_eq!(doc.text(), "a: 1\n");
+    }
+
+    #[test]
+    fn edits_outside_the_document_are_rejected() {
+        let mut doc = document("a: 1\n");
+        let edit = Edit {
+            range: 3..50,
+            text: "2".to_string(),
+        };
+        assert_eq!(
+            doc.apply(vec![edit]).unwrap_err().to_string(),
+            "edit at 3..50 is outside the document of 5 bytes"
+        );
+        let (start, end) = (4, 3);
+        let edit = Edit {
+            range: start..end,
+            text: String::new(),
+        };
+        assert!(doc.apply(vec![edit]).is_err());
+        assert_eq!(doc.text(), "a: 1\n");
+    }
+
+    #[test]
+    fn edits_inside_a_character_are_rejected() {
+        let mut doc = document("a: é");
+        let edit = Edit {
+            range: 4..5,
+            text: "e".to_string(),
+        };
+        assert_eq!(
+            doc.apply(vec![edit]).unwrap_err().to_string(),
+            "edit at 4..5 splits a character"
+        );
+        assert_eq!(doc.text(), "a: é");
+    }
+}
@@ -0,0 +1,507 @@
+//! A tree of YAML nodes and their spans
+
+use std::fmt;
+
+use yaml_rust2::{
+    parser::{Event, Parser},
+    scanner::{Marker, ScanError, TScalarStyle},
+};
+
+use crate::validator::diagnostic::{AstPath, PathSegment, Position, Span};
+
+/// A YAML node and the range of the source it was parsed from
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum SourceNode {
+    /// A string, number, boolean or null
+    Scalar {
+        /// Where the scalar is
+        span: Span,
+        /// The scalar as written, without quotes
+        value: String,
+    },
+    /// A list of nodes
+    Sequence {
+        /// Where the sequence is
+        span: Span,
+        /// Each item
+        items: Vec<SourceNode>,
+    },
+    /// Key/value pairs
+    Mapping {
+        /// Where the mapping is
+        span: Span,
+        /// Each key and its value
+        entries: Vec<(SourceNode, SourceNode)>,
+    },
+    /// A reference to an anchor, e.g. `*defaults`
+    Alias {
+        /// Where the alias is
+        span: Span,
+    },
+}
+
+impl SourceNode {
+    /// Where this node is
+    pub fn span(&self) -> Span {
+        match self {
+            SourceNode::Scalar { span, .. }
+            | SourceNode::Sequence { span, .. }
+            | SourceNode::Mapping { span, .. }
+            | SourceNode::Alias { span } => *span,
+        }
+    }
+
+    /// The value for `key` in a mapping
+    pub fn get(&self, key: &str) -> Option<&SourceNode> {
+        self.entry(key).map(|(_, value)| value)
+    }
+
+    /// The key and value for `key` in a mapping
+    pub fn entry(&self, key: &str) -> Option<(&SourceNode, &SourceNode)> {
+        match self {
+            SourceNode::Mapping { entries, .. } => entries
+                .iter()
+                .find(|(k, _)| matches!(k, SourceNode::Scalar { value, .. } if value == key))
+                .map(|(k, v)| (k, v)),
+            _ => None,
+        }
+    }
+
+    /// The item at `index` in a sequence
+    pub fn index(&self, index: usize) -> Option<&SourceNode> {
+        match self {
+            SourceNode::Sequence { items, .. } => items.get(index),
+            _ => None,
+        }
+    }
+
+    /// The node at `path` below this one
+    pub fn at(&self, path: &[Segment<'_>]) -> Option<&SourceNode> {
+        path.iter().try_fold(self, |node, segment| match segment {
+            Segment::Key(key) => node.get(key),
+            Segment::Index(index) => node.index(*index),
+        })
+    }
+}
+
+/// One step along a path of mapping keys and sequence indexes
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Segment<'a> {
+    /// The value of a mapping key
+    Key(&'a str),
+    /// An item of a sequence
+    Index(usize),
+}
+
+impl<'a> From<&'a str> for Segment<'a> {
+    fn from(key: &'a str) -> Self {
+        Segment::Key(key)
+    }
+}
+
+impl From<usize> for Segment<'_> {
+    fn from(index: usize) -> Self {
+        Segment::Index(index)
+    }
+}
+
+impl fmt::Display for Segment<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Segment::Key(key) => write!(f, ".{key}"),
+            Segment::Index(index) => write!(f, "[{index}]"),
+        }
+    }
+}
+
+/// The location of every node of a YAML document
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct SourceMap {
+    /// The document's root node
+    pub root: SourceNode,
+}
+
+impl SourceMap {
+    /// Scan `source` and record the span of every node in its first document
+    pub fn new(source: &str) -> Result<Self, ScanError> {
+        let mut builder = Builder {
+            source,
+            offsets: (!source.is_ascii())
+                .then(|| source.char_indices().map(|(offset, _)| offset).collect()),
+            parser: Parser::new_from_str(source),
+            pending: None,
+        };
+        let root = builder.document()?;
+        Ok(SourceMap { root })
+    }
+
+    /// The node at a path of mapping keys from the root, e.g.
+    /// `["extends", "parameters", "stages"]`
+    pub fn node(&self, keys: &[&str]) -> Option<&SourceNode> {
+        keys.iter().try_fold(&self.root, |node, key| node.get(key))
+    }
+
+    /// The span of the node a diagnostic's [`AstPath`] refers to, where the
+    /// list of stages is found at `stages_at`. If part of the path isn't in
+    /// the source (e.g. a missing optional field), the span of the closest
+    /// node that is gets returned.
+    pub fn span(&self, stages_at: &[&str], path: &AstPath) -> Option<Span> {
+        let mut node = self.node(stages_at)?;
+        for segment in &path.0 {
+            let next = match segment {
+                PathSegment::Stage { index, .. } | PathSegment::Item { index, .. } => {
+                    node.index(*index)
+                }
+                PathSegment::Job { index, .. } => {
+                    node.get("jobs").and_then(|jobs| jobs.index(*index))
+                }
+                PathSegment::Step { index, .. } => {
+                    node.get("steps").and_then(|steps| steps.index(*index))
+                }
+                PathSegment::Field(field) => node.get(field),
+            };
+            match next {
+                Some(next) => node = next,
+                None => break,
+            }
+        }
+        Some(node.span())
+    }
+}
+
+struct Builder<'a> {
+    source: &'a str,
+    /// The byte offset of each character, or `None` in ASCII sources where
+    /// character and byte offsets are the same
+    offsets: Option<Vec<usize>>,
+    parser: Parser<std::str::Chars<'a>>,
+    pending: Option<(Event, Marker)>,
+}
+
+impl Builder<'_> {
+    fn next(&mut self) -> Result<(Event, Marker), ScanError> {
+        match self.pending.take() {
+            Some(event) => Ok(event),
+            None => self.parser.next_token(),
+        }
+    }
+
+    /// Marker indexes count characters; convert to a [`Position`] with a byte
+    /// offset
+    fn position(&self, marker: &Marker) -> Position {
+        let offset = match &self.offsets {
+            None => marker.index(),
+            Some(offsets) => offsets
+                .get(marker.index())
+                .copied()
+                .unwrap_or(self.source.len()),
+        };
+        Position {
+            line: marker.line(),
+            column: marker.col() + 1,
+            offset,
+        }
+    }
+
+    /// `position` moved forward over `len` bytes, which may span lines
+    fn advance(&self, position: Position, len: usize) -> Position {
+        let skipped = &self.source[position.offset..position.offset + len];
+        match skipped.rfind('\n') {
+            None => Position {
+                column: position.column + skipped.chars().count(),
+                offset: position.offset + len,
+                ..position
+            },
+            Some(last) => Position {
+                line: position.line + skipped.matches('\n').count(),
+                column: skipped[last + 1..].chars().count() + 1,
+                offset: position.offset + len,
+            },
+        }
+    }
+
+    /// `position` moved back over `len` bytes, which may span lines
+    fn retreat(&self, position: Position, len: usize) -> Position {
+        let skipped = &self.source[position.offset - len..position.offset];
+        let lines = skipped.matches('\n').count();
+        if lines == 0 {
+            return Position {
+                column: position.column - skipped.chars().count(),
+                offset: position.offset - len,
+                ..position
+            };
+        }
+        let offset = position.offset - len;
+        let line_start = self.source[..offset].rfind('\n').map_or(0, |i| i + 1);
+        Position {
+            line: position.line - lines,
+            column: self.source[line_start..offset].chars().count() + 1,
+            offset,
+        }
+    }
+
+    /// Flow collections end after their closing bracket; block collections end
+    /// where their last item does
+    fn collection_end(&self, marker: &Marker, last: Option<&SourceNode>, close: char) -> Position {
+        let end = self.position(marker);
+        if self.source[end.offset..].starts_with(close) {
+            self.advance(end, close.len_utf8())
+        } else {
+            last.map_or(end, |node| node.span().end)
+        }
+    }
+
+    fn document(&mut self) -> Result<SourceNode, ScanError> {
+        loop {
+            let (event, marker) = self.next()?;
+            match event {
+                Event::StreamStart | Event::DocumentStart | Event::Nothing => {}
+                Event::StreamEnd | Event::DocumentEnd => {
+                    let position = self.position(&marker);
+                    return Ok(SourceNode::Scalar {
+                        span: Span {
+                            start: position,
+                            end: position,
+                        },
+                        value: String::new(),
+                    });
+                }
+                event => return self.node(event, marker),
+            }
+        }
+    }
+
+    fn node(&mut self, event: Event, marker: Marker) -> Result<SourceNode, ScanError> {
+        let start = self.position(&marker);
+        match event {
+            Event::Scalar(value, style, _, _) => {
+                let (start, end) = match style {
+                    TScalarStyle::Plain => {
+                        let len = plain_len(&self.source[start.offset..], &value);
+                        (start, self.advance(start, len))
+                    }
+                    TScalarStyle::SingleQuoted | TScalarStyle::DoubleQuoted => {
+                        let len = quoted_len(&self.source[start.offset..]);
+                        (start, self.advance(start, len))
+                    }
+                    TScalarStyle::Literal | TScalarStyle::Folded => {
+                        // the marker is at the content; the node starts at
+                        // its `|` or `>` header and ends after its last
+                        // non-blank character
+                        let next = self.next()?;
+                        let end = self.position(&next.1);
+                        self.pending = Some(next);
+                        let header = block_header(&self.source[..start.offset]);
+                        let content = self.source[..end.offset].trim_end().len();
+                        (
+                            self.retreat(start, start.offset - header.unwrap_or(start.offset)),
+                            self.retreat(end, end.offset - content.max(start.offset)),
+                        )
+                    }
+                };
+                Ok(SourceNode::Scalar {
+                    span: Span { start, end },
+                    value,
+                })
+            }
+            Event::SequenceStart(..) => {
+                let mut items = Vec::new();
+                loop {
+                    let (event, marker) = self.next()?;
+                    if event == Event::SequenceEnd {
+                        let end = self.collection_end(&marker, items.last(), ']');
+                        return Ok(SourceNode::Sequence {
+                            span: Span { start, end },
+                            items,
+                        });
+                    }
+                    items.push(self.node(event, marker)?);
+                }
+            }
+            Event::MappingStart(..) => {
+                let mut entries: Vec<(SourceNode, SourceNode)> = Vec::new();
+                loop {
+                    let (event, marker) = self.next()?;
+                    if event == Event::MappingEnd {
+                        let end = self.collection_end(
+                            &marker,
+                            entries.last().map(|(_, value)| value),
+                            '}',
+                        );
+                        // a block mapping's marker is at its first `:`
+                        let start = match entries.first() {
+                            Some((key, _)) if key.span().start < start => key.span().start,
+                            _ => start,
+                        };
+                        return Ok(SourceNode::Mapping {
+                            span: Span { start, end },
+                            entries,
+                        });
+                    }
+                    let key = self.node(event, marker)?;
+                    let (event, marker) = self.next()?;
+                    let value = self.node(event, marker)?;
+                    entries.push((key, value));
+                }
+            }
+            _ => {
+                let len = self.source[start.offset..]
+                    .find(|c: char| c.is_whitespace() || matches!(c, ',' | ']' | '}'))
+                    .unwrap_or(self.source.len() - start.offset);
+                Ok(SourceNode::Alias {
+                    span: Span {
+                        start,
+                        end: self.advance(start, len),
+                    },
+                })
+            }
+        }
+    }
+}
+
+/// Length of the quoted scalar at the start of `source`, including its quotes
+fn quoted_len(source: &str) -> usize {
+    let Some(quote) = source.chars().next() else {
+        return 0;
+    };
+    let mut chars = source.char_indices().skip(1).peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if quote == '"' => {
+                chars.next();
+            }
+            '\'' if quote == '\'' && chars.peek().is_some_and(|(_, c)| *c == '\'') => {
+                chars.next();
+            }
+            c if c == quote => return i + 1,
+            _ => {}
+        }
+    }
+    source.len()
+}
+
+/// Length of the plain scalar at the start of `source` whose value is
+/// `value`. A plain scalar may continue over several lines, which are folded
+/// into `value` with their indentation removed, so each run of whitespace in
+/// `value` matches any run of whitespace in `source`.
+fn plain_len(source: &str, value: &str) -> usize {
+    let mut len = 0;
+    let mut value = value.chars().peekable();
+    while let Some(expected) = value.next() {
+        let rest = &source[len..];
+        if expected.is_whitespace() {
+            while value.next_if(|c| c.is_whitespace()).is_some() {}
+            len += rest.len() - rest.trim_start().len();
+        } else if rest.starts_with(expected) {
+            len += expected.len_utf8();
+        } else {
+            break;
+        }
+    }
+    len
+}
+
+/// Offset of the `|` or `>` header of a block scalar whose content starts at
+/// the end of `before`
+fn block_header(before: &str) -> Option<usize> {
+    let header = before.trim_end();
+    let indicator = header.trim_end_matches(|c: char| c == '+' || c == '-' || c.is_ascii_digit());
+    indicator.ends_with(['|', '>']).then(|| indicator.len() - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The source text of the node at `keys`
+    fn text<'a>(source: &'a str, keys: &[&str]) -> &'a str {
+        let map = SourceMap::new(source).unwrap();
+        let span = map.node(keys).unwrap().span();
+        &source[span.start.offset..span.end.offset]
+    }
+
+    #[test]
+    fn plain_scalars() {
+        let source = "a: x\nb: [1, two]\n";
+        assert_eq!(text(source, &["a"]), "x");
+        assert_eq!(text(source, &["b"]), "[1, two]");
+        let span = SourceMap::new(source).unwrap().node(&["b"]).unwrap().span();
+        assert_eq!((span.start.line, span.start.column), (2, 4));
+        assert_eq!((span.end.line, span.end.column), (2, 12));
+    }
+
+    #[test]
+    fn multi_line_plain_scalars() {
+        let source = "a: x\n   éé\nb: 1\n";
+        let map = SourceMap::new(source).unwrap();
+        let span = map.node(&["a"]).unwrap().span();
+        assert_eq!(&source[span.start.offset..span.end.offset], "x\n   éé");
+        assert_eq!((span.end.line, span.end.column), (2, 6));
+        assert_eq!(text(source, &["b"]), "1");
+
+        let source = "condition: and(succeeded(),\n    eq(1, 1))  # comment\n\n  \nnext: 1\n";
+        assert_eq!(
+            text(source, &["condition"]),
+            "and(succeeded(),\n    eq(1, 1))"
+        );
+
+        let source = "a: one\n\n  two\nb: 2\n";
+        assert_eq!(text(source, &["a"]), "one\n\n  two");
+    }
+
+    #[test]
+    fn non_ascii_scalars() {
+        let source = "name: café au lait\nnext: ü\n";
+        assert_eq!(text(source, &["name"]), "café au lait");
+        assert_eq!(text(source, &["next"]), "ü");
+        let span = SourceMap::new(source)
+            .unwrap()
+            .node(&["next"])
+            .unwrap()
+            .span();
+        assert_eq!((span.end.line, span.end.column), (2, 8));
+
+        let source: String = (0..500).map(|i| format!("k{i}: é{i}\n")).collect();
+        assert_eq!(text(&source, &["k499"]), "é499");
+        let span = SourceMap::new(&source)
+            .unwrap()
+            .node(&["k499"])
+            .unwrap()
+            .span();
+        assert_eq!((span.start.line, span.start.column), (500, 7));
+        assert_eq!(span.end.offset, source.len() - 1);
+    }
+
+    #[test]
+    fn quoted_scalars() {
+        let source = "a: 'it''s'\nb: \"multi\n  line\"\nc: 1\n";
+        assert_eq!(text(source, &["a"]), "'it''s'");
+        assert_eq!(text(source, &["b"]), "\"multi\n  line\"");
+        let span = SourceMap::new(source).unwrap().node(&["b"]).unwrap().span();
+        assert_eq!((span.end.line, span.end.column), (3, 8));
+    }
+
+    #[test]
+    fn block_scalars() {
+        let source = "script: |\n  echo one\n  echo two\n\nnext: >-\n  folded\n";
+        assert_eq!(text(source, &["script"]), "|\n  echo one\n  echo two");
+        assert_eq!(text(source, &["next"]), ">-\n  folded");
+    }
+
+    #[test]
+    fn collections() {
+        let source = "stages:\n- stage: a\n  jobs: []\n- {stage: b}\n";
+        let map = SourceMap::new(source).unwrap();
+        let stages = map.node(&["stages"]).unwrap();
+        let first = stages.index(0).unwrap().span();
+        assert_eq!(
+            &source[first.start.offset..first.end.offset],
+            "stage: a\n  jobs: []"
+        );
+        let second = stages.index(1).unwrap().span();
+        assert_eq!(
+            &source[second.start.offset..second.end.offset],
+            "{stage: b}"
+        );
+    }
+}
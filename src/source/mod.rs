@@ -0,0 +1,12 @@
+//! Where things are in the pipeline's YAML source
+//!
+//! Parsing with [`parse::from_str`](crate::source::parse::from_str) keeps a
+//! [`map::SourceMap`](crate::source::map::SourceMap) alongside the parsed
+//! value, so diagnostics can point at a line and column, and
+//! [`edit::Document`](crate::source::edit::Document) uses it to change the
+//! source without disturbing comments or layout.
+
+pub mod edit;
+pub mod map;
+pub mod parse;
+pub(crate) mod text;
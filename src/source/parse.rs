@@ -0,0 +1,215 @@
+//! Parse pipeline YAML and keep track of where everything came from
+
+use std::error::Error;
+
+use serde::de::DeserializeOwned;
+
+use crate::{
+    core::v1::{tagged, unknown},
+    source::map::{Segment, SourceMap, SourceNode},
+};
+
+/// A value parsed from YAML along with the location of each of its nodes
+#[derive(PartialEq, Debug)]
+pub struct Parsed<T> {
+    /// The parsed value
+    pub value: T,
+
+    /// The location of every node in the source
+    pub source: SourceMap,
+}
+
+/// Parse YAML into `T` (such as a [`Pipeline`](crate::core::v1::pipeline::Pipeline))
+/// and record the source span of every node. Like plain `serde_yaml`, this
+/// rejects keys this crate doesn't know.
+///
+/// Errors in a stage, job, step, variable or `dependsOn` point at the
+/// innermost of those nodes the problem is in.
+pub fn from_str<T: DeserializeOwned>(source: &str) -> Result<Parsed<T>, Box<dyn Error>> {
+    let value = serde_yaml::from_str(source).map_err(|e| locate(e, source))?;
+    let source = SourceMap::new(source)?;
+    Ok(Parsed { value, source })
+}
+
+/// Parse YAML like [`from_str`], but keep the keys this crate doesn't know in
+/// the `unknown` map of the node they're in, so they can be reported with
+/// [`unknown_field_diagnostics`](crate::validator::unknown::unknown_field_diagnostics)
+/// instead
+pub fn from_str_lenient<T: DeserializeOwned>(source: &str) -> Result<Parsed<T>, Box<dyn Error>> {
+    unknown::lenient(|| from_str(source))
+}
+
+/// Stages, jobs and the like are read as a whole before their variant is
+/// chosen, so `serde_yaml` reports errors inside them where the outermost of
+/// them starts. After its own path to that node, the message alternates
+/// between the kind of each node and the path from it to the next one, e.g.
+/// `stages[0]: stage: jobs[0]: job: steps[1]: ...`, so follow those paths
+/// through the source map and report where the innermost node starts.
+fn locate(error: serde_yaml::Error, source: &str) -> Box<dyn Error> {
+    let (Some(location), Ok(map)) = (error.location(), SourceMap::new(source)) else {
+        return error.into();
+    };
+    let Some(start) = starting_at(&map.root, location.line(), location.column()) else {
+        return error.into();
+    };
+    let message = tagged::message(&error);
+    let mut parts = message.split(": ");
+    if !std::ptr::eq(start, &map.root) {
+        parts.next();
+    }
+    let mut node = start;
+    // skip the kind of each node, then follow the path to the next one
+    while parts.next().is_some() {
+        match parts.next().and_then(path).and_then(|path| node.at(&path)) {
+            Some(next) => node = next,
+            None => break,
+        }
+    }
+    if std::ptr::eq(node, start) {
+        return error.into();
+    }
+    let start = node.span().start;
+    format!("{message} at line {} column {}", start.line, start.column).into()
+}
+
+/// The outermost node at or under `node` that starts at `line` and `column`
+fn starting_at(node: &SourceNode, line: usize, column: usize) -> Option<&SourceNode> {
+    let span = node.span();
+    let position = (line, column);
+    if (span.start.line, span.start.column) == position {
+        return Some(node);
+    }
+    if position < (span.start.line, span.start.column)
+        || position > (span.end.line, span.end.column)
+    {
+        return None;
+    }
+    match node {
+        SourceNode::Sequence { items, .. } => items
+            .iter()
+            .find_map(|item| starting_at(item, line, column)),
+        SourceNode::Mapping { entries, .. } => entries
+            .iter()
+            .find_map(|(_, value)| starting_at(value, line, column)),
+        _ => None,
+    }
+}
+
+/// A path as `serde_yaml` writes it in errors, e.g. `jobs[0].steps` or `.[1]`
+fn path(text: &str) -> Option<Vec<Segment<'_>>> {
+    if text.is_empty() || text.contains(char::is_whitespace) {
+        return None;
+    }
+    let mut path = Vec::new();
+    for part in text.split('.') {
+        let (key, mut indexes) = part.split_at(part.find('[').unwrap_or(part.len()));
+        if !key.is_empty() {
+            path.push(Segment::Key(key));
+        }
+        while let Some(rest) = indexes.strip_prefix('[') {
+            let (index, rest) = rest.split_once(']')?;
+            path.push(Segment::Index(index.parse().ok()?));
+            indexes = rest;
+        }
+        if !indexes.is_empty() {
+            return None;
+        }
+    }
+    Some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::v1::{job::Job, stage::Stage, step::Step};
+
+    use super::*;
+
+    fn error(source: &str) -> String {
+        from_str::<Vec<Stage>>(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn parses_with_source_map() {
+        let parsed = from_str::<Vec<Stage>>("- stage: a\n  jobs: []\n").unwrap();
+        assert_eq!(parsed.value.len(), 1);
+        assert!(parsed.source.root.index(0).is_some());
+    }
+
+    #[test]
+    fn errors_point_at_the_innermost_node() {
+        let source = "\
+- stage: build
+  jobs:
+  - job: compile
+    steps:
+    - task: Build@1
+    - bash: echo
+- stage: test
+  jobs: []
+";
+        assert_eq!(
+            error(source),
+            ".[0]: stage: jobs[0]: job: steps[1]: unsupported step `bash`; \
+             expected `checkout`, `task` or `template` at line 6 column 7"
+        );
+
+        let source = "\
+- stage: build
+  jobs: []
+- stage: test
+  dependsOn: build
+";
+        assert_eq!(
+            error(source),
+            ".[1]: stage: missing field `jobs` at line 3 column 3"
+        );
+
+        let source = "\
+- stage: build
+  jobs: []
+  dependsOn: [a, [b]]
+";
+        assert_eq!(
+            error(source),
+            ".[0]: stage: dependsOn: dependsOn: .[1]: invalid type: sequence, \
+             expected a string at line 3 column 18"
+        );
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected_unless_lenient() {
+        let source = "\
+- stage: build
+  jobs:
+  - job: compile
+    steps:
+    - task: Build@1
+      inputz: {}
+";
+        assert_eq!(
+            error(source),
+            ".[0]: stage: jobs[0]: job: steps[0]: task step: unknown field `inputz` \
+             at line 5 column 7"
+        );
+        let parsed = from_str_lenient::<Vec<Stage>>(source).unwrap();
+        let Stage::Stage(stage) = &parsed.value[0] else {
+            unreachable!()
+        };
+        let Job::Job(job) = &stage.jobs[0] else {
+            unreachable!()
+        };
+        let Step::Step(task) = &job.steps[0] else {
+            unreachable!()
+        };
+        assert!(task.unknown.contains_key("inputz"));
+    }
+
+    #[test]
+    fn other_errors_are_unchanged() {
+        assert_eq!(
+            error("stage: build\n"),
+            "invalid type: map, expected a sequence"
+        );
+        assert!(error("- [unclosed\n").contains("line 2"));
+    }
+}
@@ -0,0 +1,18 @@
+//! Offsets within YAML text
+
+/// Offset of the end of the line containing `offset`, before the newline
+pub(crate) fn line_end(text: &str, offset: usize) -> usize {
+    text[offset..].find('\n').map_or(text.len(), |i| offset + i)
+}
+
+/// Offset of the `-` of the block sequence item that starts at `offset`,
+/// which may be followed by an anchor or tag
+pub(crate) fn dash(text: &str, offset: usize) -> usize {
+    let start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let before = &text[start..offset];
+    if before.trim_end().ends_with('-') {
+        start + before.trim_end().len() - 1
+    } else {
+        before.rfind("- ").map_or(offset, |i| start + i)
+    }
+}
@@ -0,0 +1,411 @@
+//! Generate a Rust parameters type from a template's `parameters:` block
+//!
+//! The generated struct derives [`Parameterized`](super::parameterized::Parameterized)
+//! and [`Declare`](super::declare::Declare), so building it needs the `derive`
+//! feature. A stage, job or step parameter whose default this crate can't read
+//! is generated as a YAML `Value`, like an `object` parameter.
+//!
+//! Run [`generate`] from a build script and include its output:
+//!
+//! ```ignore
+//! // build.rs
+//! let out = Path::new(&env::var("OUT_DIR")?).join("entrypoint.rs");
+//! codegen::generate("templates/entrypoint.yml", "EntrypointParameters", out)?;
+//!
+//! // src/lib.rs
+//! include!(concat!(env!("OUT_DIR"), "/entrypoint.rs"));
+//! ```
+
+use std::{error::Error, fmt::Write, fs, path::Path};
+
+use serde::de::DeserializeOwned;
+use serde_yaml::{Mapping, Value};
+
+use crate::{
+    core::v1::{job::Job, pipeline::PipelineParameter, stage::Stage, step::Step},
+    templates::template::declared_parameters,
+};
+
+/// Read the template at `template`, write a struct named `name` for its
+/// parameters to `out`, and tell Cargo to run the build script again when the
+/// template changes
+pub fn generate(
+    template: impl AsRef<Path>,
+    name: &str,
+    out: impl AsRef<Path>,
+) -> Result<(), Box<dyn Error>> {
+    let template = template.as_ref();
+    let source = fs::read_to_string(template)?;
+    let code = parameters_struct(&source, name, &template.display().to_string())
+        .map_err(|e| format!("{}: {e}", template.display()))?;
+    fs::write(out, code)?;
+    println!("cargo:rerun-if-changed={}", template.display());
+    Ok(())
+}
+
+/// Rust source of a struct named `name` with a field for each parameter the
+/// template `source` declares. Errors name the template `template_name`.
+pub fn parameters_struct(
+    source: &str,
+    name: &str,
+    template_name: &str,
+) -> Result<String, Box<dyn Error>> {
+    let mut document: Mapping = serde_yaml::from_str(source)?;
+    let parameters = declared_parameters(document.remove("parameters"))?;
+    let fields = parameters
+        .iter()
+        .map(|parameter| Field::new(parameter, name))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut code = String::new();
+    writeln!(
+        code,
+        "// Generated by azure-pipelines-rs from {template_name}. Don't edit."
+    )?;
+    writeln!(code)?;
+    writeln!(code, "/// Parameters of the `{template_name}` template")?;
+    writeln!(code, "#[derive(")?;
+    writeln!(code, "    Debug,")?;
+    writeln!(code, "    PartialEq,")?;
+    writeln!(
+        code,
+        "    ::azure_pipelines_rs::templates::parameterized::Parameterized,"
+    )?;
+    writeln!(
+        code,
+        "    ::azure_pipelines_rs::templates::declare::Declare,"
+    )?;
+    writeln!(code, ")]")?;
+    if fields.iter().any(|field| field.doc.is_empty()) {
+        writeln!(code, "#[allow(missing_docs)]")?;
+    }
+    writeln!(code, "#[parameterized(template = {template_name:?})]")?;
+    writeln!(code, "#[serde(deny_unknown_fields)]")?;
+    writeln!(code, "pub struct {name} {{")?;
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            writeln!(code)?;
+        }
+        field.write(&mut code)?;
+    }
+    writeln!(code, "}}")?;
+
+    let defaults: Vec<&Field> = fields
+        .iter()
+        .filter(|field| field.default.is_some())
+        .collect();
+    if !defaults.is_empty() {
+        writeln!(code)?;
+        writeln!(code, "impl {name} {{")?;
+        for (i, field) in defaults.iter().enumerate() {
+            if i > 0 {
+                writeln!(code)?;
+            }
+            field.write_default(&mut code, template_name)?;
+        }
+        writeln!(code, "}}")?;
+    }
+    Ok(code)
+}
+
+/// A field of the generated struct
+struct Field<'a> {
+    parameter: &'a PipelineParameter,
+    ident: String,
+    rust_type: RustType,
+    doc: Vec<String>,
+    /// Path of the function returning the default, if there is one
+    default: Option<String>,
+}
+
+/// The Rust type a parameter is read into
+#[derive(PartialEq, Clone, Copy)]
+enum RustType {
+    String,
+    Integer,
+    Float,
+    Bool,
+    Value,
+    Step,
+    Steps,
+    Job,
+    Jobs,
+    Stage,
+    Stages,
+}
+
+impl RustType {
+    fn for_parameter(parameter: &PipelineParameter) -> Self {
+        match parameter.parameter_type.as_str() {
+            "string" | "filePath" => RustType::String,
+            "number" => {
+                let integers = parameter
+                    .default
+                    .iter()
+                    .chain(&parameter.values)
+                    .all(|value| value.as_i64().is_some() || !value.is_number());
+                if integers {
+                    RustType::Integer
+                } else {
+                    RustType::Float
+                }
+            }
+            "boolean" => RustType::Bool,
+            "step" if default_is::<Step>(parameter) => RustType::Step,
+            "stepList" if default_is::<Vec<Step>>(parameter) => RustType::Steps,
+            "job" if default_is::<Job>(parameter) => RustType::Job,
+            "jobList" if default_is::<Vec<Job>>(parameter) => RustType::Jobs,
+            "stage" if default_is::<Stage>(parameter) => RustType::Stage,
+            "stageList" if default_is::<Vec<Stage>>(parameter) => RustType::Stages,
+            // including stages, jobs and steps whose default this crate can't read
+            _ => RustType::Value,
+        }
+    }
+
+    fn path(&self) -> &'static str {
+        match self {
+            RustType::String => "String",
+            RustType::Integer => "i64",
+            RustType::Float => "f64",
+            RustType::Bool => "bool",
+            RustType::Value => "::azure_pipelines_rs::__private::serde_yaml::Value",
+            RustType::Step => "::azure_pipelines_rs::core::v1::step::Step",
+            RustType::Steps => "Vec<::azure_pipelines_rs::core::v1::step::Step>",
+            RustType::Job => "::azure_pipelines_rs::core::v1::job::Job",
+            RustType::Jobs => "Vec<::azure_pipelines_rs::core::v1::job::Job>",
+            RustType::Stage => "::azure_pipelines_rs::core::v1::stage::Stage",
+            RustType::Stages => "Vec<::azure_pipelines_rs::core::v1::stage::Stage>",
+        }
+    }
+
+    /// The parameter type `#[derive(Declare)]` declares this type as
+    fn declared_as(&self) -> &'static str {
+        match self {
+            RustType::String => "string",
+            RustType::Integer | RustType::Float => "number",
+            RustType::Bool => "boolean",
+            RustType::Value => "object",
+            RustType::Step => "step",
+            RustType::Steps => "stepList",
+            RustType::Job => "job",
+            RustType::Jobs => "jobList",
+            RustType::Stage => "stage",
+            RustType::Stages => "stageList",
+        }
+    }
+}
+
+impl<'a> Field<'a> {
+    fn new(parameter: &'a PipelineParameter, struct_name: &str) -> Result<Self, Box<dyn Error>> {
+        if parameter.name.is_empty() {
+            return Err("a parameter has no name".into());
+        }
+        let ident = ident(&parameter.name);
+        let default = parameter
+            .default
+            .as_ref()
+            .map(|_| format!("{struct_name}::default_{}", ident.trim_start_matches("r#")));
+        Ok(Field {
+            parameter,
+            rust_type: RustType::for_parameter(parameter),
+            doc: parameter
+                .display_name
+                .iter()
+                .flat_map(|display_name| display_name.lines())
+                .map(str::to_string)
+                .collect(),
+            ident,
+            default,
+        })
+    }
+
+    fn write(&self, code: &mut String) -> Result<(), Box<dyn Error>> {
+        for line in &self.doc {
+            writeln!(code, "    /// {line}")?;
+        }
+
+        let mut parameterized = Vec::new();
+        if self.parameter.parameter_type != self.rust_type.declared_as() {
+            parameterized.push(format!("type = {:?}", self.parameter.parameter_type));
+        }
+        let values: Option<Vec<String>> = self.parameter.values.iter().map(literal).collect();
+        if let Some(values) = values.filter(|values| !values.is_empty()) {
+            parameterized.push(format!("values({})", values.join(", ")));
+        }
+        if !parameterized.is_empty() {
+            writeln!(code, "    #[parameterized({})]", parameterized.join(", "))?;
+        }
+
+        let mut serde = Vec::new();
+        if self.ident.trim_start_matches("r#") != self.parameter.name {
+            serde.push(format!("rename = {:?}", self.parameter.name));
+        }
+        if let Some(default) = &self.default {
+            serde.push(format!("default = {default:?}"));
+        }
+        if !serde.is_empty() {
+            writeln!(code, "    #[serde({})]", serde.join(", "))?;
+        }
+
+        writeln!(code, "    pub {}: {},", self.ident, self.rust_type.path())?;
+        Ok(())
+    }
+
+    fn write_default(&self, code: &mut String, template_name: &str) -> Result<(), Box<dyn Error>> {
+        let Some(default) = &self.parameter.default else {
+            return Ok(());
+        };
+        let name = self.ident.trim_start_matches("r#");
+        let ty = self.rust_type.path();
+        let value = match (self.rust_type, default) {
+            (RustType::String, Value::String(value)) => format!("String::from({value:?})"),
+            (RustType::Integer | RustType::Float, value @ Value::Number(_))
+            | (RustType::Bool, value @ Value::Bool(_)) => literal(value).unwrap_or_default(),
+            (_, value) => format!(
+                "::azure_pipelines_rs::__private::serde_yaml::from_str({:?})\n            .expect(\"the default of `{}` in {template_name}\")",
+                serde_yaml::to_string(value)?,
+                self.parameter.name
+            ),
+        };
+        writeln!(code, "    fn default_{name}() -> {ty} {{")?;
+        writeln!(code, "        {value}")?;
+        writeln!(code, "    }}")?;
+        Ok(())
+    }
+}
+
+/// Whether a parameter's default, if it has one, can be read as a `T`
+fn default_is<T: DeserializeOwned>(parameter: &PipelineParameter) -> bool {
+    parameter
+        .default
+        .as_ref()
+        .is_none_or(|default| serde_yaml::from_value::<T>(default.clone()).is_ok())
+}
+
+/// A Rust literal for a scalar value
+fn literal(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(format!("{value:?}")),
+        Value::Bool(value) => Some(value.to_string()),
+        Value::Number(number) if number.is_f64() => {
+            number.as_f64().map(|value| format!("{value:?}"))
+        }
+        Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    }
+}
+
+/// A `snake_case` field name for a parameter name
+fn ident(name: &str) -> String {
+    let mut ident = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 && !ident.ends_with('_') {
+                ident.push('_');
+            }
+            ident.extend(c.to_lowercase());
+        } else if c.is_alphanumeric() {
+            ident.push(c);
+        } else if !ident.ends_with('_') {
+            ident.push('_');
+        }
+    }
+    if ident.starts_with(|c: char| c.is_numeric()) {
+        ident.insert(0, '_');
+    }
+    match ident.as_str() {
+        "as" | "async" | "await" | "break" | "const" | "continue" | "dyn" | "else" | "enum"
+        | "extern" | "false" | "fn" | "for" | "gen" | "if" | "impl" | "in" | "let" | "loop"
+        | "match" | "mod" | "move" | "mut" | "pub" | "ref" | "return" | "static" | "struct"
+        | "trait" | "true" | "type" | "unsafe" | "use" | "where" | "while" | "yield" => {
+            format!("r#{ident}")
+        }
+        _ => ident,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEMPLATE: &str = "\
+parameters:
+- name: poolName
+  displayName: Pool to build on
+  type: string
+  default: linux
+  values: [linux, windows]
+- name: retries
+  type: number
+  default: 2
+- name: ratio
+  type: number
+  default: 0.5
+- name: type
+  type: boolean
+- name: buildSteps
+  type: stepList
+  default:
+  - task: Bash@3
+- name: deploy
+  type: stage
+  default:
+    unknownThing: 1
+steps: []
+";
+
+    #[test]
+    fn generates_a_struct_for_the_parameters() {
+        assert_eq!(
+            parameters_struct(TEMPLATE, "BuildParameters", "build.yml").unwrap(),
+            include_str!("testdata/build_parameters.rs")
+        );
+    }
+
+    #[test]
+    fn field_names() {
+        assert_eq!(ident("poolName"), "pool_name");
+        assert_eq!(ident("build-steps"), "build_steps");
+        assert_eq!(ident("2fa"), "_2fa");
+        assert_eq!(ident("type"), "r#type");
+    }
+
+    #[test]
+    fn parameters_must_be_named() {
+        let err = parameters_struct("parameters:\n- name: ''\n  type: string\n", "P", "t.yml");
+        assert_eq!(err.unwrap_err().to_string(), "a parameter has no name");
+    }
+
+    #[cfg(feature = "derive")]
+    mod generated {
+        use indexmap::IndexMap;
+
+        use crate::templates::{
+            declare::Declare, parameterized::Parameterized, template::declared_parameters,
+        };
+
+        use super::TEMPLATE;
+
+        include!("testdata/build_parameters.rs");
+
+        #[test]
+        fn reads_parameters_with_defaults() {
+            let parameters: IndexMap<String, serde_yaml::Value> =
+                serde_yaml::from_str("{type: true, retries: 5}").unwrap();
+            let read = BuildParameters::get_parameters(&parameters).unwrap();
+            assert_eq!(read.pool_name, "linux");
+            assert_eq!(read.retries, 5);
+            assert_eq!(read.ratio, 0.5);
+            assert!(read.r#type);
+            assert_eq!(read.build_steps.len(), 1);
+            assert_eq!(read.deploy["unknownThing"], 1);
+        }
+
+        #[test]
+        fn declares_what_the_template_declares() {
+            let template: serde_yaml::Mapping = serde_yaml::from_str(TEMPLATE).unwrap();
+            let declared = declared_parameters(template.get("parameters").cloned()).unwrap();
+            assert_eq!(BuildParameters::declarations().unwrap(), declared);
+        }
+    }
+}
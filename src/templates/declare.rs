@@ -0,0 +1,295 @@
+//! Declare a template's parameters from the type they're read into
+//!
+//! A template's `parameters:` block and the Rust type its parameters are read
+//! into describe the same thing. [`Declare`] generates the block from the
+//! type, and [`check`] finds where a template's block has drifted from it.
+
+use std::error::Error;
+
+use serde::Serialize;
+use serde_yaml::{Mapping, Value};
+
+#[cfg(feature = "derive")]
+pub use azure_pipelines_rs_derive::Declare;
+
+use crate::{
+    core::v1::pipeline::PipelineParameter,
+    source::{map::SourceNode, parse},
+    templates::template::declared_parameters,
+    validator::diagnostic::{AstPath, Diagnostic, Span, codes},
+};
+
+/// A type whose fields are a template's parameters
+pub trait Declare {
+    /// The template's `parameters:` declaration
+    fn declarations() -> Result<Vec<PipelineParameter>, Box<dyn Error>>;
+}
+
+/// The `parameters:` block declaring `declarations`, as YAML
+pub fn to_yaml(declarations: &[PipelineParameter]) -> Result<String, Box<dyn Error>> {
+    #[derive(Serialize)]
+    struct Block<'a> {
+        parameters: &'a [PipelineParameter],
+    }
+
+    Ok(serde_yaml::to_string(&Block {
+        parameters: declarations,
+    })?)
+}
+
+/// Compare `declarations` with the `parameters:` block of a template's source,
+/// reporting every parameter that only one of them declares or that they
+/// declare differently
+pub fn check(
+    declarations: &[PipelineParameter],
+    template: &str,
+) -> Result<Vec<Diagnostic>, Box<dyn Error>> {
+    let parsed = parse::from_str::<Mapping>(template)?;
+    let declared = declared_parameters(parsed.value.get("parameters").cloned())?;
+    let root = &parsed.source.root;
+    // a parameter's node, in either the list or the older mapping syntax
+    let node = |index: usize, name: &str| {
+        root.at(&["parameters".into(), index.into()])
+            .or_else(|| root.at(&["parameters".into(), name.into()]))
+    };
+    let parameters = AstPath::default().field("parameters");
+
+    let mut diagnostics = Vec::new();
+    for expected in declarations {
+        let path = parameters.field(&expected.name);
+        let Some((index, found)) = declared
+            .iter()
+            .enumerate()
+            .find(|(_, found)| found.name == expected.name)
+        else {
+            diagnostics.push(
+                Diagnostic::error(
+                    codes::PARAMETER_DECLARATION,
+                    format!("the template doesn't declare `{}`", expected.name),
+                )
+                .at(path)
+                .with_span(root.at(&["parameters".into()]).map(SourceNode::span)),
+            );
+            continue;
+        };
+
+        let properties = [
+            (
+                "type",
+                Some(show_str(&found.parameter_type)),
+                Some(show_str(&expected.parameter_type)),
+            ),
+            (
+                "displayName",
+                found.display_name.as_deref().map(show_str),
+                expected.display_name.as_deref().map(show_str),
+            ),
+            (
+                "default",
+                found.default.as_ref().map(show),
+                expected.default.as_ref().map(show),
+            ),
+            (
+                "values",
+                (!found.values.is_empty()).then(|| show_all(&found.values)),
+                (!expected.values.is_empty()).then(|| show_all(&expected.values)),
+            ),
+        ];
+        for (property, in_template, in_type) in properties {
+            if in_template == in_type {
+                continue;
+            }
+            let span: Option<Span> = node(index, &expected.name)
+                .and_then(|node| node.entry(property).map(|(_, value)| value).or(Some(node)))
+                .map(SourceNode::span);
+            diagnostics.push(
+                Diagnostic::error(
+                    codes::PARAMETER_DECLARATION,
+                    format!(
+                        "`{property}` is {} in the template, but {} in the parameters type",
+                        in_template.as_deref().unwrap_or("not set"),
+                        in_type.as_deref().unwrap_or("not set")
+                    ),
+                )
+                .at(path.field(property))
+                .with_span(span),
+            );
+        }
+    }
+
+    for (index, found) in declared.iter().enumerate() {
+        if declarations
+            .iter()
+            .all(|expected| expected.name != found.name)
+        {
+            diagnostics.push(
+                Diagnostic::error(
+                    codes::PARAMETER_DECLARATION,
+                    format!(
+                        "the template declares `{}`, which the parameters type doesn't have",
+                        found.name
+                    ),
+                )
+                .at(parameters.field(&found.name))
+                .with_span(node(index, &found.name).map(SourceNode::span)),
+            );
+        }
+    }
+    Ok(diagnostics)
+}
+
+fn show_str(value: &str) -> String {
+    format!("`{value}`")
+}
+
+fn show(value: &Value) -> String {
+    format!("`{}`", flow(value))
+}
+
+fn show_all(values: &[Value]) -> String {
+    show(&Value::Sequence(values.to_vec()))
+}
+
+/// A value on one line, in flow style
+fn flow(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(value) => value.to_string(),
+        Value::Number(value) => value.to_string(),
+        Value::String(value) => value.clone(),
+        Value::Sequence(items) => {
+            let items: Vec<String> = items.iter().map(flow).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Mapping(entries) => {
+            let entries: Vec<String> = entries
+                .iter()
+                .map(|(key, value)| format!("{}: {}", flow(key), flow(value)))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+        Value::Tagged(tagged) => format!("{} {}", tagged.tag, flow(&tagged.value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use azure_pipelines_rs_derive::Declare;
+    use serde::Deserialize;
+
+    use super::*;
+
+    fn default_os() -> String {
+        "linux".to_string()
+    }
+
+    #[derive(Deserialize, Declare)]
+    #[serde(rename_all = "camelCase")]
+    #[allow(dead_code)]
+    struct BuildParameters {
+        /// Operating system to build on
+        ///
+        /// Only the first paragraph is the display name.
+        #[parameterized(values("linux", "windows"))]
+        #[serde(default = "default_os")]
+        os: String,
+
+        #[parameterized(type = "filePath")]
+        build_script: String,
+
+        #[serde(default)]
+        retries: u32,
+
+        run_tests: Option<bool>,
+    }
+
+    const DECLARED: &str = "\
+parameters:
+- name: os
+  displayName: Operating system to build on
+  type: string
+  default: linux
+  values:
+  - linux
+  - windows
+- name: buildScript
+  type: filePath
+- name: retries
+  type: number
+  default: 0
+- name: runTests
+  type: boolean
+";
+
+    #[test]
+    fn declares_fields_as_parameters() {
+        let declarations = BuildParameters::declarations().unwrap();
+        assert_eq!(to_yaml(&declarations).unwrap(), DECLARED);
+    }
+
+    #[test]
+    fn matching_template() {
+        let declarations = BuildParameters::declarations().unwrap();
+        let template = format!("{DECLARED}steps: []\n");
+        assert!(check(&declarations, &template).unwrap().is_empty());
+    }
+
+    #[test]
+    fn drifted_template() {
+        let template = "\
+parameters:
+- name: os
+  displayName: Operating system to build on
+  type: string
+  default: windows
+  values: [linux, windows, macos]
+- name: retries
+  type: number
+  default: 0
+- name: runTests
+  type: boolean
+- name: verbose
+  type: boolean
+steps: []
+";
+        let declarations = BuildParameters::declarations().unwrap();
+        let diagnostics = check(&declarations, template).unwrap();
+        let found: Vec<(String, String, usize)> = diagnostics
+            .iter()
+            .map(|d| {
+                assert_eq!(d.code, codes::PARAMETER_DECLARATION);
+                let line = d.span.as_ref().unwrap().start.line;
+                (d.path.to_string(), d.message.clone(), line)
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "parameters > os > default".to_string(),
+                    "`default` is `windows` in the template, but `linux` in the parameters type"
+                        .to_string(),
+                    5
+                ),
+                (
+                    "parameters > os > values".to_string(),
+                    "`values` is `[linux, windows, macos]` in the template, but \
+                     `[linux, windows]` in the parameters type"
+                        .to_string(),
+                    6
+                ),
+                (
+                    "parameters > buildScript".to_string(),
+                    "the template doesn't declare `buildScript`".to_string(),
+                    2
+                ),
+                (
+                    "parameters > verbose".to_string(),
+                    "the template declares `verbose`, which the parameters type doesn't have"
+                        .to_string(),
+                    12
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,391 @@
+//! Expand template expressions (`${{ }}`) in template YAML
+//!
+//! <https://learn.microsoft.com/en-us/azure/devops/pipelines/process/template-expressions?view=azure-pipelines>
+
+use std::collections::BTreeMap;
+
+use serde_yaml::{Mapping, Value};
+
+use crate::{
+    core::v1::pipeline::PipelineParameter,
+    expressions::{
+        eval::{self, Context},
+        parser::{self, template_expression_end},
+    },
+};
+
+/// The values template expressions are evaluated against
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct ExpansionContext {
+    /// Template parameters, available as `parameters.<name>`
+    pub parameters: BTreeMap<String, eval::Value>,
+
+    /// Variables known at compile time, available as `variables.<name>`
+    pub variables: BTreeMap<String, eval::Value>,
+
+    /// Loop variables bound by `${{ each }}`
+    locals: BTreeMap<String, eval::Value>,
+}
+
+impl ExpansionContext {
+    /// Resolve the parameters a template is called with against the template's
+    /// declarations: declared defaults are used for parameters that aren't
+    /// passed, and parameters without a default must be passed.
+    pub fn for_call(declared: &[PipelineParameter], passed: &Mapping) -> Result<Self, String> {
+        let mut parameters = BTreeMap::new();
+        for parameter in declared {
+            let value = passed
+                .get(parameter.name.as_str())
+                .or(parameter.default.as_ref())
+                .ok_or_else(|| format!("missing required parameter {:?}", parameter.name))?;
+            parameters.insert(parameter.name.clone(), eval::Value::from(value));
+        }
+        Ok(ExpansionContext {
+            parameters,
+            ..Default::default()
+        })
+    }
+
+    fn with_local(&self, name: &str, value: eval::Value) -> Self {
+        let mut context = self.clone();
+        context.locals.insert(name.to_string(), value);
+        context
+    }
+}
+
+impl Context for ExpansionContext {
+    fn named_value(&self, name: &str) -> Option<eval::Value> {
+        if let Some(value) = self.locals.get(name) {
+            return Some(value.clone());
+        }
+        match name.to_ascii_lowercase().as_str() {
+            "parameters" => Some(eval::Value::Object(self.parameters.clone())),
+            "variables" => Some(eval::Value::Object(self.variables.clone())),
+            _ => None,
+        }
+    }
+
+    fn status(&self, function: &str, _names: &[String]) -> Result<bool, String> {
+        Err(format!(
+            "`{function}` can't be used in a template expression"
+        ))
+    }
+}
+
+/// A template expression directive used as a mapping key
+enum Directive<'a> {
+    If(&'a str),
+    ElseIf(&'a str),
+    Else,
+    Each {
+        variable: &'a str,
+        collection: &'a str,
+    },
+    Insert,
+}
+
+fn directive(key: &Value) -> Result<Option<Directive<'_>>, String> {
+    match key.as_str() {
+        Some(key) => directive_in(key),
+        None => Ok(None),
+    }
+}
+
+fn directive_in(key: &str) -> Result<Option<Directive<'_>>, String> {
+    let Some(inner) = whole_expression(key) else {
+        return Ok(None);
+    };
+    let directive = if let Some(condition) = inner.strip_prefix("if ") {
+        Directive::If(condition)
+    } else if let Some(condition) = inner.strip_prefix("elseif ") {
+        Directive::ElseIf(condition)
+    } else if inner == "else" {
+        Directive::Else
+    } else if inner == "insert" {
+        Directive::Insert
+    } else if let Some(rest) = inner.strip_prefix("each ") {
+        let (variable, collection) = rest
+            .split_once(" in ")
+            .ok_or_else(|| format!("expected `each <name> in <collection>`, found {inner:?}"))?;
+        Directive::Each {
+            variable: variable.trim(),
+            collection: collection.trim(),
+        }
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(directive))
+}
+
+/// The expression inside every `${{ }}` in `s`. A directive gives the
+/// expression it evaluates, e.g. `parameters.stages` for
+/// `${{ each stage in parameters.stages }}`.
+pub(crate) fn expressions(s: &str) -> Vec<&str> {
+    match directive_in(s) {
+        Ok(Some(Directive::If(condition) | Directive::ElseIf(condition))) => {
+            return vec![condition];
+        }
+        Ok(Some(Directive::Each { collection, .. })) => return vec![collection],
+        Ok(Some(Directive::Else | Directive::Insert)) => return Vec::new(),
+        Ok(None) | Err(_) => {}
+    }
+    if let Some(expression) = whole_expression(s) {
+        return vec![expression];
+    }
+
+    let mut found = Vec::new();
+    let mut rest = s;
+    while let Some(start) = rest.find("${{") {
+        let inner = &rest[start + 3..];
+        let Some(end) = template_expression_end(inner) else {
+            break;
+        };
+        found.push(inner[..end].trim());
+        rest = &inner[end + 2..];
+    }
+    found
+}
+
+/// The inside of `${{ ... }}` if that's all `s` contains
+fn whole_expression(s: &str) -> Option<&str> {
+    let inner = s.trim().strip_prefix("${{")?;
+    let end = template_expression_end(inner)?;
+    (end + 2 == inner.len()).then(|| inner[..end].trim())
+}
+
+fn evaluate(expression: &str, context: &ExpansionContext) -> Result<eval::Value, String> {
+    let expr = parser::parse(expression).map_err(|e| format!("{expression:?}: {e}"))?;
+    eval::evaluate(&expr, context).map_err(|e| format!("{expression:?}: {e}"))
+}
+
+/// Expand every template expression in `value`: `${{ if }}`, `${{ elseif }}`,
+/// `${{ else }}`, `${{ each }}` and `${{ insert }}` directives, whole-value
+/// expressions, and expressions embedded in strings
+pub fn expand(value: &Value, context: &ExpansionContext) -> Result<Value, String> {
+    match value {
+        Value::String(s) => expand_string(s, context),
+        Value::Sequence(items) => {
+            let mut expanded = Vec::new();
+            expand_sequence(items, context, &mut expanded)?;
+            Ok(Value::Sequence(expanded))
+        }
+        Value::Mapping(map) => {
+            let mut expanded = Mapping::new();
+            expand_mapping(map, context, &mut expanded)?;
+            Ok(Value::Mapping(expanded))
+        }
+        Value::Tagged(tagged) => expand(&tagged.value, context),
+        other => Ok(other.clone()),
+    }
+}
+
+fn expand_string(s: &str, context: &ExpansionContext) -> Result<Value, String> {
+    if let Some(expression) = whole_expression(s) {
+        return Ok(evaluate(expression, context)?.into());
+    }
+
+    let mut output = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find("${{") {
+        let inner = &rest[start + 3..];
+        let end = template_expression_end(inner)
+            .ok_or_else(|| format!("unterminated template expression in {s:?}"))?;
+        output.push_str(&rest[..start]);
+        output.push_str(&evaluate(inner[..end].trim(), context)?.to_display_string());
+        rest = &inner[end + 2..];
+    }
+    output.push_str(rest);
+    Ok(Value::String(output))
+}
+
+/// Expand the items of a sequence into `out`, splicing in the results of
+/// directives
+fn expand_sequence(
+    items: &[Value],
+    context: &ExpansionContext,
+    out: &mut Vec<Value>,
+) -> Result<(), String> {
+    let mut chain: Option<bool> = None;
+    for item in items {
+        if let Value::Mapping(map) = item
+            && map.len() == 1
+            && let Some((key, body)) = map.iter().next()
+            && let Some(directive) = directive(key)?
+        {
+            splice(directive, body, context, &mut chain, out)?;
+            continue;
+        }
+
+        chain = None;
+        match (item, expand(item, context)?) {
+            // a whole-value expression that produced a list, e.g.
+            // `- ${{ parameters.steps }}`, is spliced into the list
+            (Value::String(_), Value::Sequence(items)) => out.extend(items),
+            (_, expanded) => out.push(expanded),
+        }
+    }
+    Ok(())
+}
+
+/// Apply a directive in a sequence. `chain` tracks whether an earlier branch
+/// of an if/elseif/else chain was taken.
+fn splice(
+    directive: Directive<'_>,
+    body: &Value,
+    context: &ExpansionContext,
+    chain: &mut Option<bool>,
+    out: &mut Vec<Value>,
+) -> Result<(), String> {
+    let push_body = |context: &ExpansionContext, out: &mut Vec<Value>| match body {
+        Value::Sequence(items) => expand_sequence(items, context, out),
+        body => {
+            out.push(expand(body, context)?);
+            Ok(())
+        }
+    };
+
+    match directive {
+        Directive::If(condition) => {
+            let taken = evaluate(condition, context)?.is_truthy();
+            *chain = Some(taken);
+            if taken {
+                push_body(context, out)?;
+            }
+        }
+        Directive::ElseIf(condition) => {
+            let previous = chain.ok_or("`elseif` without a preceding `if`")?;
+            let taken = !previous && evaluate(condition, context)?.is_truthy();
+            *chain = Some(previous || taken);
+            if taken {
+                push_body(context, out)?;
+            }
+        }
+        Directive::Else => {
+            let previous = chain.take().ok_or("`else` without a preceding `if`")?;
+            if !previous {
+                push_body(context, out)?;
+            }
+        }
+        Directive::Each {
+            variable,
+            collection,
+        } => {
+            *chain = None;
+            for item in each_items(collection, context)? {
+                push_body(&context.with_local(variable, item), out)?;
+            }
+        }
+        Directive::Insert => {
+            return Err("`insert` can only be used in a mapping".to_string());
+        }
+    }
+    Ok(())
+}
+
+fn each_items(collection: &str, context: &ExpansionContext) -> Result<Vec<eval::Value>, String> {
+    Ok(match evaluate(collection, context)? {
+        eval::Value::Array(items) => items,
+        eval::Value::Object(map) => map
+            .into_iter()
+            .map(|(key, value)| {
+                let mut pair = BTreeMap::new();
+                pair.insert("key".to_string(), eval::Value::String(key));
+                pair.insert("value".to_string(), value);
+                eval::Value::Object(pair)
+            })
+            .collect(),
+        eval::Value::Null => Vec::new(),
+        other => return Err(format!("can't iterate over {other:?} in `each`")),
+    })
+}
+
+/// Expand the entries of a mapping into `out`, merging in the bodies of
+/// directives
+fn expand_mapping(
+    map: &Mapping,
+    context: &ExpansionContext,
+    out: &mut Mapping,
+) -> Result<(), String> {
+    let mut chain: Option<bool> = None;
+    for (key, body) in map {
+        let merge = |context: &ExpansionContext, out: &mut Mapping| match body {
+            Value::Mapping(body) => expand_mapping(body, context, out),
+            Value::Null => Ok(()),
+            other => Err(format!(
+                "expected a mapping inside directive, found {other:?}"
+            )),
+        };
+
+        match directive(key)? {
+            Some(Directive::If(condition)) => {
+                let taken = evaluate(condition, context)?.is_truthy();
+                chain = Some(taken);
+                if taken {
+                    merge(context, out)?;
+                }
+            }
+            Some(Directive::ElseIf(condition)) => {
+                let previous = chain.ok_or("`elseif` without a preceding `if`")?;
+                let taken = !previous && evaluate(condition, context)?.is_truthy();
+                chain = Some(previous || taken);
+                if taken {
+                    merge(context, out)?;
+                }
+            }
+            Some(Directive::Else) => {
+                let previous = chain.take().ok_or("`else` without a preceding `if`")?;
+                if !previous {
+                    merge(context, out)?;
+                }
+            }
+            Some(Directive::Each {
+                variable,
+                collection,
+            }) => {
+                chain = None;
+                for item in each_items(collection, context)? {
+                    merge(&context.with_local(variable, item), out)?;
+                }
+            }
+            Some(Directive::Insert) => {
+                chain = None;
+                if let Value::Mapping(inserted) = expand(body, context)? {
+                    out.extend(inserted);
+                }
+            }
+            None => {
+                chain = None;
+                out.insert(expand(key, context)?, expand(body, context)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn braces_in_string_literals() {
+        let mut context = ExpansionContext::default();
+        context
+            .parameters
+            .insert("x".to_string(), eval::Value::String("a".to_string()));
+
+        let source = "${{ format('{0}}}', parameters.x) }}";
+        assert_eq!(
+            expressions(&format!("name: {source} and ${{{{ 'b' }}}}")),
+            ["format('{0}}}', parameters.x)", "'b'"]
+        );
+        assert_eq!(
+            whole_expression(source),
+            Some("format('{0}}}', parameters.x)")
+        );
+        assert_eq!(
+            expand_string(&format!("[{source}]"), &context).unwrap(),
+            Value::String("[a}]".to_string())
+        );
+        assert!(expand_string("${{ '}}' ", &context).is_err());
+    }
+}
@@ -1 +1,6 @@
+pub mod codegen;
+pub mod declare;
+pub mod expand;
 pub mod parameterized;
+pub mod resolver;
+pub mod template;
@@ -1,10 +1,128 @@
-use std::{collections::HashMap, error::Error};
-
+use indexmap::IndexMap;
 use serde_yaml::Value;
 
+#[cfg(feature = "derive")]
+pub use azure_pipelines_rs_derive::Parameterized;
+
+use crate::validator::diagnostic::Diagnostic;
+
 pub trait Parameterized {
     type Parameters;
-    fn get_parameters(
-        hash_map: &HashMap<String, Value>,
-    ) -> Result<Self::Parameters, Box<dyn Error>>;
+
+    /// Read the parameters passed to a template. Problems are reported as
+    /// [`TEMPLATE_PARAMETER`](crate::validator::diagnostic::codes::TEMPLATE_PARAMETER)
+    /// errors at `parameters > <name>`, relative to the template call.
+    fn get_parameters(hash_map: &IndexMap<String, Value>) -> Result<Self::Parameters, Diagnostic>;
+}
+
+#[cfg(test)]
+mod tests {
+    use azure_pipelines_rs_derive::Parameterized;
+    use serde::Deserialize;
+
+    use crate::validator::diagnostic::{AstPath, codes};
+
+    use super::*;
+
+    #[derive(Deserialize, Parameterized, PartialEq, Debug)]
+    #[parameterized(template = "build.yml")]
+    #[serde(deny_unknown_fields, rename_all = "camelCase")]
+    struct BuildParameters {
+        pool_name: String,
+        #[serde(default)]
+        retries: u32,
+    }
+
+    fn parameters(yaml: &str) -> IndexMap<String, Value> {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn reads_parameters() {
+        assert_eq!(
+            BuildParameters::get_parameters(&parameters("poolName: linux")).unwrap(),
+            BuildParameters {
+                pool_name: "linux".to_string(),
+                retries: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn problems_are_diagnostics() {
+        for (yaml, name, message) in [
+            (
+                "retries: 2",
+                "poolName",
+                "template build.yml: missing parameter `poolName`",
+            ),
+            (
+                "{poolName: linux, retries: many}",
+                "retries",
+                "parameter `retries`",
+            ),
+            (
+                "{poolName: linux, os: windows}",
+                "os",
+                "unexpected parameter `os`",
+            ),
+        ] {
+            let diagnostic = BuildParameters::get_parameters(&parameters(yaml)).unwrap_err();
+            assert_eq!(diagnostic.code, codes::TEMPLATE_PARAMETER);
+            assert_eq!(
+                diagnostic.path,
+                AstPath::default().field("parameters").field(name)
+            );
+            assert!(
+                diagnostic.message.contains(message),
+                "{}",
+                diagnostic.message
+            );
+        }
+    }
+
+    fn default_os() -> String {
+        "linux".to_string()
+    }
+
+    #[derive(Deserialize, Parameterized, PartialEq, Debug, Default)]
+    #[serde(rename_all = "camelCase")]
+    struct TestParameters {
+        #[serde(rename = "os", default = "default_os")]
+        operating_system: String,
+        #[serde(alias = "suite")]
+        test_suite: Option<String>,
+        #[serde(skip)]
+        cache: Vec<String>,
+    }
+
+    #[test]
+    fn names_and_defaults_follow_serde() {
+        assert_eq!(
+            TestParameters::get_parameters(&parameters("suite: unit")).unwrap(),
+            TestParameters {
+                operating_system: "linux".to_string(),
+                test_suite: Some("unit".to_string()),
+                cache: Vec::new(),
+            }
+        );
+        let parameters = parameters("{os: windows, testSuite: e2e, unknown: ignored}");
+        let read = TestParameters::get_parameters(&parameters).unwrap();
+        assert_eq!(read.operating_system, "windows");
+        assert_eq!(read.test_suite.as_deref(), Some("e2e"));
+    }
+
+    #[test]
+    fn template_defaults_to_the_struct_name() {
+        let diagnostic = TestParameters::get_parameters(&parameters("os: [linux]")).unwrap_err();
+        assert!(
+            diagnostic.message.starts_with("template TestParameters:"),
+            "{}",
+            diagnostic.message
+        );
+        assert_eq!(
+            diagnostic.path,
+            AstPath::default().field("parameters").field("os")
+        );
+    }
 }
@@ -0,0 +1,111 @@
+//! Locate the contents of a template referenced by `template:`
+//!
+//! <https://learn.microsoft.com/en-us/azure/devops/pipelines/process/templates?view=azure-pipelines#use-other-repositories>
+
+use std::{collections::HashMap, error::Error, fs, path::PathBuf};
+
+/// Loads the YAML source of a template given the path written after
+/// `template:`, e.g. `jobs/build.yml` or `jobs/build.yml@tools`
+pub trait TemplateResolver {
+    /// Load the template's YAML source
+    fn resolve(&self, template: &str) -> Result<String, Box<dyn Error>>;
+}
+
+/// Resolves templates from directories on disk. Paths are relative to `root`,
+/// or to the directory registered for the repository alias after `@`.
+#[derive(Debug, Default)]
+pub struct FileResolver {
+    /// Directory holding this repository's templates
+    pub root: PathBuf,
+
+    /// Directories holding the templates of other repositories, keyed by
+    /// repository alias
+    pub repositories: HashMap<String, PathBuf>,
+}
+
+impl FileResolver {
+    /// Resolve templates relative to `root`, with no other repositories
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        FileResolver {
+            root: root.into(),
+            repositories: HashMap::new(),
+        }
+    }
+}
+
+impl TemplateResolver for FileResolver {
+    fn resolve(&self, template: &str) -> Result<String, Box<dyn Error>> {
+        let (path, root) = match template.rsplit_once('@') {
+            Some((path, "self")) => (path, &self.root),
+            Some((path, alias)) => (
+                path,
+                self.repositories
+                    .get(alias)
+                    .ok_or_else(|| format!("no directory registered for repository {alias}"))?,
+            ),
+            None => (template, &self.root),
+        };
+        let path = root.join(path.trim_start_matches('/'));
+        fs::read_to_string(&path)
+            .map_err(|e| format!("can't read template {}: {e}", path.display()).into())
+    }
+}
+
+/// The path of a template called from inside the template at `parent`, both
+/// as written after `template:`. Azure reads a relative path from the
+/// directory of the file that includes it, and a path starting with `/` from
+/// the root of that file's repository, unless it names a repository of its
+/// own after `@`.
+pub fn nested_path(parent: &str, template: &str) -> String {
+    if template.contains('@') {
+        return template.to_string();
+    }
+    let (parent, repository) = match parent.rsplit_once('@') {
+        Some((parent, repository)) => (parent, format!("@{repository}")),
+        None => (parent, String::new()),
+    };
+    if template.starts_with('/') {
+        return format!("{template}{repository}");
+    }
+
+    let directory = parent
+        .rsplit_once('/')
+        .map_or("", |(directory, _)| directory);
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in directory.split('/').chain(template.split('/')) {
+        match segment {
+            "" | "." => {}
+            ".." if segments.last().is_some_and(|last| *last != "..") => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    let root = if parent.starts_with('/') { "/" } else { "" };
+    format!("{root}{}{repository}", segments.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_paths() {
+        assert_eq!(nested_path("stages.yml", "deploy.yml"), "deploy.yml");
+        assert_eq!(nested_path("ci/stages.yml", "deploy.yml"), "ci/deploy.yml");
+        assert_eq!(
+            nested_path("ci/stages/all.yml", "../jobs/build.yml"),
+            "ci/jobs/build.yml"
+        );
+        assert_eq!(nested_path("/ci/stages.yml", "./x.yml"), "/ci/x.yml");
+        assert_eq!(nested_path("ci/stages.yml", "/jobs.yml"), "/jobs.yml");
+        assert_eq!(
+            nested_path("ci/stages.yml@tools", "deploy.yml"),
+            "ci/deploy.yml@tools"
+        );
+        assert_eq!(
+            nested_path("ci/stages.yml@tools", "steps.yml@self"),
+            "steps.yml@self"
+        );
+    }
+}
@@ -0,0 +1,110 @@
+//! A template file: its parameter declarations and the stages, jobs or steps
+//! it produces
+//!
+//! <https://learn.microsoft.com/en-us/azure/devops/pipelines/process/templates?view=azure-pipelines>
+
+use std::error::Error;
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+
+use crate::{
+    core::v1::{
+        job::Job,
+        pipeline::{PipelineParameter, PipelineVariable},
+        stage::Stage,
+        step::Step,
+        unknown,
+    },
+    templates::{
+        expand::{ExpansionContext, expand},
+        resolver::TemplateResolver,
+    },
+};
+
+/// A template after its template expressions have been expanded
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+pub struct Template {
+    /// The parameters the template declares
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parameters: Vec<PipelineParameter>,
+
+    /// Variables produced by a variable template
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variables: Vec<PipelineVariable>,
+
+    /// Stages produced by a stage template
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stages: Vec<Stage>,
+
+    /// Jobs produced by a job template
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub jobs: Vec<Job>,
+
+    /// Steps produced by a step template
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<Step>,
+
+    /// Keys this crate doesn't know, which are rejected unless parsed with
+    /// [`parse::from_str_lenient`](crate::source::parse::from_str_lenient)
+    #[serde(flatten, deserialize_with = "unknown::deserialize")]
+    pub unknown: Mapping,
+}
+
+impl Template {
+    /// Load a template through `resolver` and expand it with the parameters
+    /// passed at the call site
+    pub fn expand(
+        resolver: &dyn TemplateResolver,
+        template: &str,
+        parameters: &IndexMap<String, Value>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let source = resolver.resolve(template)?;
+        Self::expand_source(&source, parameters)
+            .map_err(|e| format!("template {template}: {e}").into())
+    }
+
+    /// Expand a template's YAML source with the parameters passed at the call
+    /// site. Keys this crate doesn't know are rejected.
+    pub fn expand_source(
+        source: &str,
+        parameters: &IndexMap<String, Value>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut document: Mapping = serde_yaml::from_str(source)?;
+        let declared = declared_parameters(document.remove("parameters"))?;
+
+        let passed: Mapping = parameters
+            .iter()
+            .map(|(name, value)| (Value::String(name.clone()), value.clone()))
+            .collect();
+        let context = ExpansionContext::for_call(&declared, &passed)?;
+        let expanded = expand(&Value::Mapping(document), &context)?;
+
+        let mut template: Template = serde_yaml::from_value(expanded)?;
+        template.parameters = declared;
+        Ok(template)
+    }
+}
+
+/// Parameters are declared as a list, or in the older syntax as a mapping of
+/// name to default value
+pub(crate) fn declared_parameters(
+    parameters: Option<Value>,
+) -> Result<Vec<PipelineParameter>, Box<dyn Error>> {
+    Ok(match parameters {
+        None => Vec::new(),
+        Some(Value::Mapping(map)) => map
+            .into_iter()
+            .map(|(name, default)| PipelineParameter {
+                name: name.as_str().unwrap_or_default().to_string(),
+                display_name: None,
+                parameter_type: "object".to_string(),
+                default: Some(default),
+                values: Vec::new(),
+                unknown: Mapping::new(),
+            })
+            .collect(),
+        Some(list) => serde_yaml::from_value(list)?,
+    })
+}
@@ -0,0 +1,57 @@
+// Generated by azure-pipelines-rs from build.yml. Don't edit.
+
+/// Parameters of the `build.yml` template
+#[derive(
+    Debug,
+    PartialEq,
+    ::azure_pipelines_rs::templates::parameterized::Parameterized,
+    ::azure_pipelines_rs::templates::declare::Declare,
+)]
+#[allow(missing_docs)]
+#[parameterized(template = "build.yml")]
+#[serde(deny_unknown_fields)]
+pub struct BuildParameters {
+    /// Pool to build on
+    #[parameterized(values("linux", "windows"))]
+    #[serde(rename = "poolName", default = "BuildParameters::default_pool_name")]
+    pub pool_name: String,
+
+    #[serde(default = "BuildParameters::default_retries")]
+    pub retries: i64,
+
+    #[serde(default = "BuildParameters::default_ratio")]
+    pub ratio: f64,
+
+    pub r#type: bool,
+
+    #[serde(rename = "buildSteps", default = "BuildParameters::default_build_steps")]
+    pub build_steps: Vec<::azure_pipelines_rs::core::v1::step::Step>,
+
+    #[parameterized(type = "stage")]
+    #[serde(default = "BuildParameters::default_deploy")]
+    pub deploy: ::azure_pipelines_rs::__private::serde_yaml::Value,
+}
+
+impl BuildParameters {
+    fn default_pool_name() -> String {
+        String::from("linux")
+    }
+
+    fn default_retries() -> i64 {
+        2
+    }
+
+    fn default_ratio() -> f64 {
+        0.5
+    }
+
+    fn default_build_steps() -> Vec<::azure_pipelines_rs::core::v1::step::Step> {
+        ::azure_pipelines_rs::__private::serde_yaml::from_str("- task: Bash@3\n")
+            .expect("the default of `buildSteps` in build.yml")
+    }
+
+    fn default_deploy() -> ::azure_pipelines_rs::__private::serde_yaml::Value {
+        ::azure_pipelines_rs::__private::serde_yaml::from_str("unknownThing: 1\n")
+            .expect("the default of `deploy` in build.yml")
+    }
+}
@@ -0,0 +1,344 @@
+use std::fmt;
+
+use crate::{
+    core::v1::{job::Job, stage::Stage, step::Step},
+    expressions::{
+        ast::{Expr, ExprKind},
+        functions::{self, FunctionKind},
+        parser,
+    },
+    validator::diagnostic::{AstPath, Diagnostic, PathSegment, codes},
+};
+
+/// Where a condition appears, which determines the functions and named values
+/// it may use
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum ConditionLevel {
+    /// `condition` of a stage
+    Stage,
+    /// `condition` of a job
+    Job,
+    /// `condition` of a step
+    Step,
+}
+
+impl ConditionLevel {
+    /// Whether a known named value may be used at this level
+    fn allows_named_value(&self, name: &str) -> bool {
+        match name {
+            "dependencies" => *self != ConditionLevel::Step,
+            "stagedependencies" => *self == ConditionLevel::Job,
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for ConditionLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConditionLevel::Stage => write!(f, "stage"),
+            ConditionLevel::Job => write!(f, "job"),
+            ConditionLevel::Step => write!(f, "step"),
+        }
+    }
+}
+
+/// Named values that may appear at the root of a condition
+const NAMED_VALUES: &[&str] = &[
+    "dependencies",
+    "pipeline",
+    "resources",
+    "stagedependencies",
+    "variables",
+];
+
+/// Check every `condition` in the given stages, along with the jobs and steps
+/// inside them, stopping at the first problem
+pub fn validate_conditions(stages: &[Stage]) -> Result<(), Diagnostic> {
+    match condition_diagnostics(stages).into_iter().next() {
+        Some(diagnostic) => Err(diagnostic),
+        None => Ok(()),
+    }
+}
+
+/// Check every `condition` in the given stages, along with the jobs and steps
+/// inside them, and report every problem found
+pub fn condition_diagnostics(stages: &[Stage]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut check = |condition: &str, level, path: AstPath| {
+        diagnostics.extend(
+            condition_problems(condition, level)
+                .into_iter()
+                .map(|d| d.at(path.field("condition"))),
+        );
+    };
+
+    for (index, stage) in stages.iter().enumerate() {
+        if let Stage::Stage(stage) = stage {
+            let stage_path = AstPath::default().join(PathSegment::Stage {
+                index,
+                name: stage.name.clone(),
+            });
+            if let Some(condition) = &stage.condition {
+                check(condition, ConditionLevel::Stage, stage_path.clone());
+            }
+            for (index, job) in stage.jobs.iter().enumerate() {
+                if let Job::Job(job) = job {
+                    let job_path = stage_path.join(PathSegment::Job {
+                        index,
+                        name: job.name.clone(),
+                    });
+                    if let Some(condition) = &job.condition {
+                        check(condition, ConditionLevel::Job, job_path.clone());
+                    }
+                    for (index, step) in job.steps.iter().enumerate() {
+                        if let Step::Step(step) = step
+                            && let Some(condition) = &step.condition
+                        {
+                            let step_path = job_path.join(PathSegment::Step {
+                                index,
+                                name: step.name.clone(),
+                            });
+                            check(condition, ConditionLevel::Step, step_path);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    diagnostics
+}
+
+/// Parse a single condition and check it for unknown functions, wrong numbers
+/// of arguments, and functions or named values that aren't available at
+/// `level`, stopping at the first problem
+pub fn check_condition(condition: &str, level: ConditionLevel) -> Result<(), Diagnostic> {
+    match condition_problems(condition, level).into_iter().next() {
+        Some(diagnostic) => Err(diagnostic),
+        None => Ok(()),
+    }
+}
+
+/// Parse a single condition and report every problem found by
+/// [`check_condition`]
+///
+/// Conditions containing template expressions (`${{ }}`) are only known after
+/// template expansion, so they are not checked.
+pub fn condition_problems(condition: &str, level: ConditionLevel) -> Vec<Diagnostic> {
+    if condition.contains("${{") {
+        return Vec::new();
+    }
+
+    let expr = match parser::parse(condition) {
+        Ok(expr) => expr,
+        Err(e) => return vec![Diagnostic::error(codes::CONDITION_SYNTAX, e.to_string())],
+    };
+
+    let mut diagnostics = Vec::new();
+    expr.walk(&mut |e| {
+        if let Err(diagnostic) = check_expr(e, level) {
+            diagnostics.push(diagnostic);
+        }
+    });
+    diagnostics
+}
+
+fn check_expr(expr: &Expr, level: ConditionLevel) -> Result<(), Diagnostic> {
+    match &expr.kind {
+        ExprKind::Call {
+            name,
+            name_span,
+            args,
+        } => {
+            let column = name_span.start + 1;
+            let Some(function) = functions::lookup(name) else {
+                return Err(Diagnostic::error(
+                    codes::CONDITION_UNKNOWN_FUNCTION,
+                    format!("unknown function `{name}` at column {column}"),
+                ));
+            };
+            if !function.accepts(args.len()) {
+                return Err(Diagnostic::error(
+                    codes::CONDITION_ARITY,
+                    format!(
+                        "`{}` takes {} argument(s) but {} were given at column {column}",
+                        function.name,
+                        function.arity(),
+                        args.len()
+                    ),
+                ));
+            }
+            if function.kind == FunctionKind::Status
+                && level == ConditionLevel::Step
+                && !args.is_empty()
+            {
+                return Err(Diagnostic::error(
+                    codes::CONDITION_WRONG_LEVEL,
+                    format!(
+                        "`{}` can't refer to jobs or stages in a step condition at column {column}",
+                        function.name
+                    ),
+                ));
+            }
+            Ok(())
+        }
+        ExprKind::NamedValue(name) => {
+            let column = expr.span.start + 1;
+            let lowercase = name.to_ascii_lowercase();
+            if !NAMED_VALUES.contains(&lowercase.as_str()) {
+                Err(Diagnostic::error(
+                    codes::CONDITION_UNKNOWN_NAMED_VALUE,
+                    format!("unknown named value `{name}` at column {column}"),
+                ))
+            } else if !level.allows_named_value(&lowercase) {
+                Err(Diagnostic::error(
+                    codes::CONDITION_WRONG_LEVEL,
+                    format!("`{name}` is not available in a {level} condition at column {column}"),
+                ))
+            } else {
+                Ok(())
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problem_codes(condition: &str, level: ConditionLevel) -> Vec<&'static str> {
+        condition_problems(condition, level)
+            .into_iter()
+            .map(|d| d.code)
+            .collect()
+    }
+
+    #[test]
+    fn valid_conditions() {
+        for (condition, level) in [
+            ("succeeded()", ConditionLevel::Step),
+            (
+                "and(succeeded(), eq(variables['Build.Reason'], 'PullRequest'))",
+                ConditionLevel::Job,
+            ),
+            ("failed('build')", ConditionLevel::Stage),
+            (
+                "eq(stageDependencies.build.compile.result, 'Succeeded')",
+                ConditionLevel::Job,
+            ),
+            (
+                "in(dependencies.a.result, 'Succeeded', 'Skipped')",
+                ConditionLevel::Stage,
+            ),
+            ("${{ parameters.condition }}", ConditionLevel::Step),
+        ] {
+            assert_eq!(
+                problem_codes(condition, level),
+                Vec::<&str>::new(),
+                "{condition}"
+            );
+        }
+    }
+
+    #[test]
+    fn syntax_error() {
+        let problems = condition_problems("eq(variables['x'] 'y')", ConditionLevel::Step);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].code, codes::CONDITION_SYNTAX);
+        assert!(problems[0].message.contains("column 19"), "{}", problems[0]);
+    }
+
+    #[test]
+    fn unknown_function() {
+        let problems = condition_problems("and(succeded(), true)", ConditionLevel::Job);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].code, codes::CONDITION_UNKNOWN_FUNCTION);
+        assert_eq!(
+            problems[0].message,
+            "unknown function `succeded` at column 5"
+        );
+    }
+
+    #[test]
+    fn arity() {
+        assert_eq!(
+            problem_codes("eq(variables.a)", ConditionLevel::Job),
+            [codes::CONDITION_ARITY]
+        );
+        assert_eq!(
+            problem_codes("not(true, false)", ConditionLevel::Job),
+            [codes::CONDITION_ARITY]
+        );
+    }
+
+    #[test]
+    fn wrong_level() {
+        assert_eq!(
+            problem_codes("succeededOrFailed('build')", ConditionLevel::Step),
+            [codes::CONDITION_WRONG_LEVEL]
+        );
+        assert_eq!(
+            problem_codes("eq(dependencies.a.result, 'Failed')", ConditionLevel::Step),
+            [codes::CONDITION_WRONG_LEVEL]
+        );
+        assert_eq!(
+            problem_codes(
+                "eq(stageDependencies.a.b.result, 'Failed')",
+                ConditionLevel::Stage
+            ),
+            [codes::CONDITION_WRONG_LEVEL]
+        );
+        assert_eq!(
+            problem_codes("eq(foo.bar, 1)", ConditionLevel::Stage),
+            [codes::CONDITION_UNKNOWN_NAMED_VALUE]
+        );
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        assert_eq!(
+            problem_codes("or(nope(), eq(1), bogus.x)", ConditionLevel::Job),
+            [
+                codes::CONDITION_UNKNOWN_FUNCTION,
+                codes::CONDITION_ARITY,
+                codes::CONDITION_UNKNOWN_NAMED_VALUE,
+            ]
+        );
+    }
+
+    #[test]
+    fn paths() {
+        let stages: Vec<Stage> = serde_yaml::from_str(
+            "
+- stage: build
+  condition: nope()
+  jobs:
+  - job: test
+    condition: eq(1)
+    steps:
+    - task: Foo@1
+      name: run
+      condition: failed('test')
+",
+        )
+        .unwrap();
+        let paths: Vec<String> = condition_diagnostics(&stages)
+            .iter()
+            .map(|d| d.path.to_string())
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                r#"stage "build" > condition"#,
+                r#"stage "build" > job "test" > condition"#,
+                r#"stage "build" > job "test" > step "run" > condition"#,
+            ]
+        );
+        assert_eq!(
+            validate_conditions(&stages).unwrap_err().code,
+            codes::CONDITION_UNKNOWN_FUNCTION
+        );
+    }
+}
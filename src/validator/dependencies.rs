@@ -1,92 +1,363 @@
 use std::collections::HashSet;
 
-use crate::core::v1::{depends::DependsOn, job::Job, stage::Stage};
+use crate::{
+    core::v1::{job::Job, stage::Stage},
+    validator::{
+        diagnostic::{AstPath, Diagnostic, PathSegment, Severity, codes},
+        graph::DependencyGraph,
+        names::{JobNameOverride, NameProvider},
+    },
+};
 
-pub fn validate_dependencies(stages: &[Stage]) -> Result<(), String> {
-    validate_stage_depends(stages)?;
-    validate_job_depends(stages)?;
-    Ok(())
+/// Check that every `dependsOn` refers to a stage or job that exists, using
+/// the `jobNameOverride` parameter to name jobs produced by templates
+pub fn validate_dependencies(stages: &[Stage]) -> Result<(), Diagnostic> {
+    validate_dependencies_with(stages, &JobNameOverride)
 }
 
-fn validate_stage_depends(stages: &[Stage]) -> Result<(), String> {
-    let mut stage_names = HashSet::new();
-    for stage in stages {
+/// Check that every `dependsOn` refers to a stage or job that exists, using
+/// `names` to learn the stages and jobs produced by templates, stopping at the
+/// first problem. Dependencies may refer to stages and jobs declared later in
+/// the file.
+pub fn validate_dependencies_with(
+    stages: &[Stage],
+    names: &dyn NameProvider,
+) -> Result<(), Diagnostic> {
+    let diagnostics = dependency_diagnostics(stages, names);
+    match diagnostics
+        .into_iter()
+        .find(|d| d.severity == Severity::Error)
+    {
+        Some(diagnostic) => Err(diagnostic),
+        None => Ok(()),
+    }
+}
+
+/// Check the dependencies of every stage and job like
+/// [`validate_dependencies_with`], and report every problem found. Stages
+/// following a template whose stages aren't known are reported as
+/// [`Severity::Info`], since the stage they implicitly depend on isn't known.
+pub fn dependency_diagnostics(stages: &[Stage], names: &dyn NameProvider) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    // each template is looked up once, while building the graph
+    let graph = DependencyGraph::stages_reporting(stages, names, &mut |index, d| {
+        diagnostics.push(d.at(stage_path(index, &None)))
+    });
+    let path = |index: usize| match &stages[index] {
+        Stage::Stage(stage) => stage_path(index, &stage.name),
+        Stage::Template(_) => stage_path(index, &None),
+    };
+    check_depends(&graph, "stage", path, &mut diagnostics);
+    check_graph(&graph, "stage", &AstPath::default(), &mut diagnostics);
+    for node in graph.nodes.iter().filter(|n| n.unknown_predecessor) {
+        diagnostics.push(
+            Diagnostic::new(
+                codes::IMPLICIT_DEPENDENCY,
+                Severity::Info,
+                format!(
+                    "stage {:?} depends on the last stage of the template before it, which isn't known",
+                    node.name
+                ),
+            )
+            .at(path(node.index))
+            .with_note("add `dependsOn` to make its dependencies explicit"),
+        );
+    }
+
+    for (index, stage) in stages.iter().enumerate() {
         if let Stage::Stage(stage) = stage {
-            if let Some(depends_on) = &stage.depends_on {
-                match depends_on {
-                    DependsOn::Single(other) => {
-                        if !stage_names.contains(other) {
-                            return Err(format!(
-                                "stage {:?} depends on non-existent stage {other}",
-                                stage.name
-                            ));
-                        }
-                    }
-                    DependsOn::Multi(others) => {
-                        for other in others {
-                            if !stage_names.contains(other) {
-                                return Err(format!(
-                                    "stage {:?} depends on non-existent stage {other}",
-                                    stage.name
-                                ));
-                            }
-                        }
-                    }
-                }
-            }
-            if let Some(name) = &stage.name {
-                stage_names.insert(name.clone());
-            }
+            let stage_path = stage_path(index, &stage.name);
+            let job_path = |index: usize| {
+                let name = match &stage.jobs[index] {
+                    Job::Job(job) => job.name.clone(),
+                    Job::Template(_) => None,
+                };
+                stage_path.join(PathSegment::Job { index, name })
+            };
+            let graph = DependencyGraph::jobs_reporting(stage, names, &mut |index, d| {
+                diagnostics.push(d.at(job_path(index)))
+            });
+            check_depends(&graph, "job", job_path, &mut diagnostics);
+            check_graph(&graph, "job", &stage_path, &mut diagnostics);
         }
     }
+    diagnostics
+}
 
-    Ok(())
+fn stage_path(index: usize, name: &Option<String>) -> AstPath {
+    AstPath::default().join(PathSegment::Stage {
+        index,
+        name: name.clone(),
+    })
 }
 
-fn validate_job_depends(stages: &[Stage]) -> Result<(), String> {
-    for stage in stages {
-        if let Stage::Stage(stage) = stage {
-            let mut job_names = HashSet::new();
-            for job in &stage.jobs {
-                match job {
-                    Job::Job(job) => {
-                        if let Some(depends_on) = &job.depends_on {
-                            match depends_on {
-                                DependsOn::Single(other) => {
-                                    if !job_names.contains(other) {
-                                        return Err(format!(
-                                            "job {:?} depends on non-existent job {other}",
-                                            job.name
-                                        ));
-                                    }
-                                }
-                                DependsOn::Multi(others) => {
-                                    for other in others {
-                                        if !job_names.contains(other) {
-                                            return Err(format!(
-                                                "job {:?} depends on non-existent job {other}",
-                                                job.name
-                                            ));
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        if let Some(name) = &job.name {
-                            job_names.insert(name.clone());
-                        }
-                    }
-                    Job::Template(job) => {
-                        if let Some(name) = job.parameters.get("jobNameOverride") {
-                            if let Some(name) = name.as_str() {
-                                job_names.insert(name.to_string());
-                            }
-                        }
-                    }
-                }
+/// Report every `dependsOn` naming a stage or job that isn't in `graph`, where
+/// `path` gives the path of the stage or job at an index
+fn check_depends(
+    graph: &DependencyGraph,
+    kind: &str,
+    path: impl Fn(usize) -> AstPath,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let names: HashSet<&str> = graph.nodes.iter().map(|n| n.name.as_str()).collect();
+    // implicit dependencies are on nodes in the graph by construction
+    for node in graph.nodes.iter().filter(|n| !n.implicit) {
+        for other in node
+            .depends_on
+            .iter()
+            .filter(|d| !names.contains(d.as_str()))
+        {
+            diagnostics.push(
+                Diagnostic::error(
+                    codes::DEPENDS_ON_UNKNOWN,
+                    format!("depends on non-existent {kind} {other}"),
+                )
+                .at(path(node.index).field("dependsOn")),
+            );
+        }
+    }
+}
+
+/// Check an effective dependency graph for duplicate names and cycles
+fn check_graph(
+    graph: &DependencyGraph,
+    kind: &str,
+    path: &AstPath,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    for name in graph.duplicates() {
+        diagnostics.push(
+            Diagnostic::error(
+                codes::DUPLICATE_NAME,
+                format!("{kind} {name:?} is declared more than once"),
+            )
+            .at(path.clone()),
+        );
+    }
+    if let Some(cycle) = graph.cycle() {
+        diagnostics.push(
+            Diagnostic::error(
+                codes::DEPENDENCY_CYCLE,
+                format!("{kind} dependency cycle: {}", cycle.join(" -> ")),
+            )
+            .at(path.clone()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use crate::core::v1::{job::JobWithTemplate, stage::StageWithTemplate};
+
+    use super::*;
+
+    fn stages(yaml: &str) -> Vec<Stage> {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn problem_codes(stages: &[Stage]) -> Vec<&'static str> {
+        dependency_diagnostics(stages, &JobNameOverride)
+            .iter()
+            .map(|d| d.code)
+            .collect()
+    }
+
+    /// Knows the stages and jobs `shared.yml` produces
+    struct Shared;
+
+    impl NameProvider for Shared {
+        fn stage_names(&self, stage: &StageWithTemplate) -> Result<Vec<String>, Diagnostic> {
+            match stage.template.as_deref() {
+                Some("shared.yml") => Ok(vec!["shared".to_string()]),
+                _ => Err(Diagnostic::error(
+                    codes::TEMPLATE_EXPANSION,
+                    "unknown template",
+                )),
             }
         }
+
+        fn job_names(&self, job: &JobWithTemplate) -> Result<Vec<String>, Diagnostic> {
+            Ok(match job.template.as_deref() {
+                Some("shared.yml") => vec!["lint".to_string(), "audit".to_string()],
+                _ => Vec::new(),
+            })
+        }
+    }
+
+    #[test]
+    fn forward_references_are_allowed() {
+        let stages = stages(
+            "
+- stage: build
+  dependsOn: prepare
+  jobs:
+  - job: compile
+    dependsOn: fetch
+  - job: fetch
+- stage: prepare
+  dependsOn: []
+  jobs: []
+",
+        );
+        assert!(problem_codes(&stages).is_empty());
+    }
+
+    #[test]
+    fn job_name_override_names_template_jobs() {
+        let stages = stages(
+            "
+- stage: build
+  jobs:
+  - template: job.yml
+    parameters:
+      jobNameOverride: compile
+  - job: test
+    dependsOn: compile
+  - job: package
+    dependsOn: publish
+",
+        );
+        let diagnostics = dependency_diagnostics(&stages, &JobNameOverride);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, codes::DEPENDS_ON_UNKNOWN);
+        assert!(diagnostics[0].message.contains("publish"));
+    }
+
+    #[test]
+    fn names_come_from_the_provider() {
+        let stages = stages(
+            "
+- template: shared.yml
+- stage: build
+  dependsOn: shared
+  jobs:
+  - template: shared.yml
+  - job: test
+    dependsOn: [lint, audit]
+",
+        );
+        assert!(validate_dependencies_with(&stages, &Shared).is_ok());
+        assert_eq!(
+            validate_dependencies(&stages).unwrap_err().code,
+            codes::DEPENDS_ON_UNKNOWN
+        );
+    }
+
+    #[test]
+    fn provider_errors_are_reported_at_the_template() {
+        let stages = stages(
+            "
+- template: other.yml
+",
+        );
+        let diagnostic = validate_dependencies_with(&stages, &Shared).unwrap_err();
+        assert_eq!(diagnostic.code, codes::TEMPLATE_EXPANSION);
+        assert_eq!(diagnostic.path, stage_path(0, &None));
+    }
+
+    #[test]
+    fn unknown_template_is_reported_instead_of_an_edge() {
+        let stages = stages(
+            "
+- stage: build
+  jobs: []
+- template: deploy.yml
+- stage: verify
+  jobs: []
+",
+        );
+        let diagnostics = dependency_diagnostics(&stages, &JobNameOverride);
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.code, codes::IMPLICIT_DEPENDENCY);
+        assert_eq!(diagnostic.severity, Severity::Info);
+        assert_eq!(diagnostic.path, stage_path(2, &Some("verify".to_string())));
+        assert!(validate_dependencies(&stages).is_ok());
+    }
+
+    #[test]
+    fn explicit_depends_on_isnt_reported() {
+        let stages = stages(
+            "
+- stage: build
+  jobs: []
+- template: deploy.yml
+- stage: verify
+  dependsOn: build
+  jobs: []
+",
+        );
+        assert!(problem_codes(&stages).is_empty());
     }
 
-    Ok(())
+    #[test]
+    fn problems() {
+        let stages = stages(
+            "
+- stage: a
+  dependsOn: missing
+  jobs:
+  - job: one
+    dependsOn: two
+  - job: two
+    dependsOn: one
+  - template: job.yml
+    parameters:
+      jobNameOverride: one
+- stage: a
+  jobs: []
+",
+        );
+        assert_eq!(
+            problem_codes(&stages),
+            [
+                codes::DEPENDS_ON_UNKNOWN,
+                codes::DUPLICATE_NAME,
+                codes::DUPLICATE_NAME,
+                codes::DEPENDENCY_CYCLE,
+            ]
+        );
+        assert_eq!(
+            validate_dependencies(&stages).unwrap_err().code,
+            codes::DEPENDS_ON_UNKNOWN
+        );
+    }
+
+    #[test]
+    fn each_template_is_looked_up_once() {
+        /// Fails every lookup, counting them
+        #[derive(Default)]
+        struct Failing(Cell<usize>);
+
+        impl NameProvider for Failing {
+            fn stage_names(&self, _: &StageWithTemplate) -> Result<Vec<String>, Diagnostic> {
+                self.0.set(self.0.get() + 1);
+                Err(Diagnostic::error(codes::TEMPLATE_EXPANSION, "unreadable"))
+            }
+
+            fn job_names(&self, _: &JobWithTemplate) -> Result<Vec<String>, Diagnostic> {
+                self.0.set(self.0.get() + 1);
+                Err(Diagnostic::error(codes::TEMPLATE_EXPANSION, "unreadable"))
+            }
+        }
+
+        let stages = stages(
+            "
+- template: stages.yml
+- stage: build
+  dependsOn: []
+  jobs:
+  - template: jobs.yml
+  - job: test
+",
+        );
+        let names = Failing::default();
+        let diagnostics = dependency_diagnostics(&stages, &names);
+        assert_eq!(names.0.get(), 2);
+        let paths: Vec<String> = diagnostics.iter().map(|d| d.path.to_string()).collect();
+        assert_eq!(paths, ["stage #0", "stage \"build\" > job #0"]);
+    }
 }
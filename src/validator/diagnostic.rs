@@ -0,0 +1,311 @@
+//! Structured validation results
+//!
+//! Every problem found by a validator is reported as a [`Diagnostic`] with a
+//! stable rule [`code`](codes), so tooling can filter, count and render them.
+
+use std::{error::Error, fmt};
+
+/// Stable identifiers for each validation rule
+pub mod codes {
+    /// A condition couldn't be parsed
+    pub const CONDITION_SYNTAX: &str = "condition-syntax";
+    /// A condition calls a function that doesn't exist
+    pub const CONDITION_UNKNOWN_FUNCTION: &str = "condition-unknown-function";
+    /// A condition calls a function with the wrong number of arguments
+    pub const CONDITION_ARITY: &str = "condition-arity";
+    /// A condition refers to a named value that doesn't exist
+    pub const CONDITION_UNKNOWN_NAMED_VALUE: &str = "condition-unknown-named-value";
+    /// A condition uses a function or named value that isn't available at its
+    /// level
+    pub const CONDITION_WRONG_LEVEL: &str = "condition-wrong-level";
+    /// A `dependsOn` refers to a stage or job that doesn't exist
+    pub const DEPENDS_ON_UNKNOWN: &str = "depends-on-unknown";
+    /// Two stages, or two jobs in a stage, have the same name
+    pub const DUPLICATE_NAME: &str = "duplicate-name";
+    /// Stages or jobs depend on each other in a cycle
+    pub const DEPENDENCY_CYCLE: &str = "dependency-cycle";
+    /// A stage follows a template whose stages aren't known, so the stage it
+    /// implicitly depends on isn't known either
+    pub const IMPLICIT_DEPENDENCY: &str = "implicit-dependency";
+    /// A template couldn't be resolved or expanded
+    pub const TEMPLATE_EXPANSION: &str = "template-expansion";
+    /// A node has a key this crate doesn't know, kept by lenient parsing
+    pub const UNKNOWN_FIELD: &str = "unknown-field";
+    /// A template's parameters are declared differently than the type they're
+    /// read into
+    pub const PARAMETER_DECLARATION: &str = "parameter-declaration";
+    /// A template is passed a parameter it doesn't declare, isn't passed one
+    /// it requires, or is passed a value its declaration doesn't allow
+    pub const TEMPLATE_PARAMETER: &str = "template-parameter";
+    /// A pipeline is queued with a runtime parameter it doesn't declare, without
+    /// one it requires, or with a value its declaration doesn't allow
+    pub const RUNTIME_PARAMETER: &str = "runtime-parameter";
+    /// A template expression refers to a parameter that isn't declared
+    pub const PARAMETER_UNDECLARED: &str = "parameter-undeclared";
+    /// A declared parameter is never referred to
+    pub const PARAMETER_UNUSED: &str = "parameter-unused";
+    /// A variable is referred to but never declared
+    pub const VARIABLE_UNDEFINED: &str = "variable-undefined";
+    /// A declared variable is never referred to
+    pub const VARIABLE_UNUSED: &str = "variable-unused";
+    /// A variable hides one of the same name declared at a higher level
+    pub const VARIABLE_SHADOWED: &str = "variable-shadowed";
+    /// A predefined variable is read where Azure DevOps doesn't set it
+    pub const VARIABLE_AVAILABILITY: &str = "variable-availability";
+}
+
+/// How serious a diagnostic is
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, Hash)]
+pub enum Severity {
+    /// Informational only
+    Info,
+    /// Likely a mistake, but the pipeline would still run
+    Warning,
+    /// The pipeline is invalid
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A position in a source file
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, Default, Hash)]
+pub struct Position {
+    /// Line number, starting at 1
+    pub line: usize,
+
+    /// Column number, starting at 1
+    pub column: usize,
+
+    /// Byte offset from the start of the file
+    pub offset: usize,
+}
+
+/// A range of a source file
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Hash)]
+pub struct Span {
+    /// Where the range starts
+    pub start: Position,
+
+    /// Where the range ends (exclusive)
+    pub end: Position,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.start.line, self.start.column)
+    }
+}
+
+/// One step along an [`AstPath`]
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub enum PathSegment {
+    /// A stage, by position in its list and name if it has one
+    Stage { index: usize, name: Option<String> },
+    /// A job, by position in its stage and name if it has one
+    Job { index: usize, name: Option<String> },
+    /// A step, by position in its job and name if it has one
+    Step { index: usize, name: Option<String> },
+    /// An item of any other list, such as a variable or parameter, by position
+    /// and name if it has one
+    Item { index: usize, name: Option<String> },
+    /// A property of the node, e.g. `condition` or `dependsOn`
+    Field(String),
+}
+
+impl fmt::Display for PathSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (kind, index, name) = match self {
+            PathSegment::Stage { index, name } => ("stage", index, name),
+            PathSegment::Job { index, name } => ("job", index, name),
+            PathSegment::Step { index, name } => ("step", index, name),
+            PathSegment::Item {
+                name: Some(name), ..
+            } => return write!(f, "{name:?}"),
+            PathSegment::Item { index, name: None } => return write!(f, "#{index}"),
+            PathSegment::Field(field) => return write!(f, "{field}"),
+        };
+        match name {
+            Some(name) => write!(f, "{kind} {name:?}"),
+            None => write!(f, "{kind} #{index}"),
+        }
+    }
+}
+
+/// Where in the pipeline a diagnostic applies, e.g.
+/// `stage "build" > job "test" > step #2 > condition`
+#[derive(PartialEq, Eq, Debug, Clone, Default, Hash)]
+pub struct AstPath(pub Vec<PathSegment>);
+
+impl AstPath {
+    /// This path extended by one segment
+    pub fn join(&self, segment: PathSegment) -> Self {
+        let mut path = self.clone();
+        path.0.push(segment);
+        path
+    }
+
+    /// This path extended by a field name
+    pub fn field(&self, field: &str) -> Self {
+        self.join(PathSegment::Field(field.to_string()))
+    }
+}
+
+impl fmt::Display for AstPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " > ")?;
+            }
+            write!(f, "{segment}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A problem found while validating a pipeline
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Diagnostic {
+    /// Stable identifier of the rule that found the problem, one of [`codes`]
+    pub code: &'static str,
+
+    /// How serious the problem is
+    pub severity: Severity,
+
+    /// What's wrong
+    pub message: String,
+
+    /// Where in the pipeline the problem is
+    pub path: AstPath,
+
+    /// Where in the source file the problem is, when known. Boxed to keep
+    /// `Result<_, Diagnostic>` small.
+    pub span: Option<Box<Span>>,
+
+    /// Additional context, e.g. where a duplicate was first declared
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    /// A new diagnostic with no location
+    pub fn new(code: &'static str, severity: Severity, message: impl Into<String>) -> Self {
+        Diagnostic {
+            code,
+            severity,
+            message: message.into(),
+            path: AstPath::default(),
+            span: None,
+            notes: Vec::new(),
+        }
+    }
+
+    /// A new error with no location
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic::new(code, Severity::Error, message)
+    }
+
+    /// A new warning with no location
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic::new(code, Severity::Warning, message)
+    }
+
+    /// Set where in the pipeline the problem is
+    pub fn at(mut self, path: AstPath) -> Self {
+        self.path = path;
+        self
+    }
+
+    /// Set where in the source file the problem is
+    pub fn with_span(mut self, span: Option<Span>) -> Self {
+        self.span = span.map(Box::new);
+        self
+    }
+
+    /// Add a note with additional context
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: ", self.severity, self.code)?;
+        if let Some(span) = &self.span {
+            write!(f, "{span}: ")?;
+        }
+        if !self.path.0.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(f, "{}", self.message)?;
+        for note in &self.notes {
+            write!(f, "\n  note: {note}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for Diagnostic {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(line: usize, column: usize) -> Position {
+        Position {
+            line,
+            column,
+            offset: 0,
+        }
+    }
+
+    #[test]
+    fn display() {
+        let path = AstPath::default()
+            .join(PathSegment::Stage {
+                index: 0,
+                name: Some("build".to_string()),
+            })
+            .join(PathSegment::Job {
+                index: 2,
+                name: None,
+            })
+            .field("dependsOn");
+        let diagnostic =
+            Diagnostic::error(codes::DEPENDS_ON_UNKNOWN, "depends on non-existent job x")
+                .at(path)
+                .with_span(Some(Span {
+                    start: position(4, 7),
+                    end: position(4, 8),
+                }))
+                .with_note("jobs in this stage: a, b");
+        assert_eq!(
+            diagnostic.to_string(),
+            "error[depends-on-unknown]: 4:7: stage \"build\" > job #2 > dependsOn: \
+             depends on non-existent job x\n  note: jobs in this stage: a, b"
+        );
+        assert_eq!(
+            Diagnostic::warning(codes::UNKNOWN_FIELD, "unknown key").to_string(),
+            "warning[unknown-field]: unknown key"
+        );
+    }
+
+    #[test]
+    fn severities_are_ordered() {
+        assert!(Severity::Info < Severity::Warning);
+        assert!(Severity::Warning < Severity::Error);
+    }
+
+    #[test]
+    fn results_stay_small() {
+        // clippy's default `large_err_threshold`
+        assert!(size_of::<Diagnostic>() <= 128);
+    }
+}
@@ -0,0 +1,606 @@
+//! The effective dependency graph of stages and jobs
+//!
+//! <https://learn.microsoft.com/en-us/azure/devops/pipelines/process/stages?view=azure-pipelines#specify-dependencies>
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use crate::{
+    core::v1::{
+        job::Job,
+        stage::{Stage, StageWithJobs},
+    },
+    validator::{
+        diagnostic::{Diagnostic, codes},
+        names::NameProvider,
+    },
+};
+
+/// Name Azure gives to a stage or job that doesn't declare one
+pub const DEFAULT_NAME: &str = "__default";
+
+/// A stage or job in a [`DependencyGraph`]
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Node {
+    /// Stage or job name
+    pub name: String,
+
+    /// Names this node depends on
+    pub depends_on: Vec<String>,
+
+    /// Whether `depends_on` comes from Azure's implicit ordering rather than a
+    /// `dependsOn` property
+    pub implicit: bool,
+
+    /// Position in the declared list of the stage or job, or of the template
+    /// call that produces it
+    pub index: usize,
+
+    /// Whether this stage implicitly depends on the last stage of a template
+    /// call whose stages aren't known, which `depends_on` leaves out
+    pub unknown_predecessor: bool,
+}
+
+/// The effective dependencies between the stages of a pipeline or the jobs of
+/// a stage.
+///
+/// A stage without `dependsOn` depends on the stage declared before it, and
+/// `dependsOn: []` removes that dependency. Jobs without `dependsOn` don't
+/// depend on anything.
+///
+/// Stages and jobs produced by template calls are part of the graph when a
+/// [`NameProvider`] knows their names. Stages produced by a template are
+/// assumed to have no `dependsOn`, so each depends on the one before it. When
+/// a stage template's stages aren't known, the stage after it gets no implicit
+/// dependency and is marked [`unknown_predecessor`](Node::unknown_predecessor)
+/// instead.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct DependencyGraph {
+    /// Every node, in declared order
+    pub nodes: Vec<Node>,
+}
+
+/// The stage a stage without `dependsOn` depends on
+enum Previous {
+    /// None, at the start of the list
+    Start,
+    /// The named stage
+    Stage(String),
+    /// The last stage of a template whose stages aren't known
+    Unknown,
+}
+
+/// Knows no template's stages or jobs
+struct NoNames;
+
+impl NameProvider for NoNames {}
+
+impl DependencyGraph {
+    /// Build the graph of the given stages. Stage templates aren't expanded,
+    /// so the stages they produce aren't part of the graph.
+    pub fn stages(stages: &[Stage]) -> Self {
+        Self::stages_with(stages, &NoNames)
+    }
+
+    /// Build the graph of the given stages, using `names` to learn the stages
+    /// produced by templates. A template whose names can't be found is treated
+    /// as producing unknown stages.
+    pub fn stages_with(stages: &[Stage], names: &dyn NameProvider) -> Self {
+        Self::stages_reporting(stages, names, &mut |_, _| {})
+    }
+
+    /// Build the graph like [`stages_with`](Self::stages_with), calling
+    /// `failed` with the index of each template call whose stages `names`
+    /// can't find and the reason
+    pub(crate) fn stages_reporting(
+        stages: &[Stage],
+        names: &dyn NameProvider,
+        failed: &mut dyn FnMut(usize, Diagnostic),
+    ) -> Self {
+        let mut graph = DependencyGraph::default();
+        let mut previous = Previous::Start;
+        for (index, stage) in stages.iter().enumerate() {
+            match stage {
+                Stage::Stage(stage) => {
+                    let name = stage_name(stage);
+                    graph.nodes.push(match &stage.depends_on {
+                        Some(depends_on) => Node {
+                            name: name.clone(),
+                            depends_on: depends_on.names().to_vec(),
+                            implicit: false,
+                            index,
+                            unknown_predecessor: false,
+                        },
+                        None => implicit(name.clone(), index, &previous),
+                    });
+                    previous = Previous::Stage(name);
+                }
+                Stage::Template(template) => match names.stage_names(template) {
+                    Ok(produced) if !produced.is_empty() => {
+                        for name in produced {
+                            graph.nodes.push(implicit(name.clone(), index, &previous));
+                            previous = Previous::Stage(name);
+                        }
+                    }
+                    Ok(_) => previous = Previous::Unknown,
+                    Err(diagnostic) => {
+                        failed(index, diagnostic);
+                        previous = Previous::Unknown;
+                    }
+                },
+            }
+        }
+        graph
+    }
+
+    /// Build the graph of the jobs in a stage. Job templates aren't expanded,
+    /// so the jobs they produce aren't part of the graph.
+    pub fn jobs(stage: &StageWithJobs) -> Self {
+        Self::jobs_with(stage, &NoNames)
+    }
+
+    /// Build the graph of the jobs in a stage, using `names` to learn the jobs
+    /// produced by templates
+    pub fn jobs_with(stage: &StageWithJobs, names: &dyn NameProvider) -> Self {
+        Self::jobs_reporting(stage, names, &mut |_, _| {})
+    }
+
+    /// Build the graph like [`jobs_with`](Self::jobs_with), calling `failed`
+    /// with the index of each template call whose jobs `names` can't find and
+    /// the reason
+    pub(crate) fn jobs_reporting(
+        stage: &StageWithJobs,
+        names: &dyn NameProvider,
+        failed: &mut dyn FnMut(usize, Diagnostic),
+    ) -> Self {
+        let mut graph = DependencyGraph::default();
+        for (index, job) in stage.jobs.iter().enumerate() {
+            match job {
+                Job::Job(job) => graph.nodes.push(Node {
+                    name: job.name.clone().unwrap_or_else(|| DEFAULT_NAME.to_string()),
+                    depends_on: job
+                        .depends_on
+                        .as_ref()
+                        .map(|d| d.names().to_vec())
+                        .unwrap_or_default(),
+                    implicit: false,
+                    index,
+                    unknown_predecessor: false,
+                }),
+                Job::Template(template) => {
+                    let produced = names.job_names(template).unwrap_or_else(|diagnostic| {
+                        failed(index, diagnostic);
+                        Vec::new()
+                    });
+                    graph.nodes.extend(produced.into_iter().map(|name| Node {
+                        name,
+                        depends_on: Vec::new(),
+                        implicit: false,
+                        index,
+                        unknown_predecessor: false,
+                    }));
+                }
+            }
+        }
+        graph
+    }
+
+    /// Find a node by name
+    pub fn node(&self, name: &str) -> Option<&Node> {
+        self.nodes.iter().find(|n| n.name == name)
+    }
+
+    /// Names of the nodes that depend directly on `name`
+    pub fn dependents(&self, name: &str) -> Vec<&str> {
+        self.nodes
+            .iter()
+            .filter(|n| n.depends_on.iter().any(|d| d == name))
+            .map(|n| n.name.as_str())
+            .collect()
+    }
+
+    /// Names of every node `name` depends on, directly or indirectly
+    pub fn ancestors(&self, name: &str) -> Vec<&str> {
+        let mut ancestors: Vec<&str> = Vec::new();
+        let mut pending: Vec<&str> = self
+            .node(name)
+            .map(|n| n.depends_on.iter().map(String::as_str).collect())
+            .unwrap_or_default();
+        while let Some(name) = pending.pop() {
+            if ancestors.contains(&name) {
+                continue;
+            }
+            if let Some(node) = self.node(name) {
+                pending.extend(node.depends_on.iter().map(String::as_str));
+            }
+            ancestors.push(name);
+        }
+        ancestors
+    }
+
+    /// Names that are declared more than once
+    pub fn duplicates(&self) -> Vec<&str> {
+        let mut seen = HashSet::new();
+        let mut duplicates = Vec::new();
+        for node in &self.nodes {
+            if !seen.insert(node.name.as_str()) && !duplicates.contains(&node.name.as_str()) {
+                duplicates.push(node.name.as_str());
+            }
+        }
+        duplicates
+    }
+
+    /// Dependencies on names that aren't in the graph, as `(node, missing)`
+    /// pairs
+    pub fn missing(&self) -> Vec<(&str, &str)> {
+        self.nodes
+            .iter()
+            .flat_map(|node| {
+                node.depends_on
+                    .iter()
+                    .filter(|d| self.node(d).is_none())
+                    .map(|d| (node.name.as_str(), d.as_str()))
+            })
+            .collect()
+    }
+
+    /// A dependency cycle, if there is one, as the names along the cycle with
+    /// the first name repeated at the end
+    pub fn cycle(&self) -> Option<Vec<&str>> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum Mark {
+            Unvisited,
+            InProgress,
+            Done,
+        }
+
+        fn visit<'a>(
+            graph: &'a DependencyGraph,
+            index: usize,
+            marks: &mut [Mark],
+            path: &mut Vec<usize>,
+        ) -> Option<Vec<&'a str>> {
+            marks[index] = Mark::InProgress;
+            path.push(index);
+            for dependency in &graph.nodes[index].depends_on {
+                let Some(next) = graph.nodes.iter().position(|n| &n.name == dependency) else {
+                    continue;
+                };
+                match marks[next] {
+                    Mark::InProgress => {
+                        let start = path.iter().position(|i| *i == next).unwrap_or(0);
+                        let mut cycle: Vec<&str> = path[start..]
+                            .iter()
+                            .map(|i| graph.nodes[*i].name.as_str())
+                            .collect();
+                        cycle.push(graph.nodes[next].name.as_str());
+                        return Some(cycle);
+                    }
+                    Mark::Unvisited => {
+                        if let Some(cycle) = visit(graph, next, marks, path) {
+                            return Some(cycle);
+                        }
+                    }
+                    Mark::Done => {}
+                }
+            }
+            path.pop();
+            marks[index] = Mark::Done;
+            None
+        }
+
+        let mut marks = vec![Mark::Unvisited; self.nodes.len()];
+        for index in 0..self.nodes.len() {
+            if marks[index] == Mark::Unvisited
+                && let Some(cycle) = visit(self, index, &mut marks, &mut Vec::new())
+            {
+                return Some(cycle);
+            }
+        }
+        None
+    }
+
+    /// Node names ordered so that each comes after everything it depends on,
+    /// keeping the declared order where possible. Dependencies on names that
+    /// aren't in the graph are ignored.
+    pub fn topological_order(&self) -> Result<Vec<&str>, Diagnostic> {
+        if let Some(cycle) = self.cycle() {
+            return Err(Diagnostic::error(
+                codes::DEPENDENCY_CYCLE,
+                format!("dependency cycle: {}", cycle.join(" -> ")),
+            ));
+        }
+
+        // Kahn's algorithm: count each node's dependencies that are in the
+        // graph, and release a name's dependents once a node with that name
+        // is done, always taking the earliest declared node that's ready
+        let names: HashSet<&str> = self.nodes.iter().map(|node| node.name.as_str()).collect();
+        let mut waiting = vec![0; self.nodes.len()];
+        let mut dependents: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            let depends_on: HashSet<&str> = node
+                .depends_on
+                .iter()
+                .map(String::as_str)
+                .filter(|name| names.contains(name))
+                .collect();
+            waiting[index] = depends_on.len();
+            for name in depends_on {
+                dependents.entry(name).or_default().push(index);
+            }
+        }
+
+        let mut ready: BinaryHeap<Reverse<usize>> = (0..self.nodes.len())
+            .filter(|&index| waiting[index] == 0)
+            .map(Reverse)
+            .collect();
+        let mut done: Vec<&str> = Vec::with_capacity(self.nodes.len());
+        while let Some(Reverse(index)) = ready.pop() {
+            let name = self.nodes[index].name.as_str();
+            done.push(name);
+            for &dependent in dependents.remove(name).iter().flatten() {
+                waiting[dependent] -= 1;
+                if waiting[dependent] == 0 {
+                    ready.push(Reverse(dependent));
+                }
+            }
+        }
+
+        // only reachable with duplicate names
+        if done.len() < self.nodes.len() {
+            return Err(Diagnostic::error(
+                codes::DUPLICATE_NAME,
+                format!("duplicate names: {:?}", self.duplicates()),
+            ));
+        }
+        Ok(done)
+    }
+}
+
+/// A stage without `dependsOn`, which depends on `previous`
+fn implicit(name: String, index: usize, previous: &Previous) -> Node {
+    let (depends_on, unknown_predecessor) = match previous {
+        Previous::Start => (Vec::new(), false),
+        Previous::Stage(previous) => (vec![previous.clone()], false),
+        Previous::Unknown => (Vec::new(), true),
+    };
+    Node {
+        name,
+        depends_on,
+        implicit: true,
+        index,
+        unknown_predecessor,
+    }
+}
+
+fn stage_name(stage: &StageWithJobs) -> String {
+    stage
+        .name
+        .clone()
+        .unwrap_or_else(|| DEFAULT_NAME.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::v1::{job::JobWithTemplate, stage::StageWithTemplate};
+
+    use super::*;
+
+    fn stages(yaml: &str) -> Vec<Stage> {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn edges(graph: &DependencyGraph) -> Vec<(&str, Vec<&str>)> {
+        graph
+            .nodes
+            .iter()
+            .map(|n| {
+                (
+                    n.name.as_str(),
+                    n.depends_on.iter().map(String::as_str).collect(),
+                )
+            })
+            .collect()
+    }
+
+    /// Knows the stages and jobs produced by `known.yml`
+    struct Known;
+
+    impl NameProvider for Known {
+        fn stage_names(&self, stage: &StageWithTemplate) -> Result<Vec<String>, Diagnostic> {
+            Ok(match stage.template.as_deref() {
+                Some("known.yml") => vec!["t1".to_string(), "t2".to_string()],
+                _ => Vec::new(),
+            })
+        }
+
+        fn job_names(&self, job: &JobWithTemplate) -> Result<Vec<String>, Diagnostic> {
+            Ok(match job.template.as_deref() {
+                Some("known.yml") => vec!["generated".to_string()],
+                _ => Vec::new(),
+            })
+        }
+    }
+
+    #[test]
+    fn stages_depend_on_the_previous_stage() {
+        let stages = stages(
+            "
+- stage: a
+  jobs: []
+- stage: b
+  jobs: []
+- stage: c
+  jobs: []
+  dependsOn: []
+- stage: d
+  jobs: []
+  dependsOn: [a, b]
+",
+        );
+        let graph = DependencyGraph::stages(&stages);
+        assert_eq!(
+            edges(&graph),
+            [
+                ("a", vec![]),
+                ("b", vec!["a"]),
+                ("c", vec![]),
+                ("d", vec!["a", "b"]),
+            ]
+        );
+        assert!(graph.node("b").unwrap().implicit);
+        assert!(!graph.node("d").unwrap().implicit);
+        assert_eq!(graph.dependents("a"), ["b", "d"]);
+    }
+
+    #[test]
+    fn unknown_template_breaks_the_chain() {
+        let stages = stages(
+            "
+- stage: a
+  jobs: []
+- template: unknown.yml
+- stage: b
+  jobs: []
+- stage: c
+  jobs: []
+",
+        );
+        let graph = DependencyGraph::stages(&stages);
+        assert_eq!(
+            edges(&graph),
+            [("a", vec![]), ("b", vec![]), ("c", vec!["b"])]
+        );
+        let b = graph.node("b").unwrap();
+        assert!(b.unknown_predecessor);
+        assert_eq!(b.index, 2);
+        assert!(!graph.node("c").unwrap().unknown_predecessor);
+    }
+
+    #[test]
+    fn template_stages_are_chained() {
+        let stages = stages(
+            "
+- stage: a
+  jobs: []
+- template: known.yml
+- stage: b
+  jobs: []
+- stage: c
+  jobs: []
+  dependsOn: t1
+",
+        );
+        let graph = DependencyGraph::stages_with(&stages, &Known);
+        assert_eq!(
+            edges(&graph),
+            [
+                ("a", vec![]),
+                ("t1", vec!["a"]),
+                ("t2", vec!["t1"]),
+                ("b", vec!["t2"]),
+                ("c", vec!["t1"]),
+            ]
+        );
+        assert_eq!(graph.node("t2").unwrap().index, 1);
+        assert!(graph.nodes.iter().all(|n| !n.unknown_predecessor));
+    }
+
+    #[test]
+    fn template_jobs_are_included() {
+        let stages = stages(
+            "
+- stage: a
+  jobs:
+  - job: first
+  - template: known.yml
+  - template: unknown.yml
+  - job: last
+    dependsOn: generated
+",
+        );
+        let Stage::Stage(stage) = &stages[0] else {
+            unreachable!()
+        };
+        assert_eq!(
+            edges(&DependencyGraph::jobs(stage)),
+            [("first", vec![]), ("last", vec!["generated"])]
+        );
+        let graph = DependencyGraph::jobs_with(stage, &Known);
+        assert_eq!(
+            edges(&graph),
+            [
+                ("first", vec![]),
+                ("generated", vec![]),
+                ("last", vec!["generated"]),
+            ]
+        );
+        assert_eq!(
+            graph.topological_order().unwrap(),
+            ["first", "generated", "last"]
+        );
+    }
+
+    #[test]
+    fn cycles_and_duplicates() {
+        let stages = stages(
+            "
+- stage: a
+  jobs: []
+  dependsOn: c
+- stage: b
+  jobs: []
+- stage: c
+  jobs: []
+- stage: b
+  jobs: []
+  dependsOn: []
+",
+        );
+        let graph = DependencyGraph::stages(&stages);
+        assert_eq!(graph.duplicates(), ["b"]);
+        assert_eq!(graph.cycle().unwrap(), ["a", "c", "b", "a"]);
+        assert_eq!(
+            graph.topological_order().unwrap_err().code,
+            codes::DEPENDENCY_CYCLE
+        );
+    }
+
+    #[test]
+    fn topological_order_keeps_declared_order() {
+        let stages = stages(
+            "
+- stage: a
+  jobs: []
+  dependsOn: [c, missing]
+- stage: b
+  jobs: []
+  dependsOn: []
+- stage: c
+  jobs: []
+  dependsOn: []
+- stage: d
+  jobs: []
+  dependsOn: [a, c]
+",
+        );
+        let graph = DependencyGraph::stages(&stages);
+        assert_eq!(graph.topological_order().unwrap(), ["b", "c", "a", "d"]);
+
+        // each node depends on the one declared after it
+        let nodes = (0..2000)
+            .map(|index| Node {
+                name: index.to_string(),
+                depends_on: vec![(index + 1).to_string()],
+                implicit: false,
+                index,
+                unknown_predecessor: false,
+            })
+            .collect();
+        let graph = DependencyGraph { nodes };
+        let order = graph.topological_order().unwrap();
+        assert_eq!((order[0], order[1999]), ("1999", "0"));
+    }
+}
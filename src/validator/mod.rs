@@ -1 +1,12 @@
+pub mod conditions;
 pub mod dependencies;
+pub mod diagnostic;
+pub mod graph;
+pub mod names;
+pub mod parameters;
+pub mod predefined;
+pub mod references;
+pub mod report;
+pub mod runtime;
+pub mod unknown;
+pub mod variables;
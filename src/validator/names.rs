@@ -0,0 +1,425 @@
+//! Names of the stages and jobs produced by template calls
+//!
+//! Templates are resolved outside of the pipeline file, so the validator
+//! can't see which stages or jobs a `template:` entry will expand into. A
+//! [`NameProvider`] fills in those names.
+
+use std::{cell::Cell, collections::HashMap};
+
+use indexmap::IndexMap;
+use serde_yaml::Value;
+
+use crate::{
+    core::v1::{
+        job::{Job, JobWithTemplate},
+        stage::{Stage, StageWithTemplate},
+    },
+    templates::{
+        resolver::{TemplateResolver, nested_path},
+        template::Template,
+    },
+    validator::diagnostic::{Diagnostic, codes},
+};
+
+/// Tells the validator which stage and job names a template call produces
+pub trait NameProvider {
+    /// Names of the stages produced by a stage template call
+    fn stage_names(&self, stage: &StageWithTemplate) -> Result<Vec<String>, Diagnostic> {
+        let _ = stage;
+        Ok(Vec::new())
+    }
+
+    /// Names of the jobs produced by a job template call
+    fn job_names(&self, job: &JobWithTemplate) -> Result<Vec<String>, Diagnostic> {
+        let _ = job;
+        Ok(Vec::new())
+    }
+}
+
+/// Reads the name of the job a template produces from its `jobNameOverride`
+/// parameter, and assumes stage templates produce no named stages
+#[derive(Debug, Default)]
+pub struct JobNameOverride;
+
+impl NameProvider for JobNameOverride {
+    fn job_names(&self, job: &JobWithTemplate) -> Result<Vec<String>, Diagnostic> {
+        Ok(job
+            .parameters
+            .get("jobNameOverride")
+            .and_then(|name| name.as_str())
+            .map(|name| vec![name.to_string()])
+            .unwrap_or_default())
+    }
+}
+
+/// Expands the template through a resolver and reads the names of the stages
+/// or jobs it declares, expanding the templates it calls in turn
+pub struct ExpandTemplate<R> {
+    /// Loads the template source
+    pub resolver: R,
+
+    /// How many templates are being expanded, one inside the other
+    depth: Cell<usize>,
+}
+
+/// Azure DevOps allows templates to be nested at most this deep
+const MAX_DEPTH: usize = 20;
+
+impl<R> ExpandTemplate<R> {
+    /// Expand templates loaded through `resolver`
+    pub fn new(resolver: R) -> Self {
+        ExpandTemplate {
+            resolver,
+            depth: Cell::new(0),
+        }
+    }
+}
+
+impl<R: TemplateResolver> ExpandTemplate<R> {
+    /// Expand the template at `path` and `read` it, one level deeper than the
+    /// template being expanded
+    fn expand(
+        &self,
+        path: &str,
+        parameters: &IndexMap<String, Value>,
+        read: impl FnOnce(Template) -> Result<Vec<String>, Diagnostic>,
+    ) -> Result<Vec<String>, Diagnostic> {
+        let depth = self.depth.get();
+        if depth >= MAX_DEPTH {
+            return Err(Diagnostic::error(
+                codes::TEMPLATE_EXPANSION,
+                format!("template {path} is nested more than {MAX_DEPTH} levels deep"),
+            ));
+        }
+        self.depth.set(depth + 1);
+        let names = Template::expand(&self.resolver, path, parameters)
+            .map_err(|e| Diagnostic::error(codes::TEMPLATE_EXPANSION, e.to_string()))
+            .and_then(read);
+        self.depth.set(depth);
+        names
+    }
+}
+
+/// Expands a template call, asking `names` about the templates it calls in
+/// turn
+trait Expand {
+    fn stage_names_with(
+        &self,
+        stage: &StageWithTemplate,
+        names: &dyn NameProvider,
+    ) -> Result<Vec<String>, Diagnostic>;
+
+    fn job_names_with(
+        &self,
+        job: &JobWithTemplate,
+        names: &dyn NameProvider,
+    ) -> Result<Vec<String>, Diagnostic>;
+}
+
+impl<R: TemplateResolver> Expand for ExpandTemplate<R> {
+    fn stage_names_with(
+        &self,
+        stage: &StageWithTemplate,
+        names: &dyn NameProvider,
+    ) -> Result<Vec<String>, Diagnostic> {
+        let Some(path) = &stage.template else {
+            return Ok(Vec::new());
+        };
+        self.expand(path, &stage.parameters, |template| {
+            let mut produced = Vec::new();
+            for stage in &template.stages {
+                match stage {
+                    Stage::Stage(stage) => produced.extend(stage.name.clone()),
+                    Stage::Template(nested) => {
+                        let nested = StageWithTemplate {
+                            template: nested.template.as_deref().map(|t| nested_path(path, t)),
+                            parameters: nested.parameters.clone(),
+                            ..StageWithTemplate::default()
+                        };
+                        produced.extend(names.stage_names(&nested)?);
+                    }
+                }
+            }
+            Ok(produced)
+        })
+    }
+
+    fn job_names_with(
+        &self,
+        job: &JobWithTemplate,
+        names: &dyn NameProvider,
+    ) -> Result<Vec<String>, Diagnostic> {
+        let Some(path) = &job.template else {
+            return Ok(Vec::new());
+        };
+        self.expand(path, &job.parameters, |template| {
+            let mut produced = Vec::new();
+            for job in &template.jobs {
+                match job {
+                    Job::Job(job) => produced.extend(job.name.clone()),
+                    Job::Template(nested) => {
+                        let nested = JobWithTemplate {
+                            template: nested.template.as_deref().map(|t| nested_path(path, t)),
+                            parameters: nested.parameters.clone(),
+                            ..JobWithTemplate::default()
+                        };
+                        produced.extend(names.job_names(&nested)?);
+                    }
+                }
+            }
+            Ok(produced)
+        })
+    }
+}
+
+impl<R: TemplateResolver> NameProvider for ExpandTemplate<R> {
+    fn stage_names(&self, stage: &StageWithTemplate) -> Result<Vec<String>, Diagnostic> {
+        self.stage_names_with(stage, self)
+    }
+
+    fn job_names(&self, job: &JobWithTemplate) -> Result<Vec<String>, Diagnostic> {
+        self.job_names_with(job, self)
+    }
+}
+
+/// Name providers registered per template path (as written after
+/// `template:`, relative to the pipeline), with a fallback for templates that
+/// aren't registered. Templates called from inside other templates are looked
+/// up here too, by their path relative to the pipeline.
+#[derive(Default)]
+pub struct NameRegistry {
+    providers: HashMap<String, Box<dyn NameProvider>>,
+    fallback: Option<Fallback>,
+}
+
+/// What a [`NameRegistry`] does with templates that aren't registered
+enum Fallback {
+    /// Expand them, looking up the templates they call in the registry
+    Expand(Box<dyn Expand>),
+    /// Ask another provider
+    Provider(Box<dyn NameProvider>),
+}
+
+impl NameRegistry {
+    /// A registry that expands unregistered templates through `resolver`
+    pub fn with_resolver(resolver: impl TemplateResolver + 'static) -> Self {
+        NameRegistry {
+            providers: HashMap::new(),
+            fallback: Some(Fallback::Expand(Box::new(ExpandTemplate::new(resolver)))),
+        }
+    }
+
+    /// Use `provider` for every call to `template`
+    pub fn register(&mut self, template: &str, provider: impl NameProvider + 'static) {
+        self.providers
+            .insert(template.to_string(), Box::new(provider));
+    }
+
+    /// Use `provider` for templates that aren't registered
+    pub fn set_fallback(&mut self, provider: impl NameProvider + 'static) {
+        self.fallback = Some(Fallback::Provider(Box::new(provider)));
+    }
+}
+
+impl NameProvider for NameRegistry {
+    fn stage_names(&self, stage: &StageWithTemplate) -> Result<Vec<String>, Diagnostic> {
+        let registered = stage.template.as_ref().and_then(|t| self.providers.get(t));
+        match (registered, &self.fallback) {
+            (Some(provider), _) => provider.stage_names(stage),
+            (None, Some(Fallback::Expand(expand))) => expand.stage_names_with(stage, self),
+            (None, Some(Fallback::Provider(provider))) => provider.stage_names(stage),
+            (None, None) => Ok(Vec::new()),
+        }
+    }
+
+    fn job_names(&self, job: &JobWithTemplate) -> Result<Vec<String>, Diagnostic> {
+        let registered = job.template.as_ref().and_then(|t| self.providers.get(t));
+        match (registered, &self.fallback) {
+            (Some(provider), _) => provider.job_names(job),
+            (None, Some(Fallback::Expand(expand))) => expand.job_names_with(job, self),
+            (None, Some(Fallback::Provider(provider))) => provider.job_names(job),
+            (None, None) => Ok(Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use super::*;
+
+    /// Resolves templates from sources held in memory
+    struct Sources(&'static [(&'static str, &'static str)]);
+
+    impl TemplateResolver for Sources {
+        fn resolve(&self, template: &str) -> Result<String, Box<dyn Error>> {
+            self.0
+                .iter()
+                .find(|(path, _)| *path == template)
+                .map(|(_, source)| source.to_string())
+                .ok_or_else(|| format!("no template {template}").into())
+        }
+    }
+
+    const SOURCES: Sources = Sources(&[
+        (
+            "stages.yml",
+            "
+parameters:
+- name: env
+  type: string
+  default: dev
+stages:
+- stage: build
+  jobs: []
+- template: deploy.yml
+  parameters:
+    env: ${{ parameters.env }}
+",
+        ),
+        (
+            "deploy.yml",
+            "
+parameters:
+- name: env
+  type: string
+stages:
+- stage: deploy_${{ parameters.env }}
+  jobs: []
+",
+        ),
+        (
+            "ci/all.yml",
+            "
+stages:
+- template: deploy/all.yml
+",
+        ),
+        (
+            "ci/deploy/all.yml",
+            "
+stages:
+- stage: deploy
+  jobs: []
+- template: ../verify.yml
+",
+        ),
+        (
+            "ci/verify.yml",
+            "
+stages:
+- stage: verify
+  jobs: []
+",
+        ),
+        (
+            "jobs.yml",
+            "
+jobs:
+- job: lint
+- template: jobs.yml
+",
+        ),
+    ]);
+
+    fn stage(yaml: &str) -> StageWithTemplate {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn job(yaml: &str) -> JobWithTemplate {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn job_name_override() {
+        let names = JobNameOverride;
+        let call = job("{template: build.yml, parameters: {jobNameOverride: compile}}");
+        assert_eq!(names.job_names(&call).unwrap(), ["compile"]);
+        assert!(
+            names
+                .job_names(&job("template: build.yml"))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn nested_stage_templates_are_expanded() {
+        let names = ExpandTemplate::new(SOURCES);
+        assert_eq!(
+            names
+                .stage_names(&stage("{template: stages.yml, parameters: {env: prod}}"))
+                .unwrap(),
+            ["build", "deploy_prod"]
+        );
+    }
+
+    #[test]
+    fn expansion_errors() {
+        let names = ExpandTemplate::new(SOURCES);
+        let error = names
+            .stage_names(&stage("template: missing.yml"))
+            .unwrap_err();
+        assert_eq!(error.code, codes::TEMPLATE_EXPANSION);
+
+        let error = names.job_names(&job("template: jobs.yml")).unwrap_err();
+        assert!(error.message.contains("nested more than 20 levels"));
+
+        // nested calls made through a registry count towards the same depth
+        let names = NameRegistry::with_resolver(SOURCES);
+        let error = names.job_names(&job("template: jobs.yml")).unwrap_err();
+        assert!(error.message.contains("nested more than 20 levels"));
+    }
+
+    #[test]
+    fn registry_prefers_registered_providers() {
+        let mut names = NameRegistry::with_resolver(SOURCES);
+        names.register("jobs.yml", JobNameOverride);
+        let call = job("{template: jobs.yml, parameters: {jobNameOverride: check}}");
+        assert_eq!(names.job_names(&call).unwrap(), ["check"]);
+        assert_eq!(
+            names
+                .stage_names(&stage("template: deploy.yml"))
+                .unwrap_err()
+                .code,
+            codes::TEMPLATE_EXPANSION
+        );
+        assert!(
+            NameRegistry::default()
+                .stage_names(&stage("template: stages.yml"))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    /// Names every stage template's stages after the template
+    struct Named;
+
+    impl NameProvider for Named {
+        fn stage_names(&self, stage: &StageWithTemplate) -> Result<Vec<String>, Diagnostic> {
+            Ok(stage.template.clone().into_iter().collect())
+        }
+    }
+
+    #[test]
+    fn nested_paths_are_relative_to_the_including_template() {
+        let names = ExpandTemplate::new(SOURCES);
+        assert_eq!(
+            names.stage_names(&stage("template: ci/all.yml")).unwrap(),
+            ["deploy", "verify"]
+        );
+
+        let mut names = NameRegistry::with_resolver(SOURCES);
+        names.register("ci/verify.yml", Named);
+        assert_eq!(
+            names.stage_names(&stage("template: ci/all.yml")).unwrap(),
+            ["deploy", "ci/verify.yml"]
+        );
+        names.register("ci/deploy/all.yml", Named);
+        assert_eq!(
+            names.stage_names(&stage("template: ci/all.yml")).unwrap(),
+            ["ci/deploy/all.yml"]
+        );
+    }
+}
@@ -0,0 +1,430 @@
+//! Check the parameters passed to templates against the templates' declarations
+//!
+//! A template call passes `parameters:` to a template that declares its own in
+//! another file, so nothing checks one against the other until Azure DevOps
+//! expands the pipeline. These checks resolve each template and report
+//! unknown parameters, missing required ones, values of the wrong type and
+//! values outside the declared `values`.
+//!
+//! <https://learn.microsoft.com/en-us/azure/devops/pipelines/process/template-parameters?view=azure-pipelines>
+
+use std::error::Error;
+
+use indexmap::IndexMap;
+use serde_yaml::{Mapping, Value};
+
+use crate::{
+    core::v1::{extends::Extends, job::Job, pipeline::PipelineParameter, stage::Stage, step::Step},
+    templates::{resolver::TemplateResolver, template::declared_parameters},
+    validator::diagnostic::{AstPath, Diagnostic, PathSegment, codes},
+};
+
+/// Check the parameters passed to every stage, job and step template called
+/// in `stages`, loading the templates through `resolver`. Calls whose template
+/// is itself an expression can't be resolved and are skipped.
+pub fn template_parameter_diagnostics(
+    stages: &[Stage],
+    resolver: &dyn TemplateResolver,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut call = |template: &Option<String>, passed: &IndexMap<String, Value>, path: AstPath| {
+        if let Some(template) = template {
+            diagnostics.extend(check_call(resolver, template, passed, &path));
+        }
+    };
+
+    for (index, stage) in stages.iter().enumerate() {
+        let (name, jobs) = match stage {
+            Stage::Stage(stage) => (stage.name.clone(), &stage.jobs[..]),
+            Stage::Template(template) => {
+                let path = AstPath::default().join(PathSegment::Stage { index, name: None });
+                call(&template.template, &template.parameters, path);
+                continue;
+            }
+        };
+        let stage_path = AstPath::default().join(PathSegment::Stage { index, name });
+
+        for (index, job) in jobs.iter().enumerate() {
+            let (name, steps) = match job {
+                Job::Job(job) => (job.name.clone(), &job.steps[..]),
+                Job::Template(template) => {
+                    let path = stage_path.join(PathSegment::Job { index, name: None });
+                    call(&template.template, &template.parameters, path);
+                    continue;
+                }
+            };
+            let job_path = stage_path.join(PathSegment::Job { index, name });
+
+            for (index, step) in steps.iter().enumerate() {
+                if let Step::Template(template) = step {
+                    let path = job_path.join(PathSegment::Step { index, name: None });
+                    call(&template.template, &template.parameters, path);
+                }
+            }
+        }
+    }
+
+    diagnostics
+}
+
+/// Check the parameters a pipeline passes to the template it extends, loading
+/// the template through `resolver`. Paths start at the pipeline, so locate
+/// them with no prefix.
+pub fn extends_parameter_diagnostics(
+    extends: &Extends,
+    resolver: &dyn TemplateResolver,
+) -> Vec<Diagnostic> {
+    let path = AstPath::default().field("extends");
+    check_call(resolver, &extends.template, &extends.parameters, &path)
+}
+
+/// Check the parameters passed to `template` at `path`
+fn check_call(
+    resolver: &dyn TemplateResolver,
+    template: &str,
+    passed: &IndexMap<String, Value>,
+    path: &AstPath,
+) -> Vec<Diagnostic> {
+    if template.contains("${{") {
+        return Vec::new();
+    }
+    match declarations(resolver, template) {
+        Ok(declared) => check_parameters(template, &declared, passed, path),
+        Err(e) => vec![
+            Diagnostic::error(
+                codes::TEMPLATE_EXPANSION,
+                format!("template {template}: {e}"),
+            )
+            .at(path.field("template")),
+        ],
+    }
+}
+
+/// The parameters a template declares
+fn declarations(
+    resolver: &dyn TemplateResolver,
+    template: &str,
+) -> Result<Vec<PipelineParameter>, Box<dyn Error>> {
+    let mut document: Mapping = serde_yaml::from_str(&resolver.resolve(template)?)?;
+    declared_parameters(document.remove("parameters"))
+}
+
+/// Check the parameters passed to `template` at `path` against the parameters
+/// it declares
+pub fn check_parameters(
+    template: &str,
+    declared: &[PipelineParameter],
+    passed: &IndexMap<String, Value>,
+    path: &AstPath,
+) -> Vec<Diagnostic> {
+    let parameters = path.field("parameters");
+    let mut diagnostics = Vec::new();
+
+    for name in passed.keys() {
+        if declared.iter().all(|parameter| parameter.name != *name) {
+            let names: Vec<String> = declared
+                .iter()
+                .map(|parameter| format!("`{}`", parameter.name))
+                .collect();
+            let note = if names.is_empty() {
+                format!("{template} doesn't declare any parameters")
+            } else {
+                format!("{template} declares {}", names.join(", "))
+            };
+            diagnostics.push(
+                Diagnostic::error(
+                    codes::TEMPLATE_PARAMETER,
+                    format!("`{name}` isn't a parameter of {template}"),
+                )
+                .at(parameters.field(name))
+                .with_note(note),
+            );
+        }
+    }
+
+    for parameter in declared {
+        let name = &parameter.name;
+        let Some(value) = passed.get(name) else {
+            if parameter.default.is_none() {
+                diagnostics.push(
+                    Diagnostic::error(
+                        codes::TEMPLATE_PARAMETER,
+                        format!("missing parameter `{name}`, which {template} requires"),
+                    )
+                    .at(path.clone()),
+                );
+            }
+            continue;
+        };
+        if is_expression(value) {
+            continue;
+        }
+        if let Err(message) = convert(parameter, value) {
+            diagnostics.push(
+                Diagnostic::error(
+                    codes::TEMPLATE_PARAMETER,
+                    format!("parameter `{name}` of {template}: {message}"),
+                )
+                .at(parameters.field(name)),
+            );
+        }
+    }
+
+    diagnostics
+}
+
+/// Whether a value is a template expression, which is only known once the
+/// pipeline is expanded
+fn is_expression(value: &Value) -> bool {
+    matches!(value, Value::String(s) if s.contains("${{"))
+}
+
+/// Convert a value to its parameter's type the way Azure DevOps does, and
+/// check that it's one of the declared `values`. Scalars convert to and from
+/// strings, so `"true"` is a boolean and `"3"` a number.
+pub(crate) fn convert(parameter: &PipelineParameter, value: &Value) -> Result<Value, String> {
+    let value = convert_type(value, &parameter.parameter_type)?;
+    if !parameter.values.is_empty()
+        && let Some(chosen) = scalar(&value)
+        && parameter
+            .values
+            .iter()
+            .all(|allowed| scalar(allowed).as_deref() != Some(chosen.as_str()))
+    {
+        let allowed: Vec<String> = parameter
+            .values
+            .iter()
+            .filter_map(scalar)
+            .map(|allowed| format!("`{allowed}`"))
+            .collect();
+        return Err(format!("`{chosen}` isn't one of {}", allowed.join(", ")));
+    }
+    Ok(value)
+}
+
+fn convert_type(value: &Value, parameter_type: &str) -> Result<Value, String> {
+    let invalid = |value: &Value| {
+        Err(format!(
+            "expected {}, found {}",
+            expected(parameter_type),
+            kind(value)
+        ))
+    };
+    match (parameter_type, value) {
+        (_, Value::Tagged(tagged)) => convert_type(&tagged.value, parameter_type),
+        ("string" | "filePath", value) => match scalar(value) {
+            Some(value) => Ok(Value::String(value)),
+            None => invalid(value),
+        },
+        ("number", Value::Number(_)) => Ok(value.clone()),
+        ("number", Value::String(s)) => match serde_yaml::from_str(s.trim()) {
+            Ok(number @ Value::Number(_)) => Ok(number),
+            _ => Err(format!("`{s}` isn't a number")),
+        },
+        ("boolean", Value::Bool(_)) => Ok(value.clone()),
+        ("boolean", Value::String(s)) if s.trim().eq_ignore_ascii_case("true") => {
+            Ok(Value::Bool(true))
+        }
+        ("boolean", Value::String(s)) if s.trim().eq_ignore_ascii_case("false") => {
+            Ok(Value::Bool(false))
+        }
+        ("boolean", Value::String(s)) => Err(format!("`{s}` isn't `true` or `false`")),
+        ("number" | "boolean", value) => invalid(value),
+        ("step" | "job" | "deployment" | "stage" | "container", value) if !value.is_mapping() => {
+            invalid(value)
+        }
+        ("stepList" | "jobList" | "deploymentList" | "stageList" | "containerList", value)
+            if !value.is_sequence() =>
+        {
+            invalid(value)
+        }
+        // `object`, and types this crate doesn't know
+        (_, value) => Ok(value.clone()),
+    }
+}
+
+/// What a parameter type expects, for messages
+fn expected(parameter_type: &str) -> String {
+    match parameter_type {
+        "number" => "a number".to_string(),
+        "boolean" => "a boolean".to_string(),
+        "string" | "filePath" => "a string".to_string(),
+        "stepList" | "jobList" | "deploymentList" | "stageList" | "containerList" => {
+            format!("a sequence for `{parameter_type}`")
+        }
+        _ => format!("a mapping for `{parameter_type}`"),
+    }
+}
+
+/// A scalar value as the string Azure DevOps compares it as
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => Some(String::new()),
+        Value::Bool(value) => Some(value.to_string()),
+        Value::Number(value) => Some(value.to_string()),
+        Value::String(value) => Some(value.clone()),
+        Value::Tagged(tagged) => scalar(&tagged.value),
+        Value::Sequence(_) | Value::Mapping(_) => None,
+    }
+}
+
+/// What kind of value was passed, for messages
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Sequence(_) => "a sequence",
+        Value::Mapping(_) => "a mapping",
+        Value::Tagged(tagged) => kind(&tagged.value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Sources(&'static [(&'static str, &'static str)]);
+
+    impl TemplateResolver for Sources {
+        fn resolve(&self, template: &str) -> Result<String, Box<dyn Error>> {
+            self.0
+                .iter()
+                .find(|(path, _)| *path == template)
+                .map(|(_, source)| source.to_string())
+                .ok_or_else(|| format!("no template {template}").into())
+        }
+    }
+
+    const SOURCES: Sources = Sources(&[(
+        "build.yml",
+        "\
+parameters:
+- name: os
+  type: string
+  values: [linux, windows]
+- name: retries
+  type: number
+  default: 1
+- name: fast
+  type: boolean
+  default: false
+- name: extraSteps
+  type: stepList
+  default: []
+steps: []
+",
+    )]);
+
+    fn messages(stages: &str) -> Vec<(String, String)> {
+        let stages: Vec<Stage> = serde_yaml::from_str(stages).unwrap();
+        template_parameter_diagnostics(&stages, &SOURCES)
+            .into_iter()
+            .map(|d| {
+                assert_eq!(d.code, codes::TEMPLATE_PARAMETER);
+                (d.path.to_string(), d.message)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn valid_calls() {
+        let stages = "\
+- stage: build
+  jobs:
+  - job: compile
+    steps:
+    - template: build.yml
+      parameters:
+        os: linux
+        retries: '3'
+        fast: 'True'
+        extraSteps:
+        - task: Bash@3
+    - template: build.yml
+      parameters:
+        os: ${{ parameters.os }}
+        fast: ${{ parameters.fast }}
+    - template: ${{ parameters.template }}
+";
+        assert!(messages(stages).is_empty());
+    }
+
+    #[test]
+    fn invalid_calls() {
+        let stages = "\
+- stage: build
+  jobs:
+  - job: compile
+    steps:
+    - template: build.yml
+      parameters:
+        os: macos
+        retries: many
+        fast: [true]
+        extraSteps: {task: Bash@3}
+        verbose: true
+    - template: build.yml
+";
+        let step = "stage \"build\" > job \"compile\" > step #0 > parameters";
+        assert_eq!(
+            messages(stages),
+            [
+                (
+                    format!("{step} > verbose"),
+                    "`verbose` isn't a parameter of build.yml".to_string()
+                ),
+                (
+                    format!("{step} > os"),
+                    "parameter `os` of build.yml: `macos` isn't one of `linux`, `windows`"
+                        .to_string()
+                ),
+                (
+                    format!("{step} > retries"),
+                    "parameter `retries` of build.yml: `many` isn't a number".to_string()
+                ),
+                (
+                    format!("{step} > fast"),
+                    "parameter `fast` of build.yml: expected a boolean, found a sequence"
+                        .to_string()
+                ),
+                (
+                    format!("{step} > extraSteps"),
+                    "parameter `extraSteps` of build.yml: expected a sequence for `stepList`, \
+                     found a mapping"
+                        .to_string()
+                ),
+                (
+                    "stage \"build\" > job \"compile\" > step #1".to_string(),
+                    "missing parameter `os`, which build.yml requires".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn unresolved_templates() {
+        let stages: Vec<Stage> = serde_yaml::from_str("- template: missing.yml\n").unwrap();
+        let diagnostics = template_parameter_diagnostics(&stages, &SOURCES);
+        assert_eq!(diagnostics[0].code, codes::TEMPLATE_EXPANSION);
+        assert_eq!(diagnostics[0].path.to_string(), "stage #0 > template");
+    }
+
+    #[test]
+    fn extends() {
+        let extends: Extends =
+            serde_yaml::from_str("template: build.yml\nparameters: {os: windows, fast: no}\n")
+                .unwrap();
+        let diagnostics = extends_parameter_diagnostics(&extends, &SOURCES);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].path.to_string(),
+            "extends > parameters > fast"
+        );
+        assert_eq!(
+            diagnostics[0].message,
+            "parameter `fast` of build.yml: `no` isn't `true` or `false`"
+        );
+    }
+}
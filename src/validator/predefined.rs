@@ -0,0 +1,171 @@
+//! Check that predefined variables are only read where they're set
+//!
+//! Azure DevOps sets predefined variables at different times (see
+//! [`predefined`](crate::core::v1::predefined)). Reading one where it isn't
+//! set isn't an error, it's just empty: `${{ variables['Agent.OS'] }}` is
+//! always empty, because templates are expanded before any agent is chosen,
+//! and so is `$(Agent.OS)` in an agentless job.
+
+use crate::{
+    core::v1::{pipeline::PipelineVariable, predefined::lookup, stage::Stage},
+    validator::{
+        diagnostic::{AstPath, Diagnostic, codes},
+        variables::{Context, Syntax, Visitor, visit},
+    },
+};
+
+/// Warn about every predefined variable read where Azure DevOps doesn't set
+/// it, in a pipeline declaring `variables` whose stages are `stages`: in a
+/// template expression, in a stage or job condition (which are evaluated
+/// before the job has an agent), or in an agentless job. Paths are those of
+/// [`variable_diagnostics`](super::variables::variable_diagnostics).
+pub fn predefined_variable_diagnostics(
+    variables: &[PipelineVariable],
+    stages: &[Stage],
+) -> Vec<Diagnostic> {
+    let mut checker = Checker::default();
+    visit(variables, &[], stages, &mut checker);
+    checker.diagnostics
+}
+
+#[derive(Default)]
+struct Checker {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Visitor<'_> for Checker {
+    /// Check a reference to `name` from `path`
+    fn reference(&mut self, name: &str, syntax: Syntax, path: &AstPath, context: Context) {
+        let Some(variable) = lookup(name) else {
+            return;
+        };
+        let name = variable.name;
+        // where the reference is read without an agent, if it is
+        let agentless = match context {
+            Context::StageCondition => Some("a stage condition"),
+            Context::JobCondition => Some("a job condition"),
+            Context::Job { agentless: true } => Some("an agentless job"),
+            Context::Job { agentless: false } | Context::Settings => None,
+        };
+        if syntax == Syntax::Template && !variable.template {
+            self.diagnostics.push(
+                Diagnostic::warning(
+                    codes::VARIABLE_AVAILABILITY,
+                    format!("`{name}` isn't set yet when `${{{{ }}}}` is expanded, so it's empty"),
+                )
+                .at(path.clone())
+                .with_note(format!(
+                    "read it when the job runs with `$({name})` or `$[ variables['{name}'] ]`"
+                )),
+            );
+        } else if let Some(context) = agentless
+            && !variable.agentless
+        {
+            self.diagnostics.push(
+                Diagnostic::warning(
+                    codes::VARIABLE_AVAILABILITY,
+                    format!("`{name}` is set by the agent, so it's empty in {context}"),
+                )
+                .at(path.clone()),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostics(stages: &str) -> Vec<(String, String)> {
+        let stages: Vec<Stage> = serde_yaml::from_str(stages).unwrap();
+        predefined_variable_diagnostics(&[], &stages)
+            .into_iter()
+            .map(|d| (d.path.to_string(), d.message))
+            .collect()
+    }
+
+    #[test]
+    fn template_expressions() {
+        let stages = "\
+- stage: build
+  displayName: ${{ variables['Build.SourceBranch'] }}
+  jobs:
+  - job: compile
+    pool: ${{ variables['Build.BuildNumber'] }}
+    steps:
+    - task: Bash@3
+      displayName: $(Build.BuildNumber)
+";
+        assert_eq!(
+            diagnostics(stages),
+            [(
+                "stage \"build\" > job \"compile\" > pool".to_string(),
+                "`Build.BuildNumber` isn't set yet when `${{ }}` is expanded, so it's empty"
+                    .to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn agentless_reads() {
+        let stages = "\
+- stage: build
+  condition: ne(variables['Agent.OS'], '')
+  jobs:
+  - job: compile
+    condition: eq(variables['Build.SourceBranch'], 'refs/heads/main')
+    steps:
+    - task: Bash@3
+      inputs:
+        script: echo $(Agent.OS)
+  - job: approve
+    pool: server
+    variables:
+      os: $(Agent.OS)
+    steps:
+    - task: ManualValidation@0
+      retryCountOnTaskFailure: $(Agent.OSArchitecture)
+      inputs:
+        instructions: $(Build.BuildNumber)
+";
+        assert_eq!(
+            diagnostics(stages),
+            [
+                (
+                    "stage \"build\" > condition".to_string(),
+                    "`Agent.OS` is set by the agent, so it's empty in a stage condition"
+                        .to_string()
+                ),
+                (
+                    "stage \"build\" > job \"approve\" > variables > os".to_string(),
+                    "`Agent.OS` is set by the agent, so it's empty in an agentless job".to_string()
+                ),
+                (
+                    "stage \"build\" > job \"approve\" > step #0 > retryCountOnTaskFailure"
+                        .to_string(),
+                    "`Agent.OSArchitecture` is set by the agent, so it's empty in an agentless job"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn job_conditions() {
+        let stages = "\
+- stage: build
+  jobs:
+  - job: test
+    condition: ne(variables.Agent.WorkFolder, '')
+    steps: []
+";
+        assert_eq!(
+            diagnostics(stages),
+            [(
+                "stage \"build\" > job \"test\" > condition".to_string(),
+                "`Agent.WorkFolder` is set by the agent, so it's empty in a job condition"
+                    .to_string()
+            )]
+        );
+    }
+}
@@ -0,0 +1,346 @@
+//! Check the `parameters.*` references in a template against the parameters
+//! it declares
+//!
+//! Parameters are only available to template expressions (`${{ }}`), so every
+//! reference is found in the template's source before it's expanded: in
+//! values, and in `if`, `elseif` and `each` directives.
+
+use std::error::Error;
+
+use serde_yaml::Mapping;
+
+use crate::{
+    expressions::{
+        ast::{Expr, ExprKind, Literal},
+        parser,
+    },
+    source::{map::SourceNode, parse},
+    templates::{
+        expand::expressions,
+        template::{Template, declared_parameters},
+    },
+    validator::diagnostic::{AstPath, Diagnostic, PathSegment, Span, codes},
+};
+
+/// How an expression refers to the template's parameters
+enum Reference {
+    /// One parameter, e.g. `parameters.name` or `parameters['name']`
+    Named(String),
+    /// All of them at once, e.g. `${{ each parameter in parameters }}`
+    All,
+}
+
+/// Report every reference to a parameter that the template or pipeline in
+/// `source` doesn't declare, and warn about every declared parameter that
+/// nothing refers to
+pub fn parameter_reference_diagnostics(source: &str) -> Result<Vec<Diagnostic>, Box<dyn Error>> {
+    let parsed = parse::from_str::<Mapping>(source)?;
+    let declared = declared_parameters(parsed.value.get("parameters").cloned())?;
+    let root = &parsed.source.root;
+
+    let mut references = Vec::new();
+    if let SourceNode::Mapping { entries, .. } = root {
+        for (key, value) in entries {
+            if matches!(key, SourceNode::Scalar { value, .. } if value == "parameters") {
+                continue;
+            }
+            collect_entry(key, value, &AstPath::default(), &mut references);
+        }
+    }
+
+    let mut diagnostics = Vec::new();
+    for (reference, path, span) in &references {
+        if let Reference::Named(name) = reference
+            && !declared
+                .iter()
+                .any(|parameter| parameter.name.eq_ignore_ascii_case(name))
+        {
+            diagnostics.push(
+                Diagnostic::error(
+                    codes::PARAMETER_UNDECLARED,
+                    format!("`parameters.{name}` isn't declared"),
+                )
+                .at(path.clone())
+                .with_span(Some(*span)),
+            );
+        }
+    }
+
+    if references
+        .iter()
+        .any(|(reference, ..)| matches!(reference, Reference::All))
+    {
+        return Ok(diagnostics);
+    }
+    for (index, parameter) in declared.iter().enumerate() {
+        let used = references.iter().any(|(reference, ..)| match reference {
+            Reference::Named(name) => name.eq_ignore_ascii_case(&parameter.name),
+            Reference::All => false,
+        });
+        if !used {
+            let node = root
+                .at(&["parameters".into(), index.into()])
+                .or_else(|| root.at(&["parameters".into(), parameter.name.as_str().into()]));
+            diagnostics.push(
+                Diagnostic::warning(
+                    codes::PARAMETER_UNUSED,
+                    format!("parameter `{}` is never used", parameter.name),
+                )
+                .at(AstPath::default()
+                    .field("parameters")
+                    .join(PathSegment::Item {
+                        index,
+                        name: Some(parameter.name.clone()),
+                    }))
+                .with_span(node.map(SourceNode::span)),
+            );
+        }
+    }
+    Ok(diagnostics)
+}
+
+/// Report every reference to a parameter that `template` doesn't declare, and
+/// warn about every declared parameter that nothing refers to
+///
+/// `template` must still hold its template expressions, e.g. one read with
+/// [`parse::from_str_lenient`] rather than expanded. It has no source text,
+/// so the diagnostics have no spans; check a template's source with
+/// [`parameter_reference_diagnostics`] to locate them, or if its lists hold
+/// `${{ if }}` or `${{ each }}` directives, which a `Template` can't.
+pub fn template_reference_diagnostics(
+    template: &Template,
+) -> Result<Vec<Diagnostic>, Box<dyn Error>> {
+    let source = serde_yaml::to_string(template)?;
+    let mut diagnostics = parameter_reference_diagnostics(&source)?;
+    for diagnostic in &mut diagnostics {
+        // a span would point into the text serialized above
+        diagnostic.span = None;
+    }
+    Ok(diagnostics)
+}
+
+/// Collect the parameter references in every scalar under `node`, which is
+/// at `path`
+fn collect(node: &SourceNode, path: &AstPath, out: &mut Vec<(Reference, AstPath, Span)>) {
+    match node {
+        SourceNode::Scalar { span, value } => {
+            for expression in expressions(value) {
+                // syntax errors are reported when the template is expanded
+                if let Ok(expr) = parser::parse(expression) {
+                    let mut found = Vec::new();
+                    references(&expr, &mut found);
+                    out.extend(
+                        found
+                            .into_iter()
+                            .map(|reference| (reference, path.clone(), *span)),
+                    );
+                }
+            }
+        }
+        SourceNode::Sequence { items, .. } => {
+            for (index, item) in items.iter().enumerate() {
+                let path = path.join(PathSegment::Item { index, name: None });
+                collect(item, &path, out);
+            }
+        }
+        SourceNode::Mapping { entries, .. } => {
+            for (key, value) in entries {
+                collect_entry(key, value, path, out);
+            }
+        }
+        SourceNode::Alias { .. } => {}
+    }
+}
+
+/// Collect the parameter references in an entry of the mapping at `path`
+fn collect_entry(
+    key: &SourceNode,
+    value: &SourceNode,
+    path: &AstPath,
+    out: &mut Vec<(Reference, AstPath, Span)>,
+) {
+    collect(key, path, out);
+    match (key, value) {
+        (SourceNode::Scalar { value: key, .. }, SourceNode::Sequence { items, .. })
+            if matches!(key.as_str(), "stages" | "jobs" | "steps") =>
+        {
+            // stage paths name their list, while job and step paths are found
+            // through their parent's `jobs` or `steps`, as in `validate_stages`
+            let list = match key.as_str() {
+                "stages" => path.field(key),
+                _ => path.clone(),
+            };
+            for (index, item) in items.iter().enumerate() {
+                collect(item, &list.join(segment(key, index, item)), out);
+            }
+        }
+        (SourceNode::Scalar { value: key, .. }, _) => collect(value, &path.field(key), out),
+        _ => collect(value, path, out),
+    }
+}
+
+/// The path segment of the item at `index` of a `stages`, `jobs` or `steps`
+/// list, named like the stages, jobs and steps of a parsed pipeline
+fn segment(list: &str, index: usize, item: &SourceNode) -> PathSegment {
+    let name = |key: &str| match item.get(key) {
+        Some(SourceNode::Scalar { value, .. }) => Some(value.clone()),
+        _ => None,
+    };
+    match list {
+        "stages" => PathSegment::Stage {
+            index,
+            name: name("stage"),
+        },
+        "jobs" => PathSegment::Job {
+            index,
+            name: name("job").or_else(|| name("deployment")),
+        },
+        _ => PathSegment::Step {
+            index,
+            name: name("name"),
+        },
+    }
+}
+
+/// The parameter references in an expression
+fn references(expr: &Expr, out: &mut Vec<Reference>) {
+    match &expr.kind {
+        ExprKind::Property { target, name } if is_parameters(target) => {
+            out.push(Reference::Named(name.clone()));
+        }
+        ExprKind::Index { target, index } if is_parameters(target) => match &index.kind {
+            ExprKind::Literal(Literal::String(name)) => out.push(Reference::Named(name.clone())),
+            _ => {
+                out.push(Reference::All);
+                references(index, out);
+            }
+        },
+        ExprKind::NamedValue(_) if is_parameters(expr) => out.push(Reference::All),
+        ExprKind::Property { target, .. } => references(target, out),
+        ExprKind::Index { target, index } => {
+            references(target, out);
+            references(index, out);
+        }
+        ExprKind::Call { args, .. } => {
+            for arg in args {
+                references(arg, out);
+            }
+        }
+        ExprKind::Literal(_) | ExprKind::NamedValue(_) => {}
+    }
+}
+
+/// Whether an expression is the `parameters` named value itself
+fn is_parameters(expr: &Expr) -> bool {
+    matches!(&expr.kind, ExprKind::NamedValue(name) if name.eq_ignore_ascii_case("parameters"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::source::map::SourceMap;
+
+    use super::*;
+
+    const TEMPLATE: &str = "\
+parameters:
+- name: os
+  type: string
+- name: unused
+  type: string
+stages:
+- stage: build
+  condition: eq('${{ parameters.os }}', 'linux')
+  jobs:
+  - job: compile
+    steps:
+    - task: Bash@3
+      name: run
+      inputs:
+        script: echo ${{ parameters.missing }}
+- stage: test
+  jobs:
+  - job: unit
+    steps:
+    - task: Bash@3
+      displayName: ${{ parameters['other'] }}
+variables:
+- name: x
+  value: ${{ parameters.OS }}
+";
+
+    fn found(diagnostics: &[Diagnostic]) -> Vec<(String, String)> {
+        diagnostics
+            .iter()
+            .map(|d| (d.path.to_string(), d.message.clone()))
+            .collect()
+    }
+
+    fn expected() -> Vec<(String, String)> {
+        [
+            (
+                "stages > stage \"build\" > job \"compile\" > step \"run\" > inputs > script",
+                "`parameters.missing` isn't declared",
+            ),
+            (
+                "stages > stage \"test\" > job \"unit\" > step #0 > displayName",
+                "`parameters.other` isn't declared",
+            ),
+            (
+                "parameters > \"unused\"",
+                "parameter `unused` is never used",
+            ),
+        ]
+        .map(|(path, message)| (path.to_string(), message.to_string()))
+        .to_vec()
+    }
+
+    #[test]
+    fn source() {
+        let diagnostics = parameter_reference_diagnostics(TEMPLATE).unwrap();
+        assert_eq!(found(&diagnostics), expected());
+        assert_eq!(diagnostics[0].code, codes::PARAMETER_UNDECLARED);
+        assert_eq!(diagnostics[2].code, codes::PARAMETER_UNUSED);
+
+        // paths locate the same nodes as the spans
+        let map = SourceMap::new(TEMPLATE).unwrap();
+        for diagnostic in &diagnostics {
+            assert_eq!(
+                map.span(&[], &diagnostic.path).as_ref(),
+                diagnostic.span.as_deref(),
+                "{}",
+                diagnostic.path
+            );
+        }
+    }
+
+    #[test]
+    fn template() {
+        let template = parse::from_str_lenient::<Template>(TEMPLATE).unwrap().value;
+        let diagnostics = template_reference_diagnostics(&template).unwrap();
+        assert_eq!(found(&diagnostics), expected());
+        assert!(diagnostics.iter().all(|d| d.span.is_none()));
+    }
+
+    #[test]
+    fn directives_and_every_parameter() {
+        let source = "\
+parameters:
+- name: stages
+  type: stageList
+stages:
+- ${{ each stage in parameters.stages }}:
+  - ${{ stage }}
+";
+        assert!(parameter_reference_diagnostics(source).unwrap().is_empty());
+
+        let source = "\
+parameters:
+- name: a
+  type: string
+steps:
+- ${{ each pair in parameters }}:
+  - script: echo ${{ pair.key }}
+";
+        assert!(parameter_reference_diagnostics(source).unwrap().is_empty());
+    }
+}
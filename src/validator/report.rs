@@ -0,0 +1,179 @@
+//! Run every validator over a pipeline in one pass
+
+use std::{error::Error, fmt};
+
+use crate::{
+    core::v1::stage::Stage,
+    source::map::SourceMap,
+    validator::{
+        conditions::condition_diagnostics,
+        dependencies::dependency_diagnostics,
+        diagnostic::{Diagnostic, Severity},
+        names::NameProvider,
+        unknown::unknown_field_diagnostics,
+    },
+};
+
+/// Whether a validation pass stops at the first error
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum Mode {
+    /// Run every validator and report everything found
+    #[default]
+    CollectAll,
+    /// Stop at the first error and report only that
+    FailFast,
+}
+
+/// Everything found by a validation pass
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Report {
+    /// Every diagnostic, in the order the validators found them
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Report {
+    /// Diagnostics with the given severity
+    pub fn with_severity(&self, severity: Severity) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(move |d| d.severity == severity)
+    }
+
+    /// Whether any diagnostic is an error
+    pub fn has_errors(&self) -> bool {
+        self.with_severity(Severity::Error).next().is_some()
+    }
+
+    /// Fill in the source span of every diagnostic from where its path points
+    /// in `source`, where the list of stages is found at `stages_at` (e.g.
+    /// `["stages"]`, or `["extends", "parameters", "stages"]` for stages
+    /// passed to an extends template)
+    pub fn locate(&mut self, source: &SourceMap, stages_at: &[&str]) {
+        for diagnostic in &mut self.diagnostics {
+            if diagnostic.span.is_none() {
+                diagnostic.span = source.span(stages_at, &diagnostic.path).map(Box::new);
+            }
+        }
+    }
+
+    /// `Ok` with the remaining (non-error) diagnostics if there are no errors,
+    /// otherwise the whole report
+    pub fn into_result(self) -> Result<Vec<Diagnostic>, Report> {
+        if self.has_errors() {
+            Err(self)
+        } else {
+            Ok(self.diagnostics)
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for diagnostic in &self.diagnostics {
+            writeln!(f, "{diagnostic}")?;
+        }
+        let errors = self.with_severity(Severity::Error).count();
+        let warnings = self.with_severity(Severity::Warning).count();
+        write!(f, "{errors} error(s), {warnings} warning(s)")
+    }
+}
+
+impl Error for Report {}
+
+/// Validate the dependencies and conditions of every stage, using `names` to
+/// learn the stages and jobs produced by templates, and warn about any unknown
+/// keys kept by lenient parsing
+pub fn validate_stages(stages: &[Stage], names: &dyn NameProvider, mode: Mode) -> Report {
+    let passes: [&dyn Fn() -> Vec<Diagnostic>; 3] = [
+        &|| dependency_diagnostics(stages, names),
+        &|| condition_diagnostics(stages),
+        &|| unknown_field_diagnostics(stages),
+    ];
+
+    let mut report = Report::default();
+    for pass in passes {
+        report.diagnostics.extend(pass());
+        if mode == Mode::FailFast
+            && let Some(first) = report
+                .diagnostics
+                .iter()
+                .position(|d| d.severity == Severity::Error)
+        {
+            report.diagnostics.truncate(first + 1);
+            break;
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::validator::{diagnostic::codes, names::JobNameOverride};
+
+    use super::*;
+
+    const PIPELINE: &str = "\
+stages:
+- stage: build
+  dependsOn: [prepare, fetch]
+  jobs:
+  - job: compile
+    dependsOn: configure
+    condition: nope()
+- stage: test
+  dependsOn: package
+  jobs: []
+";
+
+    fn stages() -> Vec<Stage> {
+        let mut document: serde_yaml::Mapping = serde_yaml::from_str(PIPELINE).unwrap();
+        serde_yaml::from_value(document.remove("stages").unwrap()).unwrap()
+    }
+
+    fn problem_codes(report: &Report) -> Vec<&'static str> {
+        report.diagnostics.iter().map(|d| d.code).collect()
+    }
+
+    #[test]
+    fn collect_all_reports_every_problem() {
+        let report = validate_stages(&stages(), &JobNameOverride, Mode::CollectAll);
+        assert_eq!(
+            problem_codes(&report),
+            [
+                codes::DEPENDS_ON_UNKNOWN,
+                codes::DEPENDS_ON_UNKNOWN,
+                codes::DEPENDS_ON_UNKNOWN,
+                codes::DEPENDS_ON_UNKNOWN,
+                codes::CONDITION_UNKNOWN_FUNCTION,
+            ]
+        );
+        assert!(report.has_errors());
+        assert!(report.to_string().ends_with("5 error(s), 0 warning(s)"));
+        assert_eq!(report.into_result().unwrap_err().diagnostics.len(), 5);
+    }
+
+    #[test]
+    fn fail_fast_reports_the_first_error() {
+        let report = validate_stages(&stages(), &JobNameOverride, Mode::FailFast);
+        assert_eq!(problem_codes(&report), [codes::DEPENDS_ON_UNKNOWN]);
+        assert!(report.diagnostics[0].message.contains("prepare"));
+    }
+
+    #[test]
+    fn no_problems_is_ok() {
+        let report = validate_stages(&[], &JobNameOverride, Mode::FailFast);
+        assert_eq!(report.into_result().unwrap(), []);
+    }
+
+    #[test]
+    fn locate_fills_in_spans() {
+        let mut report = validate_stages(&stages(), &JobNameOverride, Mode::CollectAll);
+        report.locate(&SourceMap::new(PIPELINE).unwrap(), &["stages"]);
+        let lines: Vec<_> = report
+            .diagnostics
+            .iter()
+            .map(|d| d.span.as_ref().unwrap().start.line)
+            .collect();
+        assert_eq!(lines, [3, 3, 9, 6, 7]);
+    }
+}
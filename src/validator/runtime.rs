@@ -0,0 +1,241 @@
+//! Check the values a pipeline is queued with against its runtime parameters
+//!
+//! A pipeline's `parameters:` are chosen when a run is queued, e.g. in the
+//! "Run pipeline" dialog. [`resolve`] checks the chosen values the way Azure
+//! DevOps does before the run starts: each is converted to its parameter's
+//! type, parameters that aren't given take their default, and values must be
+//! among the declared `values`. Defaults are converted and checked the same way.
+//!
+//! Values typically come from the command line, read with [`assignment`], or
+//! from a JSON or YAML file read into an `IndexMap<String, Value>`.
+//!
+//! <https://learn.microsoft.com/en-us/azure/devops/pipelines/process/runtime-parameters?view=azure-pipelines>
+
+use indexmap::IndexMap;
+use serde_yaml::Value;
+
+use crate::{
+    core::v1::pipeline::PipelineParameter,
+    validator::{
+        diagnostic::{AstPath, Diagnostic, PathSegment, codes},
+        parameters::convert,
+        report::Report,
+    },
+};
+
+/// Resolve the values a pipeline is queued with against the runtime
+/// parameters it declares, returning the value of every declared parameter,
+/// or a report of every value that's missing, unknown or not allowed
+pub fn resolve(
+    declared: &[PipelineParameter],
+    supplied: &IndexMap<String, Value>,
+) -> Result<IndexMap<String, Value>, Report> {
+    let parameters = AstPath::default().field("parameters");
+    let mut report = Report::default();
+
+    for name in supplied.keys() {
+        if declared.iter().all(|parameter| parameter.name != *name) {
+            report.diagnostics.push(
+                Diagnostic::error(
+                    codes::RUNTIME_PARAMETER,
+                    format!("the pipeline has no parameter `{name}`"),
+                )
+                .at(parameters.field(name)),
+            );
+        }
+    }
+
+    let mut resolved = IndexMap::new();
+    for (index, parameter) in declared.iter().enumerate() {
+        let name = &parameter.name;
+        let (value, message, path) = match (supplied.get(name), &parameter.default) {
+            (Some(value), _) => (
+                read(value, &parameter.parameter_type),
+                format!("parameter `{name}`"),
+                parameters.field(name),
+            ),
+            (None, Some(default)) => (
+                Ok(default.clone()),
+                format!("the default of parameter `{name}`"),
+                parameters
+                    .join(PathSegment::Item {
+                        index,
+                        name: Some(name.clone()),
+                    })
+                    .field("default"),
+            ),
+            (None, None) => {
+                report.diagnostics.push(
+                    Diagnostic::error(
+                        codes::RUNTIME_PARAMETER,
+                        format!("a value is required for parameter `{name}`"),
+                    )
+                    .at(parameters.field(name)),
+                );
+                continue;
+            }
+        };
+
+        match value.and_then(|value| convert(parameter, &value)) {
+            Ok(value) => {
+                resolved.insert(name.clone(), value);
+            }
+            Err(e) => report.diagnostics.push(
+                Diagnostic::error(codes::RUNTIME_PARAMETER, format!("{message}: {e}")).at(path),
+            ),
+        }
+    }
+
+    report.into_result().map(|_| resolved)
+}
+
+/// Read a `name=value` assignment, such as `--param os=linux` on a command
+/// line. The value is a string until [`resolve`] converts it.
+pub fn assignment(argument: &str) -> Result<(String, Value), String> {
+    let (name, value) = argument
+        .split_once('=')
+        .ok_or_else(|| format!("expected `name=value`, found `{argument}`"))?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(format!("expected `name=value`, found `{argument}`"));
+    }
+    Ok((name.to_string(), Value::String(value.to_string())))
+}
+
+/// Read a string given for a structured parameter type, such as one from the
+/// command line, as YAML. [`convert`] does the rest.
+fn read(value: &Value, parameter_type: &str) -> Result<Value, String> {
+    match (parameter_type, value) {
+        ("string" | "filePath" | "number" | "boolean", _) => Ok(value.clone()),
+        (_, Value::String(s)) => serde_yaml::from_str(s).map_err(|e| format!("invalid YAML: {e}")),
+        _ => Ok(value.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn declared() -> Vec<PipelineParameter> {
+        serde_yaml::from_str(
+            "\
+- name: os
+  type: string
+  default: linux
+  values: [linux, windows]
+- name: retries
+  type: number
+  default: '2'
+- name: fast
+  type: boolean
+- name: extraSteps
+  type: stepList
+  default: []
+",
+        )
+        .unwrap()
+    }
+
+    fn supplied(assignments: &[&str]) -> IndexMap<String, Value> {
+        assignments
+            .iter()
+            .map(|argument| assignment(argument).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn converts_values_and_defaults() {
+        let resolved = resolve(
+            &declared(),
+            &supplied(&["fast=TRUE", "extraSteps=[{task: Bash@3}]"]),
+        )
+        .unwrap();
+        assert_eq!(resolved["os"], "linux");
+        assert_eq!(resolved["retries"], 2);
+        assert_eq!(resolved["fast"], true);
+        assert_eq!(resolved["extraSteps"][0]["task"], "Bash@3");
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let report = resolve(
+            &declared(),
+            &supplied(&["os=macos", "retries=many", "verbose=true"]),
+        )
+        .unwrap_err();
+        let found: Vec<(String, String)> = report
+            .diagnostics
+            .iter()
+            .map(|d| (d.path.to_string(), d.message.clone()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "parameters > verbose".to_string(),
+                    "the pipeline has no parameter `verbose`".to_string()
+                ),
+                (
+                    "parameters > os".to_string(),
+                    "parameter `os`: `macos` isn't one of `linux`, `windows`".to_string()
+                ),
+                (
+                    "parameters > retries".to_string(),
+                    "parameter `retries`: `many` isn't a number".to_string()
+                ),
+                (
+                    "parameters > fast".to_string(),
+                    "a value is required for parameter `fast`".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn defaults_are_checked() {
+        let declared: Vec<PipelineParameter> = serde_yaml::from_str(
+            "\
+- name: os
+  type: string
+  default: macos
+  values: [linux, windows]
+- name: steps
+  type: stepList
+  default: {task: Bash@3}
+",
+        )
+        .unwrap();
+        let report = resolve(&declared, &IndexMap::new()).unwrap_err();
+        let found: Vec<(String, String)> = report
+            .diagnostics
+            .iter()
+            .map(|d| (d.path.to_string(), d.message.clone()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "parameters > \"os\" > default".to_string(),
+                    "the default of parameter `os`: `macos` isn't one of `linux`, `windows`"
+                        .to_string()
+                ),
+                (
+                    "parameters > \"steps\" > default".to_string(),
+                    "the default of parameter `steps`: expected a sequence for `stepList`, \
+                     found a mapping"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn assignments() {
+        assert_eq!(
+            assignment(" os =linux=x").unwrap(),
+            ("os".to_string(), Value::String("linux=x".to_string()))
+        );
+        assert!(assignment("os").is_err());
+        assert!(assignment("=linux").is_err());
+    }
+}
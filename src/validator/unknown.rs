@@ -0,0 +1,363 @@
+//! Keys that aren't part of this crate's schema
+//!
+//! Nodes reject keys they don't know, unless parsed with
+//! [`parse::from_str_lenient`](crate::source::parse::from_str_lenient), which
+//! keeps them in each node's `unknown` map so pipelines that use keywords newer
+//! than this crate still parse, validate and serialize back out without losing
+//! anything. [`UnknownFields`] finds the kept keys so
+//! [`unknown_field_diagnostics`] can report them as warnings.
+
+use indexmap::IndexMap;
+use serde_yaml::{Mapping, Value};
+
+use crate::{
+    core::v1::{
+        extends::Extends,
+        job::Job,
+        pipeline::{Pipeline, PipelineParameter, PipelineResources, PipelineVariable},
+        stage::Stage,
+        step::Step,
+        trigger::{PipelineResourceTrigger, ResourceTrigger, Trigger, TriggerItem},
+    },
+    templates::template::Template,
+    validator::diagnostic::{AstPath, Diagnostic, PathSegment, codes},
+};
+
+/// A node whose unknown keys, and those of every node inside it, can be listed
+pub trait UnknownFields {
+    /// Call `report` with each `unknown` map and the path of the node it
+    /// belongs to, where this node is at `path`
+    fn unknown_fields(&self, path: &AstPath, report: &mut dyn FnMut(&Mapping, &AstPath));
+}
+
+/// Warn about every key this crate doesn't know in `node` and the nodes
+/// inside it, such as the stages of a pipeline, or a whole
+/// [`Pipeline`] or [`Template`]. Paths start at `node`.
+pub fn unknown_field_diagnostics<T: UnknownFields + ?Sized>(node: &T) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    node.unknown_fields(&AstPath::default(), &mut |unknown, path| {
+        diagnostics.extend(unknown.keys().map(|key| {
+            let key = key.as_str().map(str::to_string).unwrap_or_else(|| {
+                serde_yaml::to_string(key)
+                    .unwrap_or_default()
+                    .trim_end()
+                    .to_string()
+            });
+            Diagnostic::warning(codes::UNKNOWN_FIELD, format!("unknown field `{key}`"))
+                .at(path.field(&key))
+        }));
+    });
+    diagnostics
+}
+
+/// Template parameters of unknown shape have no unknown keys
+impl UnknownFields for IndexMap<String, Value> {
+    fn unknown_fields(&self, _: &AstPath, _: &mut dyn FnMut(&Mapping, &AstPath)) {}
+}
+
+impl UnknownFields for Mapping {
+    fn unknown_fields(&self, _: &AstPath, _: &mut dyn FnMut(&Mapping, &AstPath)) {}
+}
+
+impl UnknownFields for Value {
+    fn unknown_fields(&self, _: &AstPath, _: &mut dyn FnMut(&Mapping, &AstPath)) {}
+}
+
+impl<T> UnknownFields for Vec<T>
+where
+    [T]: UnknownFields,
+{
+    fn unknown_fields(&self, path: &AstPath, report: &mut dyn FnMut(&Mapping, &AstPath)) {
+        self.as_slice().unknown_fields(path, report);
+    }
+}
+
+impl<P: UnknownFields> UnknownFields for Pipeline<P> {
+    fn unknown_fields(&self, path: &AstPath, report: &mut dyn FnMut(&Mapping, &AstPath)) {
+        report(&self.unknown, path);
+        self.extends.unknown_fields(&path.field("extends"), report);
+        parameters(&self.parameters, &path.field("parameters"), report);
+        if let Some(resources) = &self.resources {
+            resources.unknown_fields(&path.field("resources"), report);
+        }
+        if let Some(Trigger::Trigger(trigger)) = &self.trigger {
+            let path = path.field("trigger");
+            report(&trigger.unknown, &path);
+            trigger_item(trigger.branches.as_ref(), &path.field("branches"), report);
+            trigger_item(trigger.paths.as_ref(), &path.field("paths"), report);
+        }
+        self.variables
+            .unknown_fields(&path.field("variables"), report);
+    }
+}
+
+impl<P: UnknownFields> UnknownFields for Extends<P> {
+    fn unknown_fields(&self, path: &AstPath, report: &mut dyn FnMut(&Mapping, &AstPath)) {
+        report(&self.unknown, path);
+        self.parameters
+            .unknown_fields(&path.field("parameters"), report);
+    }
+}
+
+impl UnknownFields for PipelineResources {
+    fn unknown_fields(&self, path: &AstPath, report: &mut dyn FnMut(&Mapping, &AstPath)) {
+        report(&self.unknown, path);
+        for (index, container) in self.containers.iter().enumerate() {
+            let name = Some(container.name.clone());
+            report(&container.unknown, &item(path, "containers", index, name));
+        }
+        for (index, pipeline) in self.pipelines.iter().enumerate() {
+            let name = Some(pipeline.pipeline.clone());
+            let path = item(path, "pipelines", index, name);
+            report(&pipeline.unknown, &path);
+            if let Some(ResourceTrigger::Full(trigger)) = &pipeline.trigger {
+                resource_trigger(trigger, &path.field("trigger"), report);
+            }
+        }
+        for (index, repository) in self.repositories.iter().enumerate() {
+            let name = Some(repository.alias.clone());
+            report(
+                &repository.unknown,
+                &item(path, "repositories", index, name),
+            );
+        }
+    }
+}
+
+impl UnknownFields for [PipelineVariable] {
+    fn unknown_fields(&self, path: &AstPath, report: &mut dyn FnMut(&Mapping, &AstPath)) {
+        for (index, variable) in self.iter().enumerate() {
+            let (name, unknown) = match variable {
+                PipelineVariable::Group(group) => (&group.group, &group.unknown),
+                PipelineVariable::Variable(variable) => (&variable.name, &variable.unknown),
+            };
+            let path = path.join(PathSegment::Item {
+                index,
+                name: Some(name.clone()),
+            });
+            report(unknown, &path);
+        }
+    }
+}
+
+impl UnknownFields for Template {
+    fn unknown_fields(&self, path: &AstPath, report: &mut dyn FnMut(&Mapping, &AstPath)) {
+        report(&self.unknown, path);
+        parameters(&self.parameters, &path.field("parameters"), report);
+        self.stages.unknown_fields(&path.field("stages"), report);
+        // job and step paths already name their `jobs` and `steps` lists
+        self.jobs.unknown_fields(path, report);
+        self.steps.unknown_fields(path, report);
+        self.variables
+            .unknown_fields(&path.field("variables"), report);
+    }
+}
+
+impl UnknownFields for [Stage] {
+    fn unknown_fields(&self, path: &AstPath, report: &mut dyn FnMut(&Mapping, &AstPath)) {
+        for (index, stage) in self.iter().enumerate() {
+            let (name, unknown, jobs) = match stage {
+                Stage::Stage(stage) => (stage.name.clone(), &stage.unknown, &stage.jobs[..]),
+                Stage::Template(template) => (None, &template.unknown, &[][..]),
+            };
+            let path = path.join(PathSegment::Stage { index, name });
+            report(unknown, &path);
+            jobs.unknown_fields(&path, report);
+        }
+    }
+}
+
+impl UnknownFields for [Job] {
+    fn unknown_fields(&self, path: &AstPath, report: &mut dyn FnMut(&Mapping, &AstPath)) {
+        for (index, job) in self.iter().enumerate() {
+            let (name, unknown, steps) = match job {
+                Job::Job(job) => (job.name.clone(), &job.unknown, &job.steps[..]),
+                Job::Template(template) => (None, &template.unknown, &[][..]),
+            };
+            let path = path.join(PathSegment::Job { index, name });
+            report(unknown, &path);
+            steps.unknown_fields(&path, report);
+        }
+    }
+}
+
+impl UnknownFields for [Step] {
+    fn unknown_fields(&self, path: &AstPath, report: &mut dyn FnMut(&Mapping, &AstPath)) {
+        for (index, step) in self.iter().enumerate() {
+            let name = match step {
+                Step::Step(step) => step.name.clone(),
+                _ => None,
+            };
+            let path = path.join(PathSegment::Step { index, name });
+            match step {
+                Step::Checkout(checkout) => report(&checkout.unknown, &path),
+                Step::Step(task) => {
+                    report(&task.unknown, &path);
+                    if let Some(target) = &task.target {
+                        report(&target.unknown, &path.field("target"));
+                    }
+                }
+                Step::Template(template) => report(&template.unknown, &path),
+            }
+        }
+    }
+}
+
+/// The path of an item of the list at `field`
+fn item(path: &AstPath, field: &str, index: usize, name: Option<String>) -> AstPath {
+    path.field(field).join(PathSegment::Item { index, name })
+}
+
+fn parameters(
+    parameters: &[PipelineParameter],
+    path: &AstPath,
+    report: &mut dyn FnMut(&Mapping, &AstPath),
+) {
+    for (index, parameter) in parameters.iter().enumerate() {
+        let path = path.join(PathSegment::Item {
+            index,
+            name: Some(parameter.name.clone()),
+        });
+        report(&parameter.unknown, &path);
+    }
+}
+
+fn resource_trigger(
+    trigger: &PipelineResourceTrigger,
+    path: &AstPath,
+    report: &mut dyn FnMut(&Mapping, &AstPath),
+) {
+    report(&trigger.unknown, path);
+    trigger_item(trigger.branches.as_ref(), &path.field("branches"), report);
+    trigger_item(trigger.tags.as_ref(), &path.field("tags"), report);
+}
+
+fn trigger_item(
+    item: Option<&TriggerItem>,
+    path: &AstPath,
+    report: &mut dyn FnMut(&Mapping, &AstPath),
+) {
+    if let Some(item) = item {
+        report(&item.unknown, path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::source::parse;
+
+    use super::*;
+
+    fn paths<T: UnknownFields + ?Sized>(node: &T) -> Vec<String> {
+        unknown_field_diagnostics(node)
+            .iter()
+            .map(|d| d.path.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn whole_pipeline() {
+        let source = "\
+lockBehavior: sequential
+trigger:
+  batch: true
+  branches:
+    include: [main]
+    tags: [v1]
+resources:
+  containers:
+  - container: linux
+    image: ubuntu
+    options: --privileged
+  pipelines:
+  - pipeline: upstream
+    source: build
+    trigger:
+      stages: [a]
+parameters:
+- name: fast
+  type: boolean
+  displayname: Fast
+variables:
+- name: answer
+  value: '42'
+  readonly: true
+- group: secrets
+  extra: 1
+extends:
+  template: entrypoint.yml
+  extra: 1
+  parameters:
+    anything: goes
+";
+        let parsed = parse::from_str_lenient::<Pipeline>(source).unwrap();
+        assert_eq!(
+            paths(&parsed.value),
+            [
+                "lockBehavior",
+                "extends > extra",
+                "parameters > \"fast\" > displayname",
+                "resources > containers > \"linux\" > options",
+                "resources > pipelines > \"upstream\" > trigger > stages",
+                "trigger > batch",
+                "trigger > branches > tags",
+                "variables > \"answer\" > readonly",
+                "variables > \"secrets\" > extra",
+            ]
+        );
+        let lock_behavior = &unknown_field_diagnostics(&parsed.value)[0];
+        let span = parsed.source.span(&[], &lock_behavior.path).unwrap();
+        assert_eq!((span.start.line, span.start.column), (1, 15));
+    }
+
+    #[test]
+    fn stages_jobs_and_steps() {
+        let source = "\
+- stage: build
+  lockBehavior: runLatest
+  jobs:
+  - job: compile
+    workspace: {clean: all}
+    steps:
+    - task: Build@1
+      target:
+        container: linux
+        commands: restricted
+    - checkout: self
+      lfs: true
+  - template: job.yml
+    extra: 1
+- template: stage.yml
+  extra: 1
+";
+        let stages = parse::from_str_lenient::<Vec<Stage>>(source).unwrap().value;
+        assert_eq!(
+            paths(&stages),
+            [
+                "stage \"build\" > lockBehavior",
+                "stage \"build\" > job \"compile\" > workspace",
+                "stage \"build\" > job \"compile\" > step #0 > target > commands",
+                "stage \"build\" > job \"compile\" > step #1 > lfs",
+                "stage \"build\" > job #1 > extra",
+                "stage #1 > extra",
+            ]
+        );
+        assert!(
+            unknown_field_diagnostics(&stages)
+                .iter()
+                .all(|d| d.code == codes::UNKNOWN_FIELD)
+        );
+    }
+
+    #[test]
+    fn template() {
+        let template = Template::expand_source("jobs:\n- job: a\n  extra: 1\n", &IndexMap::new());
+        assert_eq!(
+            template.unwrap_err().to_string(),
+            "job: unknown field `extra`"
+        );
+
+        let template = Template::expand_source("steps:\n- script: echo\n", &IndexMap::new());
+        assert!(template.is_err());
+    }
+}
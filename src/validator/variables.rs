@@ -0,0 +1,673 @@
+//! Check the variables a pipeline declares against the references to them
+//!
+//! Variables are declared at the pipeline, stage and job level, and referred
+//! to with macro syntax (`$(name)`) or in expressions (`variables.name` or
+//! `variables['name']`). A reference must be to a variable declared at its
+//! level or above, or to a predefined variable.
+//!
+//! Some variables can't be seen in the pipeline file: the contents of variable
+//! groups, variables set by scripts, and output variables of other steps
+//! (`$(step.name)`). References that could be to one of those aren't reported,
+//! and neither are variables that a template call could be using.
+//!
+//! <https://learn.microsoft.com/en-us/azure/devops/pipelines/process/variables?view=azure-pipelines>
+
+use indexmap::IndexMap;
+use serde_yaml::Value;
+
+use crate::{
+    core::v1::{
+        job::Job,
+        pipeline::{Pipeline, PipelineVariable},
+        predefined::is_predefined,
+        stage::Stage,
+        step::{Step, TaskStep},
+    },
+    expressions::{
+        ast::{Expr, ExprKind, Literal},
+        parser,
+    },
+    templates::expand::expressions,
+    validator::diagnostic::{AstPath, Diagnostic, PathSegment, codes},
+};
+
+/// Report references to variables that aren't declared, declared variables
+/// that nothing refers to, and job or stage variables that hide a variable of
+/// the same name declared above them, in a pipeline declaring `variables`
+/// whose stages are `stages`. Paths start at the stages like those of
+/// [`validate_stages`](super::report::validate_stages), except for the
+/// pipeline's own variables, which are at `variables`.
+pub fn variable_diagnostics(variables: &[PipelineVariable], stages: &[Stage]) -> Vec<Diagnostic> {
+    check(variables, &[], stages)
+}
+
+/// [`variable_diagnostics`] for `pipeline`, whose stages are `stages`, also
+/// finding references in its run `name` and `pool`
+pub fn pipeline_variable_diagnostics<P>(
+    pipeline: &Pipeline<P>,
+    stages: &[Stage],
+) -> Vec<Diagnostic> {
+    let fields = [("name", &pipeline.name), ("pool", &pipeline.pool)];
+    let texts: Vec<(&str, AstPath)> = fields
+        .into_iter()
+        .filter_map(|(field, text)| Some((text.as_deref()?, AstPath::default().field(field))))
+        .collect();
+    check(&pipeline.variables, &texts, stages)
+}
+
+/// Check a pipeline declaring `variables`, with pipeline-level `texts` at
+/// their paths and stages `stages`
+fn check(
+    variables: &[PipelineVariable],
+    texts: &[(&str, AstPath)],
+    stages: &[Stage],
+) -> Vec<Diagnostic> {
+    let mut checker = Checker::default();
+    visit(variables, texts, stages, &mut checker);
+    checker.diagnostics
+}
+
+/// Where a reference is read, which decides what's set when it's read
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub(crate) enum Context {
+    /// Pipeline-level text, and the settings of a stage or job such as its
+    /// `displayName`, `pool` or template parameters
+    Settings,
+    /// A stage's condition, evaluated before any of its jobs has an agent
+    StageCondition,
+    /// A job's condition, evaluated before the job has an agent
+    JobCondition,
+    /// A job's variables and steps, which run on an agent unless the job is
+    /// agentless
+    Job { agentless: bool },
+}
+
+/// The variables declared at one level, which are visible to everything below
+/// it until the level is left
+pub(crate) struct Declared<'a> {
+    /// `pipeline`, `stage` or `job`, for messages
+    pub(crate) level: &'static str,
+    /// Where the variables are declared
+    pub(crate) path: AstPath,
+    pub(crate) names: Vec<&'a str>,
+    /// Whether variable groups are included, which may declare any variable
+    pub(crate) groups: bool,
+    /// Whether a template below could refer to these variables
+    pub(crate) templates: bool,
+}
+
+/// Receives every variable reference in a pipeline from [`visit`]
+pub(crate) trait Visitor<'a> {
+    /// The pipeline, a stage or a job is entered, declaring `declared`
+    fn enter(&mut self, _declared: Declared<'a>) {}
+
+    /// The level entered last is left
+    fn exit(&mut self) {}
+
+    /// `name` is read with `syntax` at `path`
+    fn reference(&mut self, name: &str, syntax: Syntax, path: &AstPath, context: Context);
+}
+
+/// Walk a pipeline declaring `variables`, with pipeline-level `texts` at their
+/// paths and stages `stages`, telling `visitor` about every variable reference
+/// in every string of it and the levels they're in. Paths are those of
+/// [`variable_diagnostics`].
+pub(crate) fn visit<'a>(
+    variables: &'a [PipelineVariable],
+    texts: &[(&str, AstPath)],
+    stages: &'a [Stage],
+    visitor: &mut dyn Visitor<'a>,
+) {
+    let mut walk = Walk { visitor };
+
+    let path = AstPath::default().field("variables");
+    walk.visitor.enter(Declared {
+        level: "pipeline",
+        path: path.clone(),
+        names: variables
+            .iter()
+            .filter_map(|variable| match variable {
+                PipelineVariable::Variable(variable) => Some(variable.name.as_str()),
+                PipelineVariable::Group(_) => None,
+            })
+            .collect(),
+        groups: variables
+            .iter()
+            .any(|variable| matches!(variable, PipelineVariable::Group(_))),
+        templates: stages.iter().any(|stage| match stage {
+            Stage::Stage(stage) => stage.jobs.iter().any(calls_template),
+            Stage::Template(_) => true,
+        }),
+    });
+    for variable in variables {
+        if let PipelineVariable::Variable(variable) = variable {
+            walk.text(
+                &variable.value,
+                &path.field(&variable.name),
+                Context::Settings,
+            );
+        }
+    }
+    for (text, path) in texts {
+        walk.text(text, path, Context::Settings);
+    }
+
+    for (index, stage) in stages.iter().enumerate() {
+        let stage = match stage {
+            Stage::Stage(stage) => stage,
+            Stage::Template(template) => {
+                let path = AstPath::default().join(PathSegment::Stage { index, name: None });
+                walk.parameters(&template.parameters, &path);
+                continue;
+            }
+        };
+        let stage_path = AstPath::default().join(PathSegment::Stage {
+            index,
+            name: stage.name.clone(),
+        });
+        walk.variables(
+            "stage",
+            &stage.variables,
+            &stage_path,
+            stage.jobs.iter().any(calls_template),
+            Context::Settings,
+        );
+        walk.condition(
+            stage.condition.as_deref(),
+            &stage_path,
+            Context::StageCondition,
+        );
+        walk.optional_text(
+            stage.display_name.as_deref(),
+            &stage_path.field("displayName"),
+        );
+        walk.optional_text(stage.pool.as_deref(), &stage_path.field("pool"));
+
+        for (index, job) in stage.jobs.iter().enumerate() {
+            let templates = calls_template(job);
+            let job = match job {
+                Job::Job(job) => job,
+                Job::Template(template) => {
+                    let path = stage_path.join(PathSegment::Job { index, name: None });
+                    walk.parameters(&template.parameters, &path);
+                    continue;
+                }
+            };
+            let job_path = stage_path.join(PathSegment::Job {
+                index,
+                name: job.name.clone(),
+            });
+            let context = Context::Job {
+                agentless: job
+                    .pool
+                    .as_deref()
+                    .is_some_and(|pool| pool.eq_ignore_ascii_case("server")),
+            };
+            walk.variables("job", &job.variables, &job_path, templates, context);
+            walk.condition(job.condition.as_deref(), &job_path, Context::JobCondition);
+            walk.optional_text(job.display_name.as_deref(), &job_path.field("displayName"));
+            walk.optional_text(job.pool.as_deref(), &job_path.field("pool"));
+            if let Some(timeout) = &job.timeout_in_minutes {
+                walk.value(
+                    timeout,
+                    &job_path.field("timeoutInMinutes"),
+                    Context::Settings,
+                );
+            }
+
+            for (index, step) in job.steps.iter().enumerate() {
+                match step {
+                    Step::Checkout(_) => {}
+                    Step::Step(task) => {
+                        let path = job_path.join(PathSegment::Step {
+                            index,
+                            name: task.name.clone(),
+                        });
+                        walk.task(task, &path, context);
+                    }
+                    Step::Template(template) => {
+                        let path = job_path.join(PathSegment::Step { index, name: None });
+                        walk.parameters(&template.parameters, &path);
+                    }
+                }
+            }
+            walk.visitor.exit();
+        }
+        walk.visitor.exit();
+    }
+    walk.visitor.exit();
+}
+
+/// Finds the references in each part of a pipeline for [`visit`]
+struct Walk<'v, 'a> {
+    visitor: &'v mut dyn Visitor<'a>,
+}
+
+impl<'a> Walk<'_, 'a> {
+    /// Enter a stage or job declaring `variables` as a mapping, and find the
+    /// references in their values
+    fn variables(
+        &mut self,
+        level: &'static str,
+        variables: &'a IndexMap<String, Value>,
+        path: &AstPath,
+        templates: bool,
+        context: Context,
+    ) {
+        let path = path.field("variables");
+        self.visitor.enter(Declared {
+            level,
+            path: path.clone(),
+            names: variables.keys().map(String::as_str).collect(),
+            groups: false,
+            templates,
+        });
+        for (name, value) in variables {
+            self.value(value, &path.field(name), context);
+        }
+    }
+
+    fn task(&mut self, task: &TaskStep, path: &AstPath, context: Context) {
+        if let Some(condition) = &task.condition {
+            self.expression(condition, &path.field("condition"), context);
+        }
+        if let Some(display_name) = &task.display_name {
+            self.text(display_name, &path.field("displayName"), context);
+        }
+        if let Some(retries) = &task.retry_count_on_task_failure {
+            self.value(retries, &path.field("retryCountOnTaskFailure"), context);
+        }
+        if let Some(target) = &task.target {
+            let path = path.field("target").field("container");
+            self.text(&target.container, &path, context);
+        }
+        for (field, values) in [("env", &task.env), ("inputs", &task.inputs)] {
+            for (name, value) in values {
+                self.text(value, &path.field(field).field(name), context);
+            }
+        }
+    }
+
+    /// Find the references in template parameters, which are passed when
+    /// templates are expanded
+    fn parameters(&mut self, parameters: &IndexMap<String, Value>, path: &AstPath) {
+        for (name, value) in parameters {
+            let path = path.field("parameters").field(name);
+            self.value(value, &path, Context::Settings);
+        }
+    }
+
+    fn condition(&mut self, condition: Option<&str>, path: &AstPath, context: Context) {
+        if let Some(condition) = condition {
+            self.expression(condition, &path.field("condition"), context);
+        }
+    }
+
+    fn optional_text(&mut self, text: Option<&str>, path: &AstPath) {
+        if let Some(text) = text {
+            self.text(text, path, Context::Settings);
+        }
+    }
+
+    /// Find the references in every string in a value
+    fn value(&mut self, value: &Value, path: &AstPath, context: Context) {
+        match value {
+            Value::String(s) => self.text(s, path, context),
+            Value::Sequence(items) => {
+                for item in items {
+                    self.value(item, path, context);
+                }
+            }
+            Value::Mapping(entries) => {
+                for (key, value) in entries {
+                    self.value(key, path, context);
+                    self.value(value, path, context);
+                }
+            }
+            Value::Tagged(tagged) => self.value(&tagged.value, path, context),
+            Value::Null | Value::Bool(_) | Value::Number(_) => {}
+        }
+    }
+
+    /// Find the references in macros and expressions in a string
+    fn text(&mut self, text: &str, path: &AstPath, context: Context) {
+        for (name, syntax) in text_references(text) {
+            self.visitor.reference(&name, syntax, path, context);
+        }
+    }
+
+    /// Find the references in a condition, which is a runtime expression
+    /// without `$[ ]`
+    fn expression(&mut self, expression: &str, path: &AstPath, context: Context) {
+        for name in expression_references(expression) {
+            self.visitor
+                .reference(&name, Syntax::Runtime, path, context);
+        }
+    }
+}
+
+/// The variables declared at one level, and which of them are used
+struct Scope<'a> {
+    declared: Declared<'a>,
+    /// Whether each of the declared names has been referred to
+    used: Vec<bool>,
+}
+
+#[derive(Default)]
+struct Checker<'a> {
+    scopes: Vec<Scope<'a>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Visitor<'a> for Checker<'a> {
+    fn enter(&mut self, declared: Declared<'a>) {
+        for name in &declared.names {
+            let outer = self.scopes.iter().rev().find(|outer| {
+                outer
+                    .declared
+                    .names
+                    .iter()
+                    .any(|outer| outer.eq_ignore_ascii_case(name))
+            });
+            if let Some(outer) = outer {
+                self.diagnostics.push(
+                    Diagnostic::warning(
+                        codes::VARIABLE_SHADOWED,
+                        format!(
+                            "`{name}` hides the {} variable of the same name",
+                            outer.declared.level
+                        ),
+                    )
+                    .at(declared.path.field(name)),
+                );
+            }
+        }
+        self.scopes.push(Scope {
+            used: vec![false; declared.names.len()],
+            declared,
+        });
+    }
+
+    /// Warn about the variables of the innermost scope that nothing refers to
+    fn exit(&mut self) {
+        let Some(Scope { declared, used }) = self.scopes.pop() else {
+            return;
+        };
+        if declared.templates {
+            return;
+        }
+        for (name, used) in declared.names.iter().zip(&used) {
+            // predefined variables are set to configure the pipeline, e.g.
+            // `System.Debug`
+            if !used && !is_predefined(name) {
+                self.diagnostics.push(
+                    Diagnostic::warning(
+                        codes::VARIABLE_UNUSED,
+                        format!("{} variable `{name}` is never used", declared.level),
+                    )
+                    .at(declared.path.field(name)),
+                );
+            }
+        }
+    }
+
+    /// Record a reference to `name` from `path`
+    fn reference(&mut self, name: &str, _: Syntax, path: &AstPath, _: Context) {
+        if is_predefined(name) {
+            return;
+        }
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(index) = scope
+                .declared
+                .names
+                .iter()
+                .position(|declared| declared.eq_ignore_ascii_case(name))
+            {
+                scope.used[index] = true;
+                return;
+            }
+        }
+        let unknowable =
+            name.contains('.') || self.scopes.iter().any(|scope| scope.declared.groups);
+        if !unknowable {
+            self.diagnostics.push(
+                Diagnostic::warning(
+                    codes::VARIABLE_UNDEFINED,
+                    format!("variable `{name}` isn't declared"),
+                )
+                .at(path.clone()),
+            );
+        }
+    }
+}
+
+/// How a variable is referred to, which determines when it's read
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub(crate) enum Syntax {
+    /// `$(name)`, replaced just before a task runs
+    Macro,
+    /// `$[ variables.name ]`, evaluated when a job or stage starts
+    Runtime,
+    /// `${{ variables.name }}`, evaluated when templates are expanded
+    Template,
+}
+
+/// The variables referred to in macros (`$(name)`), runtime expressions
+/// (`$[ ]`) and template expressions (`${{ }}`) in a string
+pub(crate) fn text_references(text: &str) -> Vec<(String, Syntax)> {
+    let mut references = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("$(") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find(')') else {
+            break;
+        };
+        let name = &after[..end];
+        if !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+        {
+            references.push((name.to_string(), Syntax::Macro));
+        }
+        rest = &after[end + 1..];
+    }
+
+    if let Some(runtime) = text
+        .trim()
+        .strip_prefix("$[")
+        .and_then(|runtime| runtime.strip_suffix(']'))
+    {
+        references.extend(
+            expression_references(runtime)
+                .into_iter()
+                .map(|name| (name, Syntax::Runtime)),
+        );
+    }
+    for expression in expressions(text) {
+        references.extend(
+            expression_references(expression)
+                .into_iter()
+                .map(|name| (name, Syntax::Template)),
+        );
+    }
+    references
+}
+
+/// The variables referred to in an expression, as `variables.name` or
+/// `variables['name']`. A dotted name may be written as a chain of
+/// properties, e.g. `variables.Build.SourceBranch`.
+pub(crate) fn expression_references(expression: &str) -> Vec<String> {
+    // syntax errors are reported by the condition checks
+    let Ok(expr) = parser::parse(expression) else {
+        return Vec::new();
+    };
+    let mut names = Vec::new();
+    references(&expr, &mut names);
+    names
+}
+
+fn references(expr: &Expr, out: &mut Vec<String>) {
+    if let Some(name) = variable(expr) {
+        out.push(name);
+        return;
+    }
+    match &expr.kind {
+        ExprKind::Property { target, .. } => references(target, out),
+        ExprKind::Index { target, index } => {
+            references(target, out);
+            references(index, out);
+        }
+        ExprKind::Call { args, .. } => {
+            for arg in args {
+                references(arg, out);
+            }
+        }
+        ExprKind::Literal(_) | ExprKind::NamedValue(_) => {}
+    }
+}
+
+/// The name of the variable an expression reads, if it's a property or
+/// literal index of `variables`, or a chain of them
+fn variable(expr: &Expr) -> Option<String> {
+    let (target, name) = match &expr.kind {
+        ExprKind::Property { target, name } => (target, name),
+        ExprKind::Index { target, index } => match &index.kind {
+            ExprKind::Literal(Literal::String(name)) => (target, name),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    if matches!(&target.kind, ExprKind::NamedValue(root) if root.eq_ignore_ascii_case("variables"))
+    {
+        Some(name.clone())
+    } else {
+        variable(target).map(|prefix| format!("{prefix}.{name}"))
+    }
+}
+
+/// Whether a job is a template call or calls a step template, which could
+/// refer to any variable visible to it
+fn calls_template(job: &Job) -> bool {
+    match job {
+        Job::Job(job) => job
+            .steps
+            .iter()
+            .any(|step| matches!(step, Step::Template(_))),
+        Job::Template(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::v1::pipeline::ValueVariable;
+
+    use super::*;
+
+    fn diagnostics(variables: &[(&str, &str)], stages: &str) -> Vec<(String, String)> {
+        let variables: Vec<PipelineVariable> = variables
+            .iter()
+            .map(|(name, value)| {
+                PipelineVariable::Variable(ValueVariable {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                    ..ValueVariable::default()
+                })
+            })
+            .collect();
+        let stages: Vec<Stage> = serde_yaml::from_str(stages).unwrap();
+        variable_diagnostics(&variables, &stages)
+            .into_iter()
+            .map(|d| (d.path.to_string(), d.message))
+            .collect()
+    }
+
+    #[test]
+    fn dotted_names() {
+        // `variables.Build.SourceBranch` reads `Build.SourceBranch`, not a
+        // variable named `Build`
+        let stages = "\
+- stage: build
+  condition: and(eq(variables.Build.SourceBranch, 'refs/heads/main'), ne(variables.my.setting, ''))
+  jobs:
+  - job: compile
+    steps:
+    - task: Bash@3
+      displayName: $[ variables['Build.Reason'] ]
+";
+        assert_eq!(diagnostics(&[("my.setting", "x")], stages), []);
+        assert_eq!(
+            expression_references("eq(variables.Build.SourceBranch, variables['a'].b)"),
+            ["Build.SourceBranch", "a.b"]
+        );
+    }
+
+    #[test]
+    fn every_string_field() {
+        let stages = "\
+- stage: build
+  pool: $(stagePool)
+  variables:
+    stagePool: linux
+    poolName: linux
+    timeout: 10
+    retries: 2
+    container: rust
+  jobs:
+  - job: compile
+    pool: $(poolName)
+    timeoutInMinutes: $(timeout)
+    steps:
+    - task: Bash@3
+      retryCountOnTaskFailure: $(retries)
+      target:
+        container: $(container)
+";
+        assert_eq!(diagnostics(&[], stages), []);
+
+        let stages = "\
+- stage: build
+  pool: $(missing)
+  jobs: []
+";
+        assert_eq!(
+            diagnostics(&[], stages),
+            [(
+                "stage \"build\" > pool".to_string(),
+                "variable `missing` isn't declared".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn pipeline_name_and_pool() {
+        let pipeline: Pipeline = serde_yaml::from_str(
+            "\
+name: $(major).$(Rev:r)
+pool: $(agents)
+variables:
+- name: major
+  value: '1'
+- name: agents
+  value: linux
+- name: unused
+  value: x
+extends:
+  template: entrypoint.yml
+  parameters: {}
+",
+        )
+        .unwrap();
+        let found: Vec<(String, String)> = pipeline_variable_diagnostics(&pipeline, &[])
+            .into_iter()
+            .map(|d| (d.path.to_string(), d.message))
+            .collect();
+        assert_eq!(
+            found,
+            [(
+                "variables > unused".to_string(),
+                "pipeline variable `unused` is never used".to_string()
+            )]
+        );
+    }
+}